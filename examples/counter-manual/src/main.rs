@@ -79,8 +79,14 @@ async fn app(socket: WebSocket) {
     let rx_fut = {
         let browser = browser.clone();
         async move {
-            while let Some(Ok(Message::Text(msg))) = rx.next().await {
-                browser.receive_incoming_message(msg);
+            while let Some(Ok(msg)) = rx.next().await {
+                match msg {
+                    Message::Text(msg) => browser.receive_incoming_message(msg),
+                    // replies come as binary messages with `ReplyFormat::MessagePack`
+                    Message::Binary(msg) => browser.receive_incoming_binary_message(msg),
+                    Message::Close(_) => break,
+                    _ => {}
+                }
             }
         }
     };
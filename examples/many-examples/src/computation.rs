@@ -18,6 +18,8 @@ pub async fn app(browser: Browser) {
             .unchecked_into();
         let dfx = &(&fxdelta - &fx) / &DELTA;
         x = &x - &(&fx / &dfx);
+        // let the commands go out before queueing more
+        browser.ready().await;
     }
     wsdom::dom::console(&browser).log(&[&"The root of 5x^2 - 3x + 4.2 is:"]);
     wsdom::dom::console(&browser).log(&[AsRef::<JsValue>::as_ref(&x)]);
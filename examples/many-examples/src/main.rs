@@ -7,7 +7,7 @@ use std::future::Future;
 
 use axum::{extract::WebSocketUpgrade, response::Html, routing::get, Router};
 use wsdom::Browser;
-use wsdom_axum::socket_to_browser;
+use wsdom_axum::{socket_to_browser_resumable, Sessions};
 
 #[tokio::main]
 async fn main() {
@@ -30,13 +30,14 @@ where
     Fut: Future + Send + 'static,
     Fut::Output: Send + 'static,
 {
+    let sessions = Sessions::default();
     let router = Router::new()
         .route("/", get(make_html(&*format!("{name}/ws"))))
         .route(
             "/ws",
             get(|wsu: WebSocketUpgrade| async move {
                 wsu.on_upgrade(|ws| async move {
-                    socket_to_browser(ws, func).await;
+                    socket_to_browser_resumable(ws, &sessions, func).await;
                 })
             }),
         );
@@ -1,24 +1,52 @@
 //! Integration code for conveniently using WSDOM with the Axum web framework.
 //!
-//! This library provides only one function: [socket_to_browser].
+//! This library provides two functions: [socket_to_browser],
+//! and [socket_to_browser_resumable] for sessions that survive reconnections.
+//!
+//! Long messages are compressed (as zlib-wrapped deflate, in binary frames) for clients that announce
+//! the `"deflate"` feature; see [ToBrowserFuture::with_compression_threshold]
+//! and [Sessions::with_compression_threshold].
+//! Binary messages from the client (replies in [ReplyFormat::MessagePack](wsdom_core::ReplyFormat::MessagePack))
+//! are handled too.
 
 use std::{
+    collections::HashMap,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
 use futures_util::{Future, Sink, Stream, StreamExt};
 use pin_project_lite::pin_project;
-use wsdom_core::Browser;
+use tokio::sync::mpsc;
+use tokio::time::Sleep;
+use wsdom_core::{Browser, Error, Quotas};
 
 pin_project! {
     /// Future type returned from [socket_to_browser].
     pub struct ToBrowserFuture<Fut: Future> {
         #[pin] ws: WebSocket,
         #[pin] fut: Fut,
+        #[pin] hello_deadline: Sleep,
         browser: Browser,
-        output: Option<Fut::Output>
+        started: bool,
+        compress_above: Option<usize>,
+        output: Option<Fut::Output>,
+        closing: Option<(Option<Message>, Output<Fut::Output>)>
+    }
+}
+
+impl<Fut: Future> ToBrowserFuture<Fut> {
+    /// Compress messages at least this many bytes long, for clients that support it,
+    /// or never compress if `None`. The default is [DEFAULT_COMPRESSION_THRESHOLD].
+    ///
+    /// Messages are compressed as they are sent, in the task polling the connection,
+    /// so batches of more than [FAST_COMPRESSION_ABOVE] bytes are compressed at the fastest level.
+    pub fn with_compression_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.compress_above = threshold;
+        self
     }
 }
 
@@ -30,60 +58,161 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
-        match this.ws.as_mut().poll_next(cx) {
-            Poll::Ready(Some(Ok(Message::Text(message)))) => {
-                this.browser.receive_incoming_message(message);
-            }
-            Poll::Ready(None | Some(Ok(Message::Close(_)))) => {
-                return Poll::Ready(Output::ConnectionClosed);
-            }
-            Poll::Ready(Some(Ok(_))) => {}
-            Poll::Ready(Some(Err(e))) => {
-                return Poll::Ready(Output::AxumError(e));
-            }
-            Poll::Pending => {}
-        }
-        match this.ws.as_mut().poll_ready(cx) {
-            Poll::Ready(Ok(_)) => {
-                match this.browser.poll_next_unpin(cx) {
-                    Poll::Ready(Some(message)) => {
-                        match this.ws.as_mut().start_send(Message::Text(message)) {
-                            Ok(_) => {}
-                            Err(e) => return Poll::Ready(Output::AxumError(e)),
+        loop {
+            if let Some((frame, _)) = this.closing {
+                // tell the client why, before giving up on the connection
+                if let Some(message) = frame.take() {
+                    match this.ws.as_mut().poll_ready(cx) {
+                        Poll::Ready(Ok(())) => {
+                            let _ = this.ws.as_mut().start_send(message);
                         }
-                    }
-                    Poll::Ready(None) => {
-                        if let Some(err) = this.browser.take_error() {
-                            return Poll::Ready(Output::WsdomError(err));
-                        } else {
+                        Poll::Ready(Err(_)) => {}
+                        Poll::Pending => {
+                            *frame = Some(message);
                             return Poll::Pending;
                         }
                     }
-                    Poll::Pending => {}
                 }
-                match this.ws.as_mut().poll_flush(cx) {
-                    Poll::Ready(Err(e)) => {
-                        return Poll::Ready(Output::AxumError(e));
+                if this.ws.as_mut().poll_flush(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                return Poll::Ready(this.closing.take().unwrap().1);
+            }
+            if !*this.started && this.hello_deadline.as_mut().poll(cx).is_ready() {
+                this.browser.assume_legacy_client();
+            }
+            let result = match poll_connection(
+                this.ws.as_mut(),
+                this.fut.as_mut(),
+                this.browser,
+                this.started,
+                *this.compress_above,
+                this.output,
+                cx,
+            ) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            match &result {
+                Output::WsdomError(e) => match close_reason(e) {
+                    Some((code, reason)) => {
+                        *this.closing = Some((Some(close_frame(code, reason)), result))
                     }
-                    Poll::Ready(Ok(_)) => {
-                        if let Some(output) = this.output.take() {
-                            return Poll::Ready(Output::Done(output));
-                        }
+                    None => return Poll::Ready(result),
+                },
+                _ => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+/// Pass messages between the WebSocket and the Browser while running the app Future.
+///
+/// The app is not started until the client has introduced itself.
+fn poll_connection<Fut: Future>(
+    mut ws: Pin<&mut WebSocket>,
+    fut: Pin<&mut Fut>,
+    browser: &mut Browser,
+    started: &mut bool,
+    compress_above: Option<usize>,
+    output: &mut Option<Fut::Output>,
+    cx: &mut Context<'_>,
+) -> Poll<Output<Fut::Output>> {
+    match ws.as_mut().poll_next(cx) {
+        Poll::Ready(Some(Ok(Message::Text(message)))) => {
+            browser.receive_incoming_message(message);
+        }
+        Poll::Ready(Some(Ok(Message::Binary(message)))) => {
+            browser.receive_incoming_binary_message(message);
+        }
+        Poll::Ready(None | Some(Ok(Message::Close(_)))) => {
+            return Poll::Ready(Output::ConnectionClosed);
+        }
+        Poll::Ready(Some(Ok(_))) => {}
+        Poll::Ready(Some(Err(e))) => {
+            return Poll::Ready(Output::AxumError(e));
+        }
+        Poll::Pending => {}
+    }
+    match ws.as_mut().poll_ready(cx) {
+        Poll::Ready(Ok(_)) => {
+            match browser.poll_next_unpin(cx) {
+                Poll::Ready(Some(message)) => {
+                    let message = compress(browser, message, compress_above);
+                    match ws.as_mut().start_send(message) {
+                        Ok(_) => {}
+                        Err(e) => return Poll::Ready(Output::AxumError(e)),
+                    }
+                }
+                Poll::Ready(None) => {
+                    if let Some(err) = browser.take_error() {
+                        return Poll::Ready(Output::WsdomError(err));
+                    } else {
+                        return Poll::Pending;
                     }
-                    _ => {}
                 }
+                Poll::Pending => {}
             }
-            Poll::Ready(Err(e)) => {
-                return Poll::Ready(Output::AxumError(e));
+            match ws.as_mut().poll_flush(cx) {
+                Poll::Ready(Err(e)) => {
+                    return Poll::Ready(Output::AxumError(e));
+                }
+                Poll::Ready(Ok(_)) => {
+                    if let Some(output) = output.take() {
+                        return Poll::Ready(Output::Done(output));
+                    }
+                }
+                _ => {}
             }
-            Poll::Pending => {}
         }
-        if this.output.is_none() {
-            if let Poll::Ready(t) = this.fut.poll(cx) {
-                *this.output = Some(t);
-            }
+        Poll::Ready(Err(e)) => {
+            return Poll::Ready(Output::AxumError(e));
+        }
+        Poll::Pending => {}
+    }
+    if !*started && browser.client_info().is_some() {
+        *started = true;
+    }
+    if *started && output.is_none() {
+        if let Poll::Ready(t) = fut.poll(cx) {
+            *output = Some(t);
         }
-        Poll::Pending
+    }
+    Poll::Pending
+}
+
+/// Messages at least this many bytes long are compressed by default.
+///
+/// Shorter ones gain little, and compressing them costs time on both ends.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// zlib's default level, which command batches (repetitive JS) compress well at.
+const COMPRESSION_LEVEL: u8 = 6;
+/// Messages longer than this are compressed at the fastest level,
+/// so that a huge batch doesn't hold up the task polling the connection for long.
+pub const FAST_COMPRESSION_ABOVE: usize = 64 << 10;
+/// zlib's fastest level.
+const FAST_COMPRESSION_LEVEL: u8 = 1;
+
+/// Compress the message into a binary frame if it's long enough and the client can inflate it.
+fn compress(browser: &Browser, message: String, compress_above: Option<usize>) -> Message {
+    let long = compress_above.is_some_and(|threshold| message.len() >= threshold);
+    if !long
+        || !browser
+            .client_info()
+            .is_some_and(|info| info.supports("deflate"))
+    {
+        return Message::Text(message);
+    }
+    let level = match message.len() > FAST_COMPRESSION_ABOVE {
+        true => FAST_COMPRESSION_LEVEL,
+        false => COMPRESSION_LEVEL,
+    };
+    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(message.as_bytes(), level);
+    if compressed.len() < message.len() {
+        Message::Binary(compressed)
+    } else {
+        Message::Text(message)
     }
 }
 
@@ -96,7 +225,15 @@ pub enum Output<T> {
     /// Axum raised an error.
     AxumError(axum::Error),
     /// WSDOM raised an error.
+    ///
+    /// This is [Error::IncompatibleClient] if the client speaks a different protocol version,
+    /// and [Error::QuotaExceeded] if the session broke its [Quotas].
+    /// For these, the WebSocket is closed with a code telling the client why
+    /// (1008, the standard "policy violation", for quotas).
     WsdomError(wsdom_core::Error),
+    /// The client reconnected, and its WebSocket was handed over to its existing session.
+    /// See [socket_to_browser_resumable].
+    Resumed,
 }
 
 /// Get a [Browser] from an [axum::WebSocket] object.
@@ -106,6 +243,11 @@ pub enum Output<T> {
 /// pass it to `socket_to_browser` along with an async function/closure that takes a Browser as argument,
 /// then await the returned Future.
 ///
+/// Your function starts running once the client has introduced itself,
+/// so [Browser::client_info] is always available in it.
+/// Clients from before the introduction are given 10 seconds to say something,
+/// and then taken for what they are (see [Browser::assume_legacy_client]).
+///
 /// ```rust
 /// # use wsdom_core::Browser;
 /// use wsdom_axum::socket_to_browser;
@@ -129,8 +271,308 @@ where
     let browser = Browser::new();
     ToBrowserFuture {
         fut: f(browser.clone()),
+        hello_deadline: tokio::time::sleep(HELLO_TIMEOUT),
         ws,
         browser,
+        started: false,
+        compress_above: Some(DEFAULT_COMPRESSION_THRESHOLD),
         output: None,
+        closing: None,
+    }
+}
+
+/// Hands a reconnected client's WebSocket and hello message over to its session.
+type Reconnect = mpsc::UnboundedSender<(WebSocket, String)>;
+
+/// Sessions that clients can reconnect to. See [socket_to_browser_resumable].
+///
+/// This is cheap to clone; clones share the same sessions.
+#[derive(Clone)]
+pub struct Sessions {
+    waiting: Arc<Mutex<HashMap<String, Reconnect>>>,
+    grace_period: Duration,
+    replay_limit: usize,
+    quotas: Quotas,
+    compress_above: Option<usize>,
+}
+
+impl Sessions {
+    /// `grace_period` is how long a disconnected session waits for the client to reconnect.
+    /// `replay_limit` is how many bytes of unacknowledged messages are kept for the client to catch up on.
+    pub fn new(grace_period: Duration, replay_limit: usize) -> Self {
+        Self {
+            waiting: Default::default(),
+            grace_period,
+            replay_limit,
+            quotas: Quotas::default(),
+            compress_above: Some(DEFAULT_COMPRESSION_THRESHOLD),
+        }
+    }
+    /// Apply these [Quotas] to every new session, from the client's first message on.
+    pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+    /// Compress messages at least this many bytes long, for clients that support it,
+    /// or never compress if `None`. The default is [DEFAULT_COMPRESSION_THRESHOLD].
+    ///
+    /// Messages are compressed as they are sent, in the task polling the connection,
+    /// so batches of more than [FAST_COMPRESSION_ABOVE] bytes are compressed at the fastest level.
+    pub fn with_compression_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.compress_above = threshold;
+        self
+    }
+}
+
+impl Default for Sessions {
+    /// 30 seconds of grace period and 1 MiB of replay buffer.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30), 1 << 20)
+    }
+}
+
+/// Close code telling the client that its session can't be resumed.
+const SESSION_LOST: u16 = 4001;
+/// Close code telling the client that it speaks a different protocol version.
+const INCOMPATIBLE_CLIENT: u16 = 4002;
+/// Close code telling the client that it broke a quota (the standard "policy violation" code).
+const QUOTA_EXCEEDED: u16 = 1008;
+/// How long to wait for the client to introduce itself.
+/// Clients too old to do so are [taken for legacy clients](Browser::assume_legacy_client) after this.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Like [socket_to_browser], but the session survives the WebSocket disconnecting.
+///
+/// If the client reconnects within the grace period, it is given what it missed,
+/// and the app continues with the same [Browser].
+/// The app is paused while waiting for the client to reconnect.
+///
+/// When a client reconnects, the Future for its new WebSocket hands it over to the old session
+/// and completes with [Output::Resumed].
+///
+/// ```rust
+/// # use wsdom_core::Browser;
+/// use wsdom_axum::{socket_to_browser_resumable, Sessions};
+/// use axum::extract::{State, WebSocketUpgrade, ws::WebSocket};
+/// use axum::response::Response;
+/// // use with `Router::new().route("/ws", get(axum_handler)).with_state(Sessions::default())`
+/// async fn axum_handler(wsu: WebSocketUpgrade, State(sessions): State<Sessions>) -> Response {
+///     wsu.on_upgrade(|ws: WebSocket| async move {
+///         socket_to_browser_resumable(ws, &sessions, app).await;
+///     })
+/// }
+/// async fn app(browser: Browser) {
+///     // do things...
+/// }
+/// ````
+pub async fn socket_to_browser_resumable<Func, Fut>(
+    mut ws: WebSocket,
+    sessions: &Sessions,
+    f: Func,
+) -> Output<Fut::Output>
+where
+    Func: FnOnce(Browser) -> Fut,
+    Fut: Future,
+{
+    // the client introduces itself with `h:<token>:<count>`; the token is empty for new clients
+    let first = match tokio::time::timeout(HELLO_TIMEOUT, ws.next()).await {
+        Ok(Some(Ok(Message::Text(first)))) => Some(first),
+        Ok(Some(Err(e))) => return Output::AxumError(e),
+        Ok(_) => return Output::ConnectionClosed,
+        // a client from before hellos, which doesn't say anything until asked
+        Err(_) => None,
+    };
+    let hello = first.as_deref().and_then(|first| first.strip_prefix("h:"));
+    let token = hello
+        .and_then(|rest| rest.split(':').next())
+        .unwrap_or_default();
+    if !token.is_empty() {
+        let waiting = sessions.waiting.lock().unwrap().get(token).cloned();
+        let hello = first.clone().unwrap_or_default();
+        let ws = match waiting {
+            Some(session) => match session.send((ws, hello)) {
+                Ok(()) => return Output::Resumed,
+                Err(mpsc::error::SendError((ws, _))) => ws,
+            },
+            None => ws,
+        };
+        close(ws, SESSION_LOST, "session not found").await;
+        return Output::ConnectionClosed;
+    }
+
+    // clients from before hellos can't resume
+    let mut browser = match hello {
+        Some(_) => Browser::new_resumable(sessions.replay_limit),
+        None => Browser::new(),
+    };
+    browser.set_quotas(sessions.quotas.clone());
+    match first {
+        Some(first) => browser.receive_incoming_message(first),
+        None => browser.assume_legacy_client(),
+    }
+    let token = browser.session_token().unwrap_or_default();
+    let (sender, mut reconnected) = mpsc::unbounded_channel();
+    if !token.is_empty() {
+        sessions
+            .waiting
+            .lock()
+            .unwrap()
+            .insert(token.clone(), sender);
+    }
+
+    let mut fut = std::pin::pin!(f(browser.clone()));
+    let mut started = false;
+    let mut output = None;
+    let result = loop {
+        let event = std::future::poll_fn(|cx| {
+            // the client may reconnect before we notice that its old connection is gone
+            if let Poll::Ready(Some(reconnect)) = reconnected.poll_recv(cx) {
+                return Poll::Ready(Event::Reconnected(Box::new(reconnect)));
+            }
+            poll_connection(
+                Pin::new(&mut ws),
+                fut.as_mut(),
+                &mut browser,
+                &mut started,
+                sessions.compress_above,
+                &mut output,
+                cx,
+            )
+            .map(Event::Connection)
+        })
+        .await;
+        let result = match event {
+            Event::Reconnected(reconnect) => {
+                let (new_ws, hello) = *reconnect;
+                // dropping the old WebSocket closes it
+                ws = new_ws;
+                browser.receive_incoming_message(hello);
+                continue;
+            }
+            Event::Connection(result) => result,
+        };
+        match result {
+            Output::ConnectionClosed | Output::AxumError(_) => {
+                match tokio::time::timeout(sessions.grace_period, reconnected.recv()).await {
+                    Ok(Some((new_ws, hello))) => {
+                        ws = new_ws;
+                        browser.receive_incoming_message(hello);
+                    }
+                    _ => break result,
+                }
+            }
+            Output::WsdomError(e) => {
+                if let Some((code, reason)) = close_reason(&e) {
+                    close(ws, code, reason).await;
+                }
+                break Output::WsdomError(e);
+            }
+            result => break result,
+        }
+    };
+    sessions.waiting.lock().unwrap().remove(&token);
+    result
+}
+
+/// What a resumable session waits for.
+enum Event<T> {
+    Connection(Output<T>),
+    Reconnected(Box<(WebSocket, String)>),
+}
+
+/// The close code and reason that tell the client why the error ended its connection.
+fn close_reason(error: &Error) -> Option<(u16, &'static str)> {
+    match error {
+        Error::ResumeFailed => Some((SESSION_LOST, "session not found")),
+        Error::IncompatibleClient(_) => Some((INCOMPATIBLE_CLIENT, "incompatible client")),
+        Error::QuotaExceeded(_) => Some((QUOTA_EXCEEDED, "quota exceeded")),
+        _ => None,
+    }
+}
+
+fn close_frame(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+async fn close(mut ws: WebSocket, code: u16, reason: &'static str) {
+    let _ = ws.send(close_frame(code, reason)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::ws::Message;
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+    use wsdom_core::{Browser, PROTOCOL_VERSION};
+
+    use super::{compress, DEFAULT_COMPRESSION_THRESHOLD, FAST_COMPRESSION_ABOVE};
+
+    fn client(features: &str) -> Browser {
+        let browser = Browser::new();
+        browser.receive_incoming_message(format!(
+            "h::0:{{\"version\":{PROTOCOL_VERSION},\"features\":[{features}]}}"
+        ));
+        browser
+    }
+
+    /// Commands `len` bytes long.
+    fn batch(len: usize) -> String {
+        let command = "_w.s(1,document.createElement(\"div\"));\n";
+        let mut batch = command.repeat(len.div_ceil(command.len()));
+        batch.truncate(len);
+        batch
+    }
+
+    fn inflated(message: Message) -> String {
+        let Message::Binary(bytes) = message else {
+            panic!("not compressed: {message:?}");
+        };
+        String::from_utf8(decompress_to_vec_zlib(&bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn long_messages_round_trip() {
+        let browser = client(r#""deflate""#);
+        // at the default level, and at the fastest
+        for len in [DEFAULT_COMPRESSION_THRESHOLD, FAST_COMPRESSION_ABOVE + 1] {
+            let message = batch(len);
+            let compressed = compress(
+                &browser,
+                message.clone(),
+                Some(DEFAULT_COMPRESSION_THRESHOLD),
+            );
+            assert_eq!(inflated(compressed), message);
+        }
+    }
+
+    #[test]
+    fn short_messages_stay_text() {
+        let browser = client(r#""deflate""#);
+        let message = batch(DEFAULT_COMPRESSION_THRESHOLD - 1);
+        assert_eq!(
+            compress(
+                &browser,
+                message.clone(),
+                Some(DEFAULT_COMPRESSION_THRESHOLD)
+            ),
+            Message::Text(message)
+        );
+    }
+
+    #[test]
+    fn only_for_clients_that_inflate() {
+        let message = batch(1 << 16);
+        for (browser, threshold) in [
+            (client(r#""eval""#), Some(DEFAULT_COMPRESSION_THRESHOLD)),
+            (Browser::new(), Some(DEFAULT_COMPRESSION_THRESHOLD)),
+            (client(r#""deflate""#), None),
+        ] {
+            assert_eq!(
+                compress(&browser, message.clone(), threshold),
+                Message::Text(message.clone())
+            );
+        }
     }
 }
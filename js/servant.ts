@@ -1,43 +1,151 @@
 type Id = number;
 type Value = unknown;
-type SendMessage = (msg: string) => void;
+type SendMessage = (msg: string | Uint8Array) => void;
 
 function WSDOMConnectWebSocket(wsUrl: string | URL, wsProtocols?: string | string[]) {
-	const ws = new WebSocket(wsUrl, wsProtocols);
-	const wsdom = new WSDOM((msg: string) => {
-		ws.send(msg);
-	});
-	ws.onopen = () => {
-		console.debug("WSDOM WebSocket connection open!");
-		console.debug("WebSocket object", ws);
-		console.debug("WSDOM object", wsdom);
-	}
-	ws.onmessage = (msg: MessageEvent<string>) => {
-		wsdom.handleIncomingMessage(msg.data);
+	const wsdom = new WSDOM();
+	let retries = 0;
+	const connect = () => {
+		const ws = new WebSocket(wsUrl, wsProtocols);
+		// compressed messages are inflated asynchronously, so the messages after them wait their turn.
+		// Messages still waiting when the socket closes are dropped, not run during the next connection
+		// (a resumed session gets them again).
+		let inflating: Promise<void> | null = null;
+		let closed = false;
+		ws.onopen = () => {
+			retries = 0;
+			wsdom.connect((msg: string | Uint8Array) => {
+				ws.send(msg);
+			});
+			console.debug("WSDOM WebSocket connection open!");
+			console.debug("WebSocket object", ws);
+			console.debug("WSDOM object", wsdom);
+		}
+		ws.binaryType = "arraybuffer";
+		ws.onmessage = (msg: MessageEvent<string | ArrayBuffer>) => {
+			const data = msg.data;
+			if (typeof data === "string" && inflating === null) {
+				wsdom.handleIncomingMessage(data);
+				return;
+			}
+			const text = typeof data === "string" ? data : inflate(data);
+			const done: Promise<void> = (inflating ?? Promise.resolve())
+				.then(() => text)
+				.then((msg) => {
+					if (!closed) wsdom.handleIncomingMessage(msg);
+				})
+				.catch((e) => console.error("WSDOM failed to run a message", e))
+				.then(() => {
+					if (inflating === done) inflating = null;
+				});
+			inflating = done;
+		};
+		ws.onclose = (ev: CloseEvent) => {
+			closed = true;
+			console.debug("WSDOM WebSocket closed", ev);
+			wsdom.disconnect();
+			// the server closes with 4002 if this client is out of date
+			if (ev.code === 4002) {
+				console.error("WSDOM server rejected this client for speaking a different protocol version. Try refreshing the page.");
+				return;
+			}
+			// the server closes with 4001 if it no longer has our session
+			if (wsdom.resumable() && ev.code !== 4001 && retries < 10) {
+				setTimeout(connect, Math.min(500 * 2 ** retries, 10000));
+				retries++;
+			}
+		}
+		ws.onerror = (ev: Event) => {
+			console.warn("WSDOM WebSocket errored", ev);
+		}
 	};
-	ws.onclose = (ev: CloseEvent) => {
-		console.debug("WSDOM WebSocket closed", ev);
-	}
-	ws.onerror = (ev: Event) => {
-		console.warn("WSDOM WebSocket errored", ev);
-	}
+	connect();
 }
 class WSDOM {
 	private internal: WSDOMInternal;
-	constructor(sendMessage: SendMessage) {
-		this.internal = new WSDOMInternal(sendMessage);
+	private sendMessage: SendMessage | null = null;
+	// session token; only given by servers that support resuming
+	private token = "";
+	// number of messages run
+	private received = 0;
+	private ackTimer: ReturnType<typeof setTimeout> | null = null;
+	// messages sent but not yet acknowledged by the server, and how many were acknowledged before them
+	private outbox: (string | Uint8Array)[] = [];
+	private outboxAcked = 0;
+	private resuming = false;
+	constructor() {
+		this.internal = new WSDOMInternal(
+			(msg: string | Uint8Array) => this.send(msg),
+			(token: string) => { this.token = token; },
+			(count: number) => this.acknowledged(count),
+		);
+	}
+	public connect(sendMessage: SendMessage) {
+		this.sendMessage = sendMessage;
+		this.resuming = this.token !== "";
+		sendMessage(`h:${this.token}:${this.received}:${JSON.stringify(clientInfo())}`);
+	}
+	public disconnect() {
+		this.sendMessage = null;
+	}
+	public resumable(): boolean {
+		return this.token !== "";
 	}
 	public handleIncomingMessage(msg: string) {
-		const fn = new Function('_w', msg);
-		fn(this.internal);
+		this.received++;
+		if (msg.startsWith("[")) {
+			runOps(this.internal, JSON.parse(msg));
+		} else {
+			const fn = compile(['_w'], msg);
+			fn(this.internal);
+		}
+		if (this.token !== "" && this.ackTimer === null) {
+			this.ackTimer = setTimeout(() => {
+				this.ackTimer = null;
+				this.sendMessage?.(`a:${this.received}`);
+			}, 100);
+		}
+	}
+	private send(msg: string | Uint8Array) {
+		if (this.token === "") {
+			this.sendMessage?.(msg);
+			return;
+		}
+		this.outbox.push(msg);
+		if (!this.resuming) {
+			this.sendMessage?.(msg);
+		}
+	}
+	private acknowledged(count: number) {
+		const n = Math.min(Math.max(count - this.outboxAcked, 0), this.outbox.length);
+		this.outbox.splice(0, n);
+		this.outboxAcked += n;
+		if (this.resuming) {
+			this.resuming = false;
+			for (const msg of this.outbox) {
+				this.sendMessage?.(msg);
+			}
+		}
 	}
 }
 class WSDOMInternal {
 	private sender: SendMessage;
 	private values: Map<Id, Value>;
-	constructor(sender: SendMessage) {
+	// set by the server with `CommandFormat::Ops`; checked by `runOps`
+	public policy: Policy | null = null;
+	// compiled by `runOps` for `CommandFormat::Templates`, by id
+	public templates: Map<number, (w: WSDOMInternal, params: Op) => void> = new Map();
+	// strings interned by the server, by token
+	private strings: string[] = [];
+	// whether to reply in binary messages; set by the server with `ReplyFormat::MessagePack`
+	private binary = false;
+	public t: (token: string) => void;
+	public k: (count: number) => void;
+	constructor(sender: SendMessage, setToken: (token: string) => void, acknowledged: (count: number) => void) {
 		this.sender = sender;
 		this.values = new Map();
+		this.t = setToken;
+		this.k = acknowledged;
 	}
 	public g = (id: Id): Value => {
 		return this.values.get(id);
@@ -48,8 +156,480 @@ class WSDOMInternal {
 	public d = (id: Id) => {
 		this.values.delete(id);
 	}
+	public D = (ids: Id[]) => {
+		for (const id of ids) this.values.delete(id);
+	}
+	public R = (lo: Id, hi: Id) => {
+		for (let id = lo; id <= hi; id++) this.values.delete(id);
+	}
+	public i = (token: number, str: string): string => {
+		return this.strings[token] = str;
+	}
+	public I = (token: number): string => {
+		return this.strings[token];
+	}
+	public B = (on: number | boolean) => {
+		this.binary = !!on;
+	}
+	// raw code from the user, compiled on its own so that it sees the page's globals, not our helpers
+	public x = (code: string) => {
+		new Function('_w', code)(this);
+	}
+	public e = (code: string): Value => {
+		return new Function('_w', `return (${code});`)(this);
+	}
 	public r = (id: Id, val: Value) => {
-		const valJson = JSON.stringify(val);
+		if (this.binary) {
+			(this.sender)(new BinaryReply(id, val).finish());
+			return;
+		}
+		const valJson = JSON.stringify(val, encodeValue) ?? '{"$w":"u"}';
 		(this.sender)(`${id}:${valJson}`);
 	}
+	public n = (val: Value, maxDepth: number, props: string[] | null): Value => {
+		const seen = new Map<object, number>();
+		const visit = (v: Value, depth: number): Value => {
+			if (typeof v === "function") {
+				return snapshotNode({ $w: "f", v: v.name });
+			}
+			if (typeof v !== "object" || v === null || v instanceof Date || v instanceof RegExp) {
+				return v;
+			}
+			const seenId = seen.get(v);
+			if (seenId !== undefined) {
+				return snapshotNode({ $w: "@", v: seenId });
+			}
+			const c = className(v);
+			if (depth >= maxDepth) {
+				return snapshotNode({ $w: "t", c });
+			}
+			const i = seen.size;
+			seen.set(v, i);
+			if (Array.isArray(v)) {
+				return snapshotNode({ $w: "a", i, v: v.map((item) => visit(item, depth + 1)) });
+			}
+			const entries: [string, Value][] = [];
+			const keys: string[] = [];
+			if (props) {
+				keys.push(...props.filter((k) => k in v));
+			} else {
+				for (const k in v) {
+					keys.push(k);
+				}
+			}
+			for (const k of keys.filter((k) => permits(this.policy, "m", k))) {
+				let item: Value;
+				try {
+					item = visit((v as any)[k], depth + 1);
+				} catch (e) {
+					item = snapshotNode({ $w: "x", v: String(e) });
+				}
+				entries.push([k, item]);
+			}
+			return snapshotNode({ $w: "s", c, i, v: entries });
+		};
+		return visit(val, 0);
+	}
+}
+// Nodes made by `n` are already tagged, so they must not be escaped by `encodeValue`.
+const snapshotNodes = new WeakSet<object>();
+function snapshotNode(node: object): object {
+	snapshotNodes.add(node);
+	return node;
+}
+function className(v: object): string | null {
+	const proto = Object.getPrototypeOf(v);
+	if (proto === null || proto === Object.prototype) {
+		return null;
+	}
+	return proto.constructor?.name ?? null;
+}
+// Values that JSON can't represent are sent as {"$w": tag, "v": payload}.
+function encodeValue(this: any, key: string, value: Value): Value {
+	const orig = this[key];
+	if (orig instanceof Date) {
+		return { $w: "d", v: orig.getTime() };
+	}
+	if (orig instanceof RegExp) {
+		return { $w: "r", v: orig.source, f: orig.flags };
+	}
+	if (typeof value === "bigint") {
+		return { $w: "b", v: value.toString() };
+	}
+	if (value === undefined) {
+		return { $w: "u" };
+	}
+	if (typeof value === "number" && (!isFinite(value) || Object.is(value, -0))) {
+		return { $w: "n", v: Object.is(value, -0) ? "-0" : String(value) };
+	}
+	if (typeof value === "object" && value !== null && !Array.isArray(value) && Object.prototype.hasOwnProperty.call(value, "$w") && !snapshotNodes.has(value)) {
+		return { $w: "o", v: Object.entries(value) };
+	}
+	return value;
+}
+const textEncoder = typeof TextEncoder === "function" ? new TextEncoder() : null;
+// A reply in a binary message: the byte `r`, the id as a big-endian u64, then the value in MessagePack,
+// encoded the way `JSON.stringify(val, encodeValue)` would encode it.
+class BinaryReply {
+	private bytes = new Uint8Array(256);
+	private view = new DataView(this.bytes.buffer);
+	private len = 0;
+	// objects being encoded, to throw on cycles like `JSON.stringify` does
+	private stack = new Set<object>();
+	constructor(id: Id, val: Value) {
+		this.u8(0x72);
+		this.u32(Math.floor(id / 2 ** 32));
+		this.u32(id >>> 0);
+		if (!this.value({ "": val }, "")) {
+			this.head(0x80, 0xde, 1);
+			this.str("$w");
+			this.str("u");
+		}
+	}
+	public finish(): Uint8Array {
+		return this.bytes.subarray(0, this.len);
+	}
+	// Encodes `holder[key]`; returns false if JSON would leave it out.
+	private value(holder: any, key: string): boolean {
+		let v = holder[key];
+		if ((typeof v === "object" && v !== null || typeof v === "bigint") && typeof (v as any).toJSON === "function") {
+			v = (v as any).toJSON(key);
+		}
+		v = encodeValue.call(holder, key, v);
+		if (v instanceof Number || v instanceof String || v instanceof Boolean) {
+			v = v.valueOf();
+		}
+		if (v === null) {
+			this.u8(0xc0);
+		} else if (typeof v === "boolean") {
+			this.u8(v ? 0xc3 : 0xc2);
+		} else if (typeof v === "string") {
+			this.str(v);
+		} else if (typeof v === "number") {
+			this.num(v);
+		} else if (typeof v === "object") {
+			this.object(v);
+		} else {
+			return false;
+		}
+		return true;
+	}
+	private object(v: any) {
+		if (this.stack.has(v)) {
+			throw new TypeError("WSDOM can't reply with a cyclic value");
+		}
+		this.stack.add(v);
+		if (Array.isArray(v)) {
+			this.head(0x90, 0xdc, v.length);
+			for (let i = 0; i < v.length; i++) {
+				if (!this.value(v, String(i))) this.u8(0xc0);
+			}
+		} else {
+			// entries JSON leaves out are only known after encoding them, so the count is filled in after;
+			// it can only be smaller than the number of keys, so it fits in the room left for that
+			const keys = Object.keys(v);
+			const at = this.len;
+			this.head(0x80, 0xde, keys.length);
+			let count = 0;
+			for (const k of keys) {
+				const before = this.len;
+				this.str(k);
+				if (this.value(v, k)) {
+					count++;
+				} else {
+					this.len = before;
+				}
+			}
+			const end = this.len;
+			this.len = at;
+			this.head(0x80, 0xde, count, keys.length);
+			this.len = end;
+		}
+		this.stack.delete(v);
+	}
+	// A MessagePack header: `fix | n` for small `n`, or `base` (then `base + 1`) with a 16-bit (then 32-bit) `n`.
+	// `room` is the largest `n` the header must have room for.
+	private head(fix: number, base: number, n: number, room = n) {
+		if (room < 16) {
+			this.u8(fix | n);
+		} else if (room < 0x10000) {
+			this.u8(base);
+			this.u16(n);
+		} else {
+			this.u8(base + 1);
+			this.u32(n);
+		}
+	}
+	private str(s: string) {
+		const utf8 = textEncoder!.encode(s);
+		if (utf8.length < 32) {
+			this.u8(0xa0 | utf8.length);
+		} else if (utf8.length < 0x100) {
+			this.u8(0xd9);
+			this.u8(utf8.length);
+		} else {
+			this.head(0, 0xda, utf8.length);
+		}
+		this.reserve(utf8.length);
+		this.bytes.set(utf8, this.len);
+		this.len += utf8.length;
+	}
+	private num(n: number) {
+		if (!isFinite(n)) {
+			this.u8(0xc0);
+		} else if (!Number.isInteger(n) || n < -0x80000000 || n > 0xffffffff) {
+			this.reserve(9);
+			this.view.setUint8(this.len, 0xcb);
+			this.view.setFloat64(this.len + 1, n);
+			this.len += 9;
+		} else if (n >= 0) {
+			if (n < 0x80) {
+				this.u8(n);
+			} else {
+				this.head(0, 0xcd, n);
+			}
+		} else if (n >= -32) {
+			this.u8(n & 0xff);
+		} else {
+			this.reserve(5);
+			this.view.setUint8(this.len, 0xd2);
+			this.view.setInt32(this.len + 1, n);
+			this.len += 5;
+		}
+	}
+	private reserve(n: number) {
+		if (this.len + n <= this.bytes.length) return;
+		const bytes = new Uint8Array(Math.max(this.bytes.length * 2, this.len + n));
+		bytes.set(this.bytes.subarray(0, this.len));
+		this.bytes = bytes;
+		this.view = new DataView(bytes.buffer);
+	}
+	private u8(n: number) {
+		this.reserve(1);
+		this.view.setUint8(this.len++, n);
+	}
+	private u16(n: number) {
+		this.reserve(2);
+		this.view.setUint16(this.len, n);
+		this.len += 2;
+	}
+	private u32(n: number) {
+		this.reserve(4);
+		this.view.setUint32(this.len, n);
+		this.len += 4;
+	}
+}
+// Compact code (`CodeStyle::Compact` on the Rust side) starts with `;`,
+// and calls the helpers of `WSDOMInternal` without `_w.`.
+// It never holds raw code from the user, which goes through `x` and `e` instead.
+function compile(params: string[], code: string): Function {
+	const helpers = "const{s,g,d,D,R,r,n,t,k,i,I,B,x,e}=_w;";
+	return new Function(...params, code.startsWith(";") ? helpers + code : code);
+}
+// Interpreter for structured ops (`CommandFormat::Ops` on the Rust side), for pages where `new Function` is not allowed.
+type Op = any[];
+function runOps(w: WSDOMInternal, ops: Op[]) {
+	for (const op of ops) {
+		if (typeof op[0] === "number") {
+			// a template call; the parameters are `op[1]` and on
+			w.templates.get(op[0])!(w, op);
+			continue;
+		}
+		switch (op[0]) {
+			case "s": w.s(op[1], evalOp(w, op[2])); break;
+			case "e": evalOp(w, op[1]); break;
+			case "d": w.d(op[1]); break;
+			case "D": w.D(op[1]); break;
+			case "Z": w.R(op[1], op[2]); break;
+			case "r": w.r(op[1], evalOp(w, op[2])); break;
+			case "f": {
+				const [obj, prop] = evalMember(w, op[1], op[2]);
+				obj[prop] = evalOp(w, op[3]);
+				break;
+			}
+			case "R": checkPolicy(w, "g", "eval"); w.x(op[1]); break;
+			case "t": w.t(op[1]); break;
+			case "k": w.k(op[1]); break;
+			case "A": w.policy = op[1]; break;
+			case "B": w.B(op[1]); break;
+			case "T": checkPolicy(w, "g", "eval"); w.templates.set(op[1], compile(['_w', '_p'], op[2]) as any); break;
+			default: throw new Error(`unknown WSDOM op ${op[0]}`);
+		}
+	}
+}
+function evalOp(w: WSDOMInternal, e: Op): any {
+	switch (e[0]) {
+		case "l": return e[1];
+		case "u": return undefined;
+		case "i": return w.i(e[1], e[2]);
+		case "I": return w.I(e[1]);
+		case "n": return Number(e[1]);
+		case "b": return BigInt(e[1]);
+		case "g": return w.g(e[1]);
+		case "v": checkPolicy(w, "g", e[1]); return lookupPath(globalThis, e[1]);
+		case "a": return e.slice(1).map((item) => evalOp(w, item));
+		case "o": {
+			const obj: any = {};
+			for (let i = 1; i < e.length; i += 2) {
+				obj[e[i]] = evalOp(w, e[i + 1]);
+			}
+			return obj;
+		}
+		case "p": {
+			const [obj, prop] = evalMember(w, e[1], e[2]);
+			return obj[prop];
+		}
+		case "c": return evalOp(w, e[1])(...evalArgs(w, e[2], e[3]));
+		case "m": {
+			const [obj, name] = evalMember(w, e[1], typeof e[2] === "string" ? ["l", e[2]] : e[2]);
+			return obj[name](...evalArgs(w, e[3], e[4]));
+		}
+		case "w": {
+			if (e[1][0] === "v") {
+				checkPolicy(w, "c", e[1][1]);
+			}
+			const ctor = e[1][0] === "v" ? lookupPath(globalThis, e[1][1]) : evalOp(w, e[1]);
+			return new ctor(...evalArgs(w, e[2], e[3]));
+		}
+		case "x": return binaryOps[e[1]](evalOp(w, e[2]), evalOp(w, e[3]));
+		case "y": return unaryOps[e[1]](evalOp(w, e[2]));
+		case "C": {
+			const array = e[1], ret = e[2];
+			return function (ev: Value) {
+				(w.g(array) as Value[]).push(ev);
+				w.r(ret, 0);
+			};
+		}
+		case "N": return w.n(evalOp(w, e[1]), e[2], e[3]);
+		case "P": {
+			const v = evalOp(w, e[1]);
+			for (const path of e[2] as string[]) {
+				path.split(".").forEach((name) => checkPolicy(w, "m", name));
+			}
+			// like `v?.a?.b`: missing properties along the way make the rest undefined
+			return e[2].map((path: string) => path.split(".").reduce((obj: any, key) => obj?.[key], v));
+		}
+		case "E": checkPolicy(w, "g", "eval"); return w.e(e[1]);
+		default: throw new Error(`unknown WSDOM op ${e[0]}`);
+	}
+}
+// Evaluate `object[property]` up to the lookup, checking the policy.
+// Properties of globals are checked as global paths, like the server does.
+function evalMember(w: WSDOMInternal, objOp: Op, propOp: Op): [any, any] {
+	if (objOp[0] === "v") {
+		const prop = evalOp(w, propOp);
+		checkPolicy(w, "g", `${objOp[1]}.${prop}`);
+		return [lookupPath(globalThis, objOp[1]), prop];
+	}
+	const obj = evalOp(w, objOp);
+	const prop = evalOp(w, propOp);
+	checkPolicy(w, "m", String(prop));
+	return [obj, prop];
+}
+function evalArgs(w: WSDOMInternal, args: Op[], spread: boolean): Value[] {
+	const values = args.map((arg) => evalOp(w, arg));
+	if (spread && values.length > 0) {
+		values.push(...(values.pop() as Value[]));
+	}
+	return values;
+}
+function lookupPath(base: any, path: string): Value {
+	return path.split(".").reduce((obj, key) => obj[key], base);
+}
+// Mirrors `Policy` on the server: "g" for globals, "c" for constructors, "m" for members.
+type Rules = { d: boolean, allow: string[], deny: string[] };
+type Policy = Record<"g" | "c" | "m", Rules>;
+function permits(policy: Policy | null, kind: "g" | "c" | "m", name: string): boolean {
+	if (policy === null) {
+		return true;
+	}
+	if (kind !== "m") {
+		name = name.replace(/^((window|globalThis|self)\.)+/, "");
+	}
+	// members of globals (`document.cookie`) are members, and globals (`eval`) may be members of `window`
+	if (kind === "g" && name.split(".").slice(1).some((member) => denies(policy.m, member))) {
+		return false;
+	}
+	if (kind === "m" && denies(policy.g, name)) {
+		return false;
+	}
+	const rules = policy[kind];
+	if (denies(rules, name)) {
+		return false;
+	}
+	return rules.d || rules.allow.some((pattern) => matches(pattern, name));
+}
+function matches(pattern: string, name: string): boolean {
+	return pattern === "*" || pattern === name
+		|| (pattern.endsWith(".*") && name.startsWith(pattern.slice(0, -1)));
+}
+function denies(rules: Rules, name: string): boolean {
+	return rules.deny.some((pattern) => matches(pattern, name));
+}
+function checkPolicy(w: WSDOMInternal, kind: "g" | "c" | "m", name: string) {
+	if (!permits(w.policy, kind, name)) {
+		throw new Error(`WSDOM policy forbids ${{ g: "global", c: "constructor", m: "member" }[kind]} ${name}`);
+	}
+}
+const binaryOps: Record<string, (a: any, b: any) => Value> = {
+	"+": (a, b) => a + b,
+	"-": (a, b) => a - b,
+	"*": (a, b) => a * b,
+	"/": (a, b) => a / b,
+	"%": (a, b) => a % b,
+	"**": (a, b) => a ** b,
+	"&": (a, b) => a & b,
+	"|": (a, b) => a | b,
+	"^": (a, b) => a ^ b,
+	"<<": (a, b) => a << b,
+	">>": (a, b) => a >> b,
+	">>>": (a, b) => a >>> b,
+	"&&": (a, b) => a && b,
+	"||": (a, b) => a || b,
+	"==": (a, b) => a == b,
+	"!=": (a, b) => a != b,
+	"===": (a, b) => a === b,
+	"!==": (a, b) => a !== b,
+	">": (a, b) => a > b,
+	">=": (a, b) => a >= b,
+	"<": (a, b) => a < b,
+	"<=": (a, b) => a <= b,
+};
+const unaryOps: Record<string, (a: any) => Value> = {
+	"-": (a) => -a,
+	"~": (a) => ~a,
+	"!": (a) => !a,
+};
+// Version of the protocol between this client and the server; must match the server's `PROTOCOL_VERSION`.
+const WSDOM_PROTOCOL_VERSION = 1;
+// Sent to the server with every hello, so that it can reject incompatible clients and adapt to this one.
+function clientInfo(): object {
+	const features: string[] = ["resume"];
+	try {
+		new Function("");
+		features.push("eval");
+	} catch (e) {
+		// forbidden by Content-Security-Policy; the server will send structured ops instead
+	}
+	if (typeof BigInt === "function") {
+		features.push("bigint");
+	}
+	if (typeof DecompressionStream === "function") {
+		features.push("deflate");
+	}
+	if (typeof TextEncoder === "function") {
+		features.push("msgpack");
+	}
+	return {
+		version: WSDOM_PROTOCOL_VERSION,
+		userAgent: navigator.userAgent,
+		url: location.href,
+		viewport: { width: window.innerWidth, height: window.innerHeight, devicePixelRatio: window.devicePixelRatio },
+		features,
+	};
+}
+// Inflates a message the server compressed, which it does only if the client announced "deflate".
+function inflate(data: ArrayBuffer): Promise<string> {
+	const stream = new Blob([data]).stream().pipeThrough(new DecompressionStream("deflate"));
+	return new Response(stream).text();
 }
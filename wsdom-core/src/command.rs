@@ -0,0 +1,905 @@
+use std::fmt::{Display, Result};
+use std::ops::Range;
+
+use crate::ident::{is_property_name, JsPath};
+use crate::lazy::Written;
+use crate::link::{Browser, BrowserInternal, Error};
+use crate::policy::Policy;
+use crate::protocol::{
+    ACK, BINARY_REPLIES, DEL, DEL_LIST, DEL_RANGE, GET, INTERN, INTERNED, RAW, RAW_EXPR, REP, SET,
+    SNAP, TOKEN,
+};
+use crate::retrieve::Projection;
+use crate::scope::Deleted;
+use crate::serialize::{JsWriter, UseInJsCode};
+
+/// How commands are written for the JS client.
+///
+/// Set with [Browser::set_command_format].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommandFormat {
+    /// JavaScript code, which the client runs with `new Function()`.
+    #[default]
+    Code,
+    /// Structured ops in JSON, which the client runs with an interpreter instead of `eval`.
+    ///
+    /// Use this for pages whose Content-Security-Policy doesn't allow `unsafe-eval`.
+    /// The raw code APIs ([Browser::run_raw_code] and the like) still need `eval`,
+    /// except for plain names like `document` or `Math.PI`.
+    Ops,
+    /// Code as templates, which the client compiles once and then calls with different literals.
+    ///
+    /// Each command is sent as its shape (like `_w.s(_p[1],_w.g(_p[2]).innerText=_p[3])`) the first time,
+    /// and after that as the shape's id and the literals only.
+    /// Apps that send the same kinds of commands over and over save the client from compiling every message,
+    /// and send less too. Commands with raw code in them are still compiled every time.
+    Templates,
+}
+
+/// How code is laid out in [CommandFormat::Code] and [CommandFormat::Templates].
+///
+/// Set with [Browser::set_code_style].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodeStyle {
+    /// One statement per line, calling the client's helpers through `_w`,
+    /// like `_w.s(2,_w.g(1).f(3,));`. Easy to read while debugging.
+    #[default]
+    Readable,
+    /// No whitespace, and the helpers called by their single-letter names,
+    /// like `;s(2,g(1).f(3))`, for clients on slow connections.
+    ///
+    /// The helpers are local variables of the code, so raw code is sent as a string
+    /// for the client to run where they can't be seen,
+    /// and globals named like a helper (like `s` or `i`) are reached through `globalThis`.
+    Compact,
+}
+
+impl Browser {
+    /// Choose how commands are written for the JS client. See [CommandFormat].
+    ///
+    /// Set this before doing anything with the Browser.
+    /// Clients that report not being able to `eval` are switched to [CommandFormat::Ops] automatically
+    /// when they connect, but only if no commands have been written for them yet,
+    /// as those are already code. Integration libraries start the app after the client connects,
+    /// so this is only a concern when driving a Browser [manually](Browser#manual-usage):
+    /// don't use it before the client's first message arrives.
+    pub fn set_command_format(&self, format: CommandFormat) {
+        let mut link = self.0.lock();
+        link.format = format;
+        link.send_policy();
+    }
+    /// The current [CommandFormat].
+    pub fn command_format(&self) -> CommandFormat {
+        self.0.lock().format
+    }
+    /// Choose how code is laid out. See [CodeStyle].
+    ///
+    /// Set this before doing anything with the Browser.
+    pub fn set_code_style(&self, style: CodeStyle) {
+        self.0.lock().style = style;
+    }
+    /// The current [CodeStyle].
+    pub fn code_style(&self) -> CodeStyle {
+        self.0.lock().style
+    }
+}
+
+/// A statement sent to the client.
+///
+/// Expressions inside are written into a [JsWriter], as code or as ops depending on the format.
+pub(crate) enum Command<'a> {
+    /// Put the value in a slot.
+    Set(u64, &'a dyn UseInJsCode),
+    /// Evaluate the value and throw it away.
+    Eval(&'a dyn UseInJsCode),
+    /// Free a slot.
+    Delete(u64),
+    /// Free some slots.
+    DeleteList(&'a [u64]),
+    /// Free the slots from one id to another, inclusive.
+    DeleteRange(u64, u64),
+    /// Send the value back.
+    Reply(u64, &'a dyn UseInJsCode),
+    /// `object[property] = value`
+    SetField(
+        &'a dyn UseInJsCode,
+        &'a dyn UseInJsCode,
+        &'a dyn UseInJsCode,
+    ),
+    /// Run raw JS statements.
+    Raw(&'a dyn Display),
+    /// Give the client its session token.
+    Token(&'a str),
+    /// Tell the client how many of its messages were received.
+    Ack(u64),
+    /// Give the client the [Policy] to enforce. Only sent in [CommandFormat::Ops].
+    Policy(&'a Policy),
+    /// Have the client send replies in [ReplyFormat::MessagePack](crate::ReplyFormat::MessagePack) (if true) or JSON.
+    BinaryReplies(bool),
+}
+
+impl CommandFormat {
+    /// Write a command that has no values from a Browser in it (as no Browser is given),
+    /// returning where its [template](crate::template) parameters are.
+    pub(crate) fn write(
+        self,
+        style: CodeStyle,
+        buf: &mut String,
+        command: Command<'_>,
+    ) -> Vec<Range<usize>> {
+        let mut writer = JsWriter::new(buf, self, style, 0, None);
+        // without values, the command can't fail to be written
+        let _ = command.write_to(&mut writer);
+        writer.params
+    }
+    /// How many bytes [write](CommandFormat::write) puts before and after the value
+    /// of a [Command::Set] or [Command::Eval].
+    pub(crate) fn value_affixes(
+        self,
+        style: CodeStyle,
+        command: &Command<'_>,
+    ) -> Option<(usize, usize)> {
+        let compact = self != CommandFormat::Ops && style == CodeStyle::Compact;
+        let prefix = match (self, command) {
+            (CommandFormat::Ops, Command::Set(id, _)) => format!(r#"["s",{id},"#).len(),
+            (CommandFormat::Ops, Command::Eval(_)) => r#"["e","#.len(),
+            (_, Command::Set(id, _)) => format!("{}({id},", helper_name(style, SET)).len(),
+            (_, Command::Eval(_)) => "(".len(),
+            _ => return None,
+        };
+        let (prefix, suffix) = match self {
+            CommandFormat::Code | CommandFormat::Templates if compact => (prefix + 1, ")".len()),
+            CommandFormat::Code | CommandFormat::Templates => (prefix, ");\n".len()),
+            CommandFormat::Ops => (prefix, "],".len()),
+        };
+        Some((prefix, suffix))
+    }
+    /// What [write](CommandFormat::write) puts after the last id of a [Command::DeleteList].
+    pub(crate) fn delete_list_suffix(self, style: CodeStyle) -> &'static str {
+        match (self, style) {
+            (CommandFormat::Ops, _) => "]],",
+            (_, CodeStyle::Compact) => "])",
+            (_, CodeStyle::Readable) => "]);\n",
+        }
+    }
+    /// Turn written commands into a message.
+    ///
+    /// For [CommandFormat::Templates], the commands must have been [templatized](BrowserInternal::templatize)
+    /// and finished as [CommandFormat::Ops].
+    pub(crate) fn finish(self, commands: String) -> String {
+        match self {
+            CommandFormat::Code | CommandFormat::Templates => commands,
+            CommandFormat::Ops => {
+                format!("[{}]", commands.strip_suffix(',').unwrap_or(&commands))
+            }
+        }
+    }
+}
+
+impl Command<'_> {
+    fn write_to(self, w: &mut JsWriter<'_>) -> Result {
+        if w.ops() {
+            self.write_op(w)?;
+            w.push(",");
+            return Ok(());
+        }
+        if let Command::Policy(_) = self {
+            // code can do anything, so there's no point enforcing it on the client
+            return Ok(());
+        }
+        let compact = w.compact();
+        let end = match (compact, &self) {
+            (true, _) => "",
+            (false, Command::Raw(_)) => "\n",
+            (false, _) => ";\n",
+        };
+        if compact {
+            w.push(";");
+        }
+        match self {
+            Command::Set(id, value) => helper_call(w, SET, Some(id), Some(value))?,
+            Command::Eval(value) => {
+                w.push("(");
+                value.serialize_to(w)?;
+                w.push(")");
+            }
+            Command::Delete(id) => helper_call(w, DEL, Some(id), None)?,
+            Command::DeleteList(ids) => {
+                w.helper(DEL_LIST);
+                w.push("(");
+                w.json_ids(ids)?;
+                w.push(")");
+            }
+            Command::DeleteRange(lo, hi) => {
+                w.helper(DEL_RANGE);
+                w.push("(");
+                w.id(lo);
+                w.push(",");
+                w.id(hi);
+                w.push(")");
+            }
+            Command::Reply(id, value) => helper_call(w, REP, Some(id), Some(value))?,
+            Command::SetField(object, property, value) => {
+                w.push("(");
+                object.serialize_to(w)?;
+                w.push(")[");
+                property.serialize_to(w)?;
+                w.push("]=");
+                value.serialize_to(w)?;
+            }
+            Command::Raw(code) if compact => {
+                w.helper(RAW);
+                w.push("(");
+                w.json(&code.to_string())?;
+                w.push(")");
+            }
+            Command::Raw(code) => w.display(format_args!("{{ {code} }}")),
+            Command::Token(token) => {
+                w.helper(TOKEN);
+                w.push("(");
+                w.json(token)?;
+                w.push(")");
+            }
+            Command::Ack(count) => helper_call(w, ACK, Some(count), None)?,
+            Command::BinaryReplies(on) => {
+                helper_call(w, BINARY_REPLIES, Some(u64::from(on)), None)?
+            }
+            Command::Policy(_) => {}
+        }
+        w.push(end);
+        Ok(())
+    }
+    fn write_op(self, w: &mut JsWriter<'_>) -> Result {
+        match self {
+            Command::Set(id, value) => {
+                w.push(r#"["s","#);
+                w.id(id);
+                w.push(",");
+                value.serialize_to(w)?;
+            }
+            Command::Eval(value) => {
+                w.push(r#"["e","#);
+                value.serialize_to(w)?;
+            }
+            Command::Delete(id) => {
+                w.push(r#"["d","#);
+                w.id(id);
+            }
+            Command::DeleteList(ids) => {
+                w.push(r#"["D","#);
+                w.json_ids(ids)?;
+            }
+            Command::DeleteRange(lo, hi) => {
+                w.push(r#"["Z","#);
+                w.id(lo);
+                w.push(",");
+                w.id(hi);
+            }
+            Command::Reply(id, value) => {
+                w.push(r#"["r","#);
+                w.id(id);
+                w.push(",");
+                value.serialize_to(w)?;
+            }
+            Command::SetField(object, property, value) => {
+                w.push(r#"["f","#);
+                object.serialize_to(w)?;
+                w.push(",");
+                property.serialize_to(w)?;
+                w.push(",");
+                value.serialize_to(w)?;
+            }
+            Command::Raw(code) => {
+                w.push(r#"["R","#);
+                w.json(&code.to_string())?;
+            }
+            Command::Token(token) => {
+                w.push(r#"["t","#);
+                w.json(token)?;
+            }
+            Command::Ack(count) => {
+                w.push(r#"["k","#);
+                w.id(count);
+            }
+            Command::Policy(policy) => {
+                w.push(r#"["A","#);
+                w.json(policy)?;
+            }
+            Command::BinaryReplies(on) => w.display(format_args!(r#"["B",{on}"#)),
+        }
+        w.push("]");
+        Ok(())
+    }
+}
+
+/// Write `helper(id,value)` with whichever of the two there are.
+fn helper_call(
+    w: &mut JsWriter<'_>,
+    helper: &'static str,
+    id: Option<u64>,
+    value: Option<&dyn UseInJsCode>,
+) -> Result {
+    w.helper(helper);
+    w.push("(");
+    if let Some(id) = id {
+        w.id(id);
+    }
+    if let Some(value) = value {
+        if id.is_some() {
+            w.push(",");
+        }
+        value.serialize_to(w)?;
+    }
+    w.push(")");
+    Ok(())
+}
+
+impl BrowserInternal {
+    /// Write a command into the outgoing buffer, killing the Browser if that fails.
+    ///
+    /// Commands that would break the [Quotas](crate::Quotas) are not written.
+    pub(crate) fn push_command(&mut self, command: Command<'_>) {
+        let (set, deleted) = match command {
+            Command::Set(id, _) => (Some(id), None),
+            Command::Delete(id) => (None, Some(id)),
+            _ => (None, None),
+        };
+        match command {
+            Command::Set(id, _) if !self.allows_new_value() => {
+                self.value_refused(id);
+                return;
+            }
+            Command::Delete(id) => {
+                self.value_deleted();
+                if self.scope_depth > 0 {
+                    // freed at the end of the scope
+                    self.scope_deleted.push(id);
+                    return;
+                }
+                // a refused delete leaves the JS value in the slot, so the slot mustn't be reused
+                let mut delete = String::new();
+                self.format
+                    .write(self.style, &mut delete, Command::Delete(id));
+                if !self.allows_outgoing(self.outgoing_len() + delete.len()) {
+                    return;
+                }
+                self.free_slot_id(id);
+                // adding to the delete before takes fewer bytes than a delete of its own
+                if self.coalesce_delete(id) {
+                    return;
+                }
+            }
+            _ => {}
+        }
+        let (format, style, key) = (self.format, self.style, self.key);
+        let affixes = format.value_affixes(style, &command);
+        let raw = matches!(command, Command::Raw(_));
+        let inlinable = matches!(
+            command,
+            Command::Set(..) | Command::Eval(_) | Command::Reply(..) | Command::SetField(..)
+        );
+        let len = self.commands_buf.len();
+        let mut writer = JsWriter::new(
+            &mut self.commands_buf,
+            format,
+            style,
+            key,
+            Some(&mut self.strings),
+        );
+        let result = command.write_to(&mut writer);
+        let JsWriter {
+            wrong_browser,
+            slots,
+            raw: raw_code,
+            conditional,
+            params,
+            ..
+        } = writer;
+        let error = match result {
+            // even if a UseInJsCode ignored the error
+            _ if wrong_browser => Some(Error::WrongBrowser),
+            Err(e) => Some(Error::CommandSerialize(e)),
+            Ok(()) => None,
+        };
+        if let Some(error) = error {
+            self.strings.rollback();
+            self.commands_buf.truncate(len);
+            self.kill(error);
+            return;
+        }
+        let new_len = self.raw_commands_buf().len();
+        if !self.allows_outgoing(self.outgoing_len()) {
+            self.strings.rollback();
+            self.raw_commands_buf().truncate(len);
+            if let Some(id) = set {
+                self.value_deleted();
+                self.value_refused(id);
+            }
+            return;
+        }
+        let value = affixes.map(|(prefix, suffix)| len + prefix..new_len - suffix);
+        self.written.push(Written {
+            start: len,
+            end: new_len,
+            set,
+            value,
+            inlinable: inlinable && !conditional,
+            slots,
+            raw: raw || raw_code,
+            params,
+            defines: self.strings.commit(),
+            deleted: deleted.map(|id| Deleted::Range(id, id)),
+        });
+    }
+}
+
+/// A helper function of the client, like [GET]; called without `_w.` in [CodeStyle::Compact].
+pub(crate) fn helper_name(style: CodeStyle, name: &'static str) -> &'static str {
+    match style {
+        CodeStyle::Compact => name.strip_prefix("_w.").unwrap_or(name),
+        CodeStyle::Readable => name,
+    }
+}
+
+/// Every helper, which code in [CodeStyle::Compact] sees as local variables.
+const HELPERS: [&str; 14] = [
+    GET,
+    DEL,
+    DEL_LIST,
+    DEL_RANGE,
+    SET,
+    REP,
+    SNAP,
+    TOKEN,
+    ACK,
+    INTERN,
+    INTERNED,
+    BINARY_REPLIES,
+    RAW,
+    RAW_EXPR,
+];
+
+/// Whether a global of this name is hidden by a helper in [CodeStyle::Compact].
+pub(crate) fn is_helper_name(name: &str) -> bool {
+    HELPERS
+        .iter()
+        .any(|helper| helper_name(CodeStyle::Compact, helper) == name)
+}
+
+/// The value in a slot.
+pub(crate) struct Slot(pub u64);
+
+impl UseInJsCode for Slot {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        w.slot(self.0);
+        Ok(())
+    }
+}
+
+/// Code already written, like the value of a command being rewritten.
+pub(crate) struct Verbatim<'a>(pub &'a str);
+
+impl UseInJsCode for Verbatim<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        w.push(self.0);
+        Ok(())
+    }
+}
+
+/// Raw JS code used as an expression. See [JsWriter::write_raw_code].
+pub(crate) struct RawCode<'a>(pub &'a str);
+
+impl UseInJsCode for RawCode<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        w.write_raw_code(self.0)
+    }
+}
+
+/// Whether the code is a dotted path of identifiers, like `Intl.NumberFormat`.
+pub(crate) fn is_path(code: &str) -> bool {
+    JsPath::new(code).is_some()
+}
+
+/// What a [Call] calls.
+pub(crate) enum Callee<'a> {
+    /// A global function, like `alert` or `Math.cos`.
+    Function(&'a str),
+    /// A global class, like `RegExp`.
+    Constructor(&'a str),
+    /// A method of the value in a slot.
+    Method(u64, &'a str),
+    /// The value in a slot, which is a function.
+    Value(u64),
+}
+
+/// A function call.
+pub(crate) struct Call<'a> {
+    pub callee: Callee<'a>,
+    pub args: Vec<&'a dyn UseInJsCode>,
+    /// Whether the last argument is spread (`...arg`).
+    pub spread: bool,
+}
+
+impl UseInJsCode for Call<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        let last = self.args.len().wrapping_sub(1);
+        if w.ops() {
+            match self.callee {
+                Callee::Function(path) => match path.rsplit_once('.') {
+                    // call it as a method so that `this` is right
+                    Some((object, name)) => {
+                        w.push(r#"["m",["v","#);
+                        w.json(object)?;
+                        w.push("],");
+                        w.json(name)?;
+                        w.push(",");
+                    }
+                    None => {
+                        w.push(r#"["c",["v","#);
+                        w.json(path)?;
+                        w.push("],");
+                    }
+                },
+                Callee::Constructor(path) => {
+                    w.push(r#"["w",["v","#);
+                    w.json(path)?;
+                    w.push("],");
+                }
+                Callee::Method(id, name) => {
+                    w.push(r#"["m","#);
+                    w.slot(id);
+                    w.push(",");
+                    w.method_name(name)?;
+                    w.push(",");
+                }
+                Callee::Value(id) => {
+                    w.push(r#"["c","#);
+                    w.slot(id);
+                    w.push(",");
+                }
+            }
+            w.push("[");
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    w.push(",");
+                }
+                arg.serialize_to(w)?;
+            }
+            w.display(format_args!("],{}]", self.spread));
+        } else {
+            match self.callee {
+                Callee::Function(name) => w.global(name),
+                Callee::Constructor(name) => {
+                    w.push("new ");
+                    w.global(name);
+                }
+                Callee::Method(id, name) => {
+                    w.slot(id);
+                    w.method_name(name)?;
+                }
+                Callee::Value(id) => {
+                    w.push("(");
+                    w.slot(id);
+                    w.push(")");
+                }
+            }
+            // readable code has a comma after every argument
+            let compact = w.compact();
+            w.push("(");
+            for (i, arg) in self.args.iter().enumerate() {
+                if self.spread && i == last {
+                    w.push("...");
+                }
+                arg.serialize_to(w)?;
+                if i != last || !compact {
+                    w.push(",");
+                }
+            }
+            w.push(")");
+        }
+        Ok(())
+    }
+}
+
+/// `object[property]`
+pub(crate) struct Field<'a>(pub &'a dyn UseInJsCode, pub &'a dyn UseInJsCode);
+
+impl UseInJsCode for Field<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        let Self(object, property) = self;
+        if w.ops() {
+            w.push(r#"["p","#);
+            object.serialize_to(w)?;
+            w.push(",");
+            property.serialize_to(w)?;
+            w.push("]");
+        } else {
+            w.push("(");
+            object.serialize_to(w)?;
+            w.push(")[");
+            property.serialize_to(w)?;
+            w.push("]");
+        }
+        Ok(())
+    }
+}
+
+/// A JS operator applied to one or two operands.
+pub(crate) enum Operator<'a> {
+    Unary(&'static str, &'a dyn UseInJsCode),
+    Binary(&'static str, &'a dyn UseInJsCode, &'a dyn UseInJsCode),
+}
+
+impl UseInJsCode for Operator<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        if let Operator::Binary("&&" | "||" | "??", ..) = self {
+            w.conditional = true;
+        }
+        match *self {
+            Operator::Unary(op, a) if w.ops() => {
+                w.push(r#"["y","#);
+                w.json(op)?;
+                w.push(",");
+                a.serialize_to(w)?;
+                w.push("]");
+            }
+            Operator::Unary(op, a) => {
+                w.push("(");
+                w.push(op);
+                w.push("(");
+                a.serialize_to(w)?;
+                w.push("))");
+            }
+            Operator::Binary(op, a, b) if w.ops() => {
+                w.push(r#"["x","#);
+                w.json(op)?;
+                w.push(",");
+                a.serialize_to(w)?;
+                w.push(",");
+                b.serialize_to(w)?;
+                w.push("]");
+            }
+            Operator::Binary(op, a, b) => {
+                w.push("((");
+                a.serialize_to(w)?;
+                if w.compact() {
+                    w.display(format_args!("){op}("));
+                } else {
+                    w.display(format_args!(") {op} ("));
+                }
+                b.serialize_to(w)?;
+                w.push("))");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function that pushes its argument onto the array in slot `array` and replies to `ret`,
+/// for [callbacks](crate::callback).
+pub(crate) struct CallbackFunction {
+    pub array: u64,
+    pub ret: u64,
+}
+
+impl UseInJsCode for CallbackFunction {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        let Self { array, ret } = *self;
+        if w.ops() {
+            w.push(r#"["C","#);
+            w.id(array);
+            w.push(",");
+            w.id(ret);
+            w.push("]");
+        } else if w.compact() {
+            w.push("e=>{");
+            w.helper(GET);
+            w.push("(");
+            w.id(array);
+            w.push(").push(e);");
+            w.helper(REP);
+            w.push("(");
+            w.id(ret);
+            w.push(",0)}");
+        } else {
+            w.push("function(e) { ");
+            w.helper(GET);
+            w.push("(");
+            w.id(array);
+            w.push(").push(e); ");
+            w.helper(REP);
+            w.push("(");
+            w.id(ret);
+            w.push(", 0) }");
+        }
+        Ok(())
+    }
+}
+
+/// The value in a slot, transformed by a [Projection] before being sent back.
+pub(crate) struct Projected<'a>(pub Option<&'a Projection>, pub u64);
+
+impl UseInJsCode for Projected<'_> {
+    fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+        let id = self.1;
+        match (self.0, w.ops()) {
+            (None, false) => {
+                w.push("(");
+                w.slot(id);
+                w.push(")");
+            }
+            (None, true) => w.slot(id),
+            (Some(Projection::Paths(paths)), false) => {
+                // a missing property along the way makes the rest `undefined`, not an error
+                w.push("(o=>[");
+                for path in *paths {
+                    w.push("o");
+                    for name in path.split('.') {
+                        w.push("?.");
+                        // hand-written FromJs impls may have any paths
+                        if is_property_name(name) {
+                            w.push(name);
+                        } else {
+                            w.push("[");
+                            w.json(name)?;
+                            w.push("]");
+                        }
+                    }
+                    w.push(",");
+                }
+                w.push("])(");
+                w.slot(id);
+                w.push(")");
+            }
+            (Some(Projection::Paths(paths)), true) => {
+                w.push(r#"["P","#);
+                w.slot(id);
+                w.push(",");
+                w.json(paths)?;
+                w.push("]");
+            }
+            (
+                Some(Projection::Snapshot {
+                    max_depth,
+                    properties,
+                }),
+                false,
+            ) => {
+                w.push("(v=>");
+                w.helper(SNAP);
+                w.display(format_args!("(v,{max_depth},"));
+                w.json(properties)?;
+                w.push("))(");
+                w.slot(id);
+                w.push(")");
+            }
+            (
+                Some(Projection::Snapshot {
+                    max_depth,
+                    properties,
+                }),
+                true,
+            ) => {
+                w.push(r#"["N","#);
+                w.slot(id);
+                w.display(format_args!(",{max_depth},"));
+                w.json(properties)?;
+                w.push("]");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Result;
+
+    use crate::{
+        js_path, js_types::*, Browser, CodeStyle, CommandFormat, Error, FormatJsCode, JsWriter,
+        UseInJsCode,
+    };
+
+    #[test]
+    fn values_of_another_browser_kill_it_without_sending_anything() {
+        for field in [false, true] {
+            let (a, b) = (Browser::new(), Browser::new());
+            let from_a: JsValue = a.new_value(&1);
+            let other: JsValue = b.new_value(&2);
+            let before = b.0.lock().commands_buf.clone();
+            let _used = match field {
+                false => b.call_function(js_path!("f"), [&from_a as &dyn UseInJsCode], false),
+                true => b.get_field(&other, &from_a),
+            };
+            assert!(matches!(b.take_error(), Some(Error::WrongBrowser)));
+            assert_eq!(b.0.lock().commands_buf, before);
+            assert!(a.take_error().is_none());
+        }
+    }
+
+    #[test]
+    fn values_of_another_browser_are_caught_even_if_the_error_is_ignored() {
+        struct Ignoring<'a>(&'a JsValue);
+        impl UseInJsCode for Ignoring<'_> {
+            fn serialize_to(&self, w: &mut JsWriter<'_>) -> Result {
+                let _ = self.0.serialize_to(w);
+                Ok(())
+            }
+        }
+        let (a, b) = (Browser::new(), Browser::new());
+        let from_a: JsValue = a.new_value(&1);
+        let _used = b.call_function(
+            js_path!("f"),
+            [&Ignoring(&from_a) as &dyn UseInJsCode],
+            false,
+        );
+        assert!(matches!(b.take_error(), Some(Error::WrongBrowser)));
+        assert_eq!(b.0.lock().commands_buf, "");
+    }
+
+    #[test]
+    fn compact_code_keeps_the_helpers_from_raw_code_and_globals() {
+        let browser = Browser::new();
+        browser.set_code_style(CodeStyle::Compact);
+        let arg: JsValue = browser.new_value(&1);
+        let _i = browser.call_function(js_path!("i"), [&arg as &dyn UseInJsCode], false);
+        let _max = browser.call_function(js_path!("Math.max"), [], false);
+        let _s = browser.call_constructor(js_path!("s.Thing"), [], false);
+        browser.run_raw_code(format_args!("g = \"{}\";", 1));
+        let _sum = browser.value_from_raw_code(format_args!("s + 1"));
+        let _document = browser.value_from_raw_code(format_args!("document"));
+        assert_eq!(
+            browser.0.lock().commands_buf,
+            concat!(
+                ";s(1,1)",
+                ";s(2,globalThis.i(g(1)))",
+                ";s(3,Math.max())",
+                ";s(4,new globalThis.s.Thing())",
+                r#";x("g = \"1\";")"#,
+                r#";s(5,e("s + 1"))"#,
+                ";s(6,document)",
+            )
+        );
+    }
+
+    #[test]
+    fn readable_code_is_unchanged() {
+        let browser = Browser::new();
+        let _i = browser.call_function(js_path!("i"), [], false);
+        browser.run_raw_code(format_args!("g = 1;"));
+        let _sum = browser.value_from_raw_code(format_args!("s + 1"));
+        assert_eq!(
+            browser.0.lock().commands_buf,
+            "_w.s(1,i());\n{ g = 1; }\n_w.s(2,s + 1);\n"
+        );
+    }
+
+    #[test]
+    fn formatter_code_is_written_as_raw_code() {
+        struct Now;
+        impl Now {
+            const NAME: &str = "now";
+        }
+        impl FormatJsCode for Now {
+            fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> Result {
+                write!(buf, "Date.{}()", Self::NAME)
+            }
+        }
+        impl UseInJsCode for Now {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> Result {
+                buf.write_formatted(self)
+            }
+        }
+        let browser = Browser::new();
+        let _t = browser.call_function(js_path!("f"), [&Now as &dyn UseInJsCode], false);
+        browser.set_command_format(CommandFormat::Ops);
+        let _t = browser.call_function(js_path!("f"), [&Now as &dyn UseInJsCode], false);
+        assert_eq!(
+            browser.0.lock().commands_buf,
+            concat!(
+                "_w.s(1,f(Date.now(),));\n",
+                r#"["s",2,["c",["v","f"],[["E","Date.now()"]],false]],"#,
+            )
+        );
+    }
+}
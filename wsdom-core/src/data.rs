@@ -0,0 +1,710 @@
+use std::collections::BTreeMap;
+
+use serde::de::{
+    value::{MapDeserializer, SeqDeserializer, StringDeserializer},
+    Deserialize, DeserializeOwned, Deserializer, EnumAccess, Error as _, IntoDeserializer,
+    MapAccess, SeqAccess, Unexpected, VariantAccess, Visitor,
+};
+
+use crate::link::Error;
+use crate::msgpack;
+use crate::protocol::TAG;
+use crate::reply::Payload;
+
+/// A value retrieved from the JS side.
+///
+/// This is like [serde_json::Value], but it can represent JS values that JSON can't:
+/// `undefined` (separately from `null`), `NaN`, `Infinity`, `-0`, `bigint`, `Date`, and `RegExp`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsData {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    /// A `bigint`, in decimal.
+    BigInt(String),
+    String(String),
+    Array(Vec<JsData>),
+    Object(BTreeMap<String, JsData>),
+    /// A `Date`, as milliseconds since the Unix epoch (what `Date.prototype.getTime()` returns).
+    Date(f64),
+    RegExp {
+        source: String,
+        flags: String,
+    },
+}
+
+impl JsData {
+    /// Parse a reply sent by the JS client.
+    pub(crate) fn from_wire(payload: Payload<'_>) -> Result<Self, Error> {
+        match payload {
+            Payload::Json(json) => serde_json::from_str(json)
+                .map(Self::from_json)
+                .map_err(Error::DataDeserialize),
+            Payload::MessagePack(bytes) => {
+                msgpack::to_data(bytes).map_err(Error::MessagePackDeserialize)
+            }
+        }
+    }
+    /// Decode the tagged objects the JS client uses for values that JSON can't represent.
+    pub(crate) fn from_json(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(b) => Self::Boolean(b),
+            Value::Number(n) => Self::Number(n.as_f64().unwrap_or(f64::NAN)),
+            Value::String(s) => Self::String(s),
+            Value::Array(arr) => Self::Array(arr.into_iter().map(Self::from_json).collect()),
+            Value::Object(obj) => Self::from_object(
+                obj.into_iter()
+                    .map(|(k, v)| (k, Self::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+    /// An object sent by the JS client, which may be a tagged value.
+    pub(crate) fn from_object(mut obj: BTreeMap<String, JsData>) -> Self {
+        let tag = match obj.get(TAG) {
+            Some(Self::String(tag)) => tag.to_owned(),
+            _ => String::new(),
+        };
+        match (tag.as_str(), obj.remove("v")) {
+            ("u", _) => Self::Undefined,
+            ("n", Some(Self::String(v))) => Self::Number(match v.as_str() {
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                "-0" => -0.0,
+                _ => f64::NAN,
+            }),
+            ("b", Some(Self::String(v))) => Self::BigInt(v),
+            ("d", Some(Self::Number(ms))) => Self::Date(ms),
+            ("d", Some(_)) => Self::Date(f64::NAN),
+            ("r", Some(Self::String(source))) => Self::RegExp {
+                source,
+                flags: match obj.remove("f") {
+                    Some(Self::String(flags)) => flags,
+                    _ => String::new(),
+                },
+            },
+            // an object that has a property named like our tag
+            ("o", Some(Self::Array(entries))) => Self::Object(
+                entries
+                    .into_iter()
+                    .filter_map(|entry| match entry {
+                        Self::Array(mut kv) if kv.len() == 2 => {
+                            let v = kv.pop()?;
+                            match kv.pop()? {
+                                Self::String(k) => Some((k, v)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            (_, v) => {
+                if let Some(v) = v {
+                    obj.insert("v".to_owned(), v);
+                }
+                Self::Object(obj)
+            }
+        }
+    }
+    /// Convert to a [serde_json::Value].
+    ///
+    /// This is lossy: `undefined` and non-finite numbers become `null`,
+    /// BigInts too large for `i64`/`u64` become strings,
+    /// dates become ISO strings like `JSON.stringify` makes them (`null` if invalid),
+    /// and regexps become `"/source/flags"` strings.
+    pub fn into_json(self) -> serde_json::Value {
+        use serde_json::Value;
+        match self {
+            Self::Undefined | Self::Null => Value::Null,
+            Self::Boolean(b) => Value::Bool(b),
+            Self::Number(n) => number_to_json(n),
+            Self::Date(ms) => date_to_iso(ms).map_or(Value::Null, Value::String),
+            Self::BigInt(s) => match (s.parse::<i64>(), s.parse::<u64>()) {
+                (Ok(i), _) => i.into(),
+                (_, Ok(u)) => u.into(),
+                _ => Value::String(s),
+            },
+            Self::String(s) => Value::String(s),
+            Self::Array(arr) => Value::Array(arr.into_iter().map(Self::into_json).collect()),
+            Self::Object(obj) => {
+                Value::Object(obj.into_iter().map(|(k, v)| (k, v.into_json())).collect())
+            }
+            Self::RegExp { source, flags } => Value::String(format!("/{source}/{flags}")),
+        }
+    }
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Self::Undefined)
+    }
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+    /// Get a property if this is an object.
+    pub fn get(&self, key: &str) -> Option<&JsData> {
+        match self {
+            Self::Object(obj) => obj.get(key),
+            _ => None,
+        }
+    }
+}
+
+fn number_to_json(n: f64) -> serde_json::Value {
+    match as_integer(n) {
+        Some(i) => i.into(),
+        None => serde_json::Number::from_f64(n)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+    }
+}
+
+/// What `Date.prototype.toISOString()` returns for the milliseconds since the Unix epoch,
+/// like `2023-11-14T22:13:20.000Z`, or `None` for an invalid date.
+fn date_to_iso(ms: f64) -> Option<String> {
+    // the range of JS dates
+    if !ms.is_finite() || ms.abs() > 8.64e15 {
+        return None;
+    }
+    let ms = ms as i64;
+    let (days, ms) = (ms.div_euclid(86_400_000), ms.rem_euclid(86_400_000));
+    // the civil date of a day since the epoch, from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let (era, doe) = (z.div_euclid(146_097), z.rem_euclid(146_097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let (h, m, s, ms) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+    let time = format!("{month:02}-{day:02}T{h:02}:{m:02}:{s:02}.{ms:03}Z");
+    Some(match year {
+        0..=9999 => format!("{year:04}-{time}"),
+        // expanded years have a sign and six digits
+        _ => format!("{year:+07}-{time}"),
+    })
+}
+
+/// JS only has `f64`, but most Rust types expect integers to come as integers.
+fn as_integer(n: f64) -> Option<i64> {
+    const MAX_SAFE: f64 = ((1u64 << 53) - 1) as f64;
+    (n.fract() == 0.0 && n.abs() <= MAX_SAFE).then_some(n as i64)
+}
+
+/// Deserialize a reply sent by the JS client.
+pub(crate) fn from_wire<T: DeserializeOwned>(payload: Payload<'_>) -> Result<T, Error> {
+    match payload {
+        Payload::Json(_) => {
+            T::deserialize(JsData::from_wire(payload)?).map_err(Error::DataDeserialize)
+        }
+        Payload::MessagePack(bytes) => {
+            msgpack::from_slice(bytes).map_err(Error::MessagePackDeserialize)
+        }
+    }
+}
+
+/// Numbers are given as integers when they have no fractional part (and aren't `-0`).
+pub(crate) fn visit_number<'de, V: Visitor<'de>, E: serde::de::Error>(
+    n: f64,
+    visitor: V,
+) -> Result<V::Value, E> {
+    if n == 0.0 && n.is_sign_negative() {
+        return visitor.visit_f64(n);
+    }
+    match as_integer(n) {
+        Some(i) if i < 0 => visitor.visit_i64(i),
+        Some(i) => visitor.visit_u64(i as u64),
+        None => visitor.visit_f64(n),
+    }
+}
+
+/// Like [visit_number], for when an integer is asked for, so `-0` is fine as one.
+pub(crate) fn visit_integer<'de, V: Visitor<'de>, E: serde::de::Error>(
+    n: f64,
+    visitor: V,
+) -> Result<V::Value, E> {
+    if n == 0.0 {
+        visitor.visit_u64(0)
+    } else {
+        visit_number(n, visitor)
+    }
+}
+
+impl JsData {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Self::Undefined => Unexpected::Other("undefined"),
+            Self::Null => Unexpected::Unit,
+            Self::Boolean(b) => Unexpected::Bool(*b),
+            Self::Number(n) => Unexpected::Float(*n),
+            Self::BigInt(_) => Unexpected::Other("BigInt"),
+            Self::String(s) => Unexpected::Str(s),
+            Self::Array(_) => Unexpected::Seq,
+            Self::Object(_) => Unexpected::Map,
+            Self::Date(_) => Unexpected::Other("Date"),
+            Self::RegExp { .. } => Unexpected::Other("RegExp"),
+        }
+    }
+}
+
+fn visit_bigint<'de, V: Visitor<'de>>(
+    s: String,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    if let Ok(i) = s.parse() {
+        visitor.visit_i64(i)
+    } else if let Ok(u) = s.parse() {
+        visitor.visit_u64(u)
+    } else if let Ok(i) = s.parse() {
+        visitor.visit_i128(i)
+    } else if let Ok(u) = s.parse() {
+        visitor.visit_u128(u)
+    } else {
+        visitor.visit_string(s)
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self {
+                    Self::Number(n) => visit_integer(n, visitor),
+                    Self::BigInt(s) => visit_bigint(s, visitor),
+                    other => other.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for JsData {
+    type Error = serde_json::Error;
+
+    /// `undefined` is given as unit and `null` is given as none;
+    /// numbers are given as integers when they have no fractional part.
+    /// Dates are given as ISO strings, except to [deserialize_f64](Deserializer::deserialize_f64),
+    /// which gets their milliseconds.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Undefined => visitor.visit_unit(),
+            Self::Null => visitor.visit_none(),
+            Self::Boolean(b) => visitor.visit_bool(b),
+            Self::Number(n) => visit_number(n, visitor),
+            Self::BigInt(s) => visit_bigint(s, visitor),
+            Self::String(s) => visitor.visit_string(s),
+            Self::Array(arr) => visitor.visit_seq(SeqDeserializer::new(arr.into_iter())),
+            Self::Object(obj) => visitor.visit_map(MapDeserializer::new(obj.into_iter())),
+            // as `JSON.stringify` sends them
+            Self::Date(ms) => match date_to_iso(ms) {
+                Some(iso) => visitor.visit_string(iso),
+                None => visitor.visit_none(),
+            },
+            Self::RegExp { source, flags } => visitor.visit_string(format!("/{source}/{flags}")),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Number(n) | Self::Date(n) => visitor.visit_f64(n),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    deserialize_integer!(
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+    );
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::BigInt(s) => visitor.visit_string(s),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Undefined | Self::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Undefined | Self::Null => visitor.visit_unit(),
+            other => Err(Self::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Self::Object(obj) if obj.len() == 1 => {
+                let (variant, value) = obj.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            }
+            other => Err(Self::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool char bytes byte_buf seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Deserializing into [JsData] from other formats can't produce [JsData::Date] or [JsData::RegExp];
+/// unit becomes [JsData::Undefined] and none becomes [JsData::Null].
+impl<'de> Deserialize<'de> for JsData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct JsDataVisitor;
+        impl<'de> Visitor<'de> for JsDataVisitor {
+            type Value = JsData;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("any JS value")
+            }
+            fn visit_bool<E>(self, v: bool) -> Result<JsData, E> {
+                Ok(JsData::Boolean(v))
+            }
+            fn visit_i64<E>(self, v: i64) -> Result<JsData, E> {
+                Ok(JsData::Number(v as f64))
+            }
+            fn visit_u64<E>(self, v: u64) -> Result<JsData, E> {
+                Ok(JsData::Number(v as f64))
+            }
+            fn visit_i128<E>(self, v: i128) -> Result<JsData, E> {
+                Ok(JsData::BigInt(v.to_string()))
+            }
+            fn visit_u128<E>(self, v: u128) -> Result<JsData, E> {
+                Ok(JsData::BigInt(v.to_string()))
+            }
+            fn visit_f64<E>(self, v: f64) -> Result<JsData, E> {
+                Ok(JsData::Number(v))
+            }
+            fn visit_str<E>(self, v: &str) -> Result<JsData, E> {
+                Ok(JsData::String(v.to_owned()))
+            }
+            fn visit_string<E>(self, v: String) -> Result<JsData, E> {
+                Ok(JsData::String(v))
+            }
+            fn visit_unit<E>(self) -> Result<JsData, E> {
+                Ok(JsData::Undefined)
+            }
+            fn visit_none<E>(self) -> Result<JsData, E> {
+                Ok(JsData::Null)
+            }
+            fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<JsData, D::Error> {
+                JsData::deserialize(d)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsData, A::Error> {
+                let mut arr = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    arr.push(item);
+                }
+                Ok(JsData::Array(arr))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsData, A::Error> {
+                let mut obj = BTreeMap::new();
+                while let Some((k, v)) = map.next_entry()? {
+                    obj.insert(k, v);
+                }
+                Ok(JsData::Object(obj))
+            }
+        }
+        deserializer.deserialize_any(JsDataVisitor)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for JsData {
+    type Deserializer = Self;
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: JsData,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = serde_json::Error;
+    type Variant = JsData;
+
+    fn variant_seed<V: serde::de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant: StringDeserializer<serde_json::Error> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for JsData {
+    type Error = serde_json::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        serde::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: serde::de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::Deserialize;
+
+    use super::{from_wire, JsData};
+    use crate::reply::Payload;
+
+    /// What the client's `JSON.stringify(value, encodeValue)` sends for `value`.
+    fn data(json: &str) -> JsData {
+        JsData::from_wire(Payload::Json(json)).unwrap()
+    }
+
+    fn de<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, crate::Error> {
+        from_wire(Payload::Json(json))
+    }
+
+    #[test]
+    fn tags() {
+        let nan = data(r#"{"$w":"n","v":"NaN"}"#);
+        assert!(matches!(nan, JsData::Number(n) if n.is_nan()));
+        assert_eq!(
+            data(r#"{"$w":"n","v":"Infinity"}"#),
+            JsData::Number(f64::INFINITY)
+        );
+        assert_eq!(
+            data(r#"{"$w":"n","v":"-Infinity"}"#),
+            JsData::Number(f64::NEG_INFINITY)
+        );
+        let zero = data(r#"{"$w":"n","v":"-0"}"#);
+        assert!(matches!(zero, JsData::Number(n) if n == 0.0 && n.is_sign_negative()));
+        assert_eq!(data(r#"{"$w":"u"}"#), JsData::Undefined);
+        assert_eq!(data("null"), JsData::Null);
+        assert_eq!(
+            data(r#"{"$w":"b","v":"12345678901234567890123"}"#),
+            JsData::BigInt("12345678901234567890123".into())
+        );
+        assert_eq!(
+            data(r#"{"$w":"d","v":1700000000000}"#),
+            JsData::Date(1700000000000.0)
+        );
+        assert_eq!(
+            data(r#"{"$w":"r","v":"a+b","f":"gi"}"#),
+            JsData::RegExp {
+                source: "a+b".into(),
+                flags: "gi".into()
+            }
+        );
+        assert_eq!(
+            data(r#"{"$w":"o","v":[["$w",1],["x",[{"$w":"u"}]]]}"#),
+            JsData::Object(BTreeMap::from([
+                ("$w".into(), JsData::Number(1.0)),
+                ("x".into(), JsData::Array(vec![JsData::Undefined])),
+            ]))
+        );
+        assert_eq!(
+            data(r#"[1,{"$w":"u"},{"a":{"$w":"u"},"b":0}]"#),
+            JsData::Array(vec![
+                JsData::Number(1.0),
+                JsData::Undefined,
+                JsData::Object(BTreeMap::from([
+                    ("a".into(), JsData::Undefined),
+                    ("b".into(), JsData::Number(0.0)),
+                ])),
+            ])
+        );
+        // not a tag we know, so just an object
+        assert_eq!(
+            data(r#"{"$w":"?","v":2}"#),
+            JsData::Object(BTreeMap::from([
+                ("$w".into(), JsData::String("?".into())),
+                ("v".into(), JsData::Number(2.0)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(de::<u8>("2.0").unwrap(), 2);
+        assert_eq!(de::<i32>("-7").unwrap(), -7);
+        assert_eq!(de::<i32>(r#"{"$w":"n","v":"-0"}"#).unwrap(), 0);
+        assert!(de::<u8>("2.5").is_err());
+        assert!(de::<u8>("256").is_err());
+        let zero = de::<f64>(r#"{"$w":"n","v":"-0"}"#).unwrap();
+        assert!(zero == 0.0 && zero.is_sign_negative());
+        assert!(de::<f64>(r#"{"$w":"n","v":"NaN"}"#).unwrap().is_nan());
+        assert_eq!(
+            de::<f32>(r#"{"$w":"n","v":"-Infinity"}"#).unwrap(),
+            f32::NEG_INFINITY
+        );
+        assert_eq!(de::<f64>(r#"{"$w":"d","v":5}"#).unwrap(), 5.0);
+        // integers that don't fit exactly in an f64 stay floats
+        assert_eq!(de::<f64>("9007199254740993").unwrap(), 9007199254740992.0);
+        assert!(de::<u64>("9007199254740993").is_err());
+    }
+
+    #[test]
+    fn bigints() {
+        let big = |v: &str| format!(r#"{{"$w":"b","v":"{v}"}}"#);
+        assert_eq!(de::<i64>(&big("-5")).unwrap(), -5);
+        assert_eq!(de::<u64>(&big(&u64::MAX.to_string())).unwrap(), u64::MAX);
+        assert_eq!(de::<i128>(&big(&i128::MIN.to_string())).unwrap(), i128::MIN);
+        assert_eq!(de::<u128>(&big(&u128::MAX.to_string())).unwrap(), u128::MAX);
+        assert!(de::<i64>(&big(&u64::MAX.to_string())).is_err());
+        assert_eq!(de::<String>(&big("-5")).unwrap(), "-5");
+        let huge = "1".repeat(50);
+        assert_eq!(de::<String>(&big(&huge)).unwrap(), huge);
+        assert_eq!(
+            data(&big(&huge)).into_json(),
+            serde_json::Value::String(huge)
+        );
+        assert_eq!(data(&big("-5")).into_json(), serde_json::json!(-5));
+    }
+
+    #[test]
+    fn undefined_and_null() {
+        assert_eq!(de::<Option<u8>>(r#"{"$w":"u"}"#).unwrap(), None);
+        assert_eq!(de::<Option<u8>>("null").unwrap(), None);
+        assert_eq!(de::<Option<u8>>("1").unwrap(), Some(1));
+        de::<()>(r#"{"$w":"u"}"#).unwrap();
+        de::<()>("null").unwrap();
+        assert!(de::<()>("0").is_err());
+        assert!(de::<u8>(r#"{"$w":"u"}"#).is_err());
+    }
+
+    #[test]
+    fn structs_and_enums() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Shape {
+            Dot,
+            Circle(f64),
+            Rect { w: u32, h: u32 },
+            Pair(u8, u8),
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Drawing {
+            name: String,
+            shapes: Vec<Shape>,
+            hidden: Option<bool>,
+            meta: HashMap<String, JsData>,
+        }
+        let drawing: Drawing = de(r#"{
+            "name": "d",
+            "shapes": ["Dot", {"Circle": 1.5}, {"Rect": {"w": 2, "h": 3}}, {"Pair": [4, 5]}],
+            "hidden": {"$w":"u"},
+            "meta": {"when": {"$w":"d","v":1}, "pattern": {"$w":"r","v":"x","f":""}},
+            "extra": 1
+        }"#)
+        .unwrap();
+        assert_eq!(
+            drawing,
+            Drawing {
+                name: "d".into(),
+                shapes: vec![
+                    Shape::Dot,
+                    Shape::Circle(1.5),
+                    Shape::Rect { w: 2, h: 3 },
+                    Shape::Pair(4, 5),
+                ],
+                hidden: None,
+                meta: HashMap::from([
+                    (
+                        "when".into(),
+                        JsData::String("1970-01-01T00:00:00.001Z".into())
+                    ),
+                    ("pattern".into(), JsData::String("/x/".into())),
+                ]),
+            }
+        );
+        assert!(de::<Shape>(r#"{"Dot": null, "Circle": 1}"#).is_err());
+        assert!(de::<Shape>("1").is_err());
+    }
+
+    #[test]
+    fn dates_are_iso_strings_like_json_stringify_makes_them() {
+        for (ms, iso) in [
+            ("1700000000000", "\"2023-11-14T22:13:20.000Z\""),
+            ("951782400123", "\"2000-02-29T00:00:00.123Z\""),
+            ("-1", "\"1969-12-31T23:59:59.999Z\""),
+            ("-62198755200000", "\"-000001-01-01T00:00:00.000Z\""),
+            ("8.64e15", "\"+275760-09-13T00:00:00.000Z\""),
+            ("{\"$w\":\"n\",\"v\":\"NaN\"}", "null"),
+        ] {
+            let date = format!(r#"{{"$w":"d","v":{ms}}}"#);
+            let iso: serde_json::Value = serde_json::from_str(iso).unwrap();
+            assert_eq!(data(&date).into_json(), iso);
+            assert_eq!(de::<serde_json::Value>(&date).unwrap(), iso);
+        }
+        let date = r#"{"$w":"d","v":1700000000000}"#;
+        assert_eq!(de::<String>(date).unwrap(), "2023-11-14T22:13:20.000Z");
+        assert_eq!(de::<f64>(date).unwrap(), 1700000000000.0);
+    }
+
+    #[test]
+    fn into_json() {
+        let value = data(
+            r#"[{"$w":"u"},null,{"$w":"n","v":"NaN"},{"$w":"n","v":"-0"},3,{"$w":"d","v":7},{"$w":"r","v":"a","f":"g"}]"#,
+        );
+        assert_eq!(
+            value.into_json(),
+            serde_json::json!([null, null, null, 0, 3, "1970-01-01T00:00:00.007Z", "/a/g"])
+        );
+    }
+}
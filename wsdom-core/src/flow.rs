@@ -0,0 +1,376 @@
+/*!
+When commands are sent, and waiting for them to be sent.
+
+Commands are buffered in the [Browser] until the transport (the integration library, or your own loop)
+takes them. The [FlushPolicy] decides when the transport is woken up to do that.
+
+[Browser::flush] waits until everything buffered has been taken,
+and [Browser::ready] waits until the buffer is below the [high-water mark](Browser::set_high_water_mark),
+so that long-running producers can pace themselves.
+
+```rust
+# use wsdom_core::Browser;
+async fn example(browser: &Browser) {
+    for i in 0..100_000 {
+        browser.new_value::<wsdom_core::js_types::JsNumber>(&i);
+        // don't let the buffer grow faster than the network can take it
+        browser.ready().await;
+    }
+    browser.flush().await;
+}
+```
+
+These Futures only complete if the Browser is being driven by a transport, or if it dies.
+*/
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::link::{Browser, BrowserInternal};
+
+/// When buffered commands are handed to the transport. Set with [Browser::set_flush_policy].
+///
+/// Whatever the policy, waiting for the client sends everything buffered right away.
+/// That is, retrieving a value, waiting for a [Callback](crate::callback::Callback),
+/// or awaiting [Browser::flush] or [Browser::ready].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// Wake the transport after every command.
+    Immediate,
+    /// Wake the transport once per batch, so that the commands written until the app yields go out together.
+    ///
+    /// This only batches when the transport is polled in the same task as the app,
+    /// as in `wsdom_axum::socket_to_browser`,
+    /// where a batch is everything the app does between two awaits.
+    /// If the app runs in a task of its own (say, spawned so that it outlives a reconnecting socket),
+    /// the transport's task can run as soon as the first command wakes it,
+    /// and batches are however much the app wrote by then, often a single command.
+    /// Use [FlushPolicy::Interval] or [FlushPolicy::Size] to batch across tasks.
+    #[default]
+    OnYield,
+    /// Send at most once per interval.
+    ///
+    /// This needs a [Timer](crate::timeout::Timer); without one, it is the same as [FlushPolicy::OnYield].
+    Interval(Duration),
+    /// Send once this many bytes are buffered, or [SIZE_MAX_DELAY] after the first command of the batch,
+    /// whichever comes first.
+    ///
+    /// The delay needs a [Timer](crate::timeout::Timer); without one,
+    /// smaller batches wait until something forces them out, like a retrieval or [Browser::flush].
+    Size(usize),
+}
+
+/// How long [FlushPolicy::Size] holds a batch that hasn't reached its size: 50 ms.
+pub const SIZE_MAX_DELAY: Duration = Duration::from_millis(50);
+
+/// The default [high-water mark](Browser::set_high_water_mark): 1 MiB.
+pub const DEFAULT_HIGH_WATER_MARK: usize = 1 << 20;
+
+impl Browser {
+    /// Wait until every command so far has been handed to the transport. See the [flow module](crate::flow).
+    pub fn flush(&self) -> Flush {
+        Flush {
+            browser: self.clone(),
+        }
+    }
+    /// Wait until the commands waiting to be sent are no more than the
+    /// [high-water mark](Browser::set_high_water_mark). See the [flow module](crate::flow).
+    pub fn ready(&self) -> Ready {
+        Ready {
+            browser: self.clone(),
+        }
+    }
+    /// Choose when buffered commands are sent. See [FlushPolicy].
+    pub fn set_flush_policy(&self, policy: FlushPolicy) {
+        let mut link = self.0.lock();
+        link.flush_policy = policy;
+        link.force_flush();
+    }
+    /// Set how many bytes of commands may wait to be sent before [Browser::ready] waits.
+    ///
+    /// The default is [DEFAULT_HIGH_WATER_MARK].
+    pub fn set_high_water_mark(&self, bytes: usize) {
+        self.0.lock().high_water_mark = bytes;
+    }
+}
+
+/// Future returned by [Browser::flush].
+pub struct Flush {
+    browser: Browser,
+}
+
+/// Future returned by [Browser::ready].
+pub struct Ready {
+    browser: Browser,
+}
+
+impl Future for Flush {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut link = self.browser.0.lock();
+        link.wait_for_outgoing(0, cx)
+    }
+}
+
+impl Future for Ready {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut link = self.browser.0.lock();
+        let mark = link.high_water_mark;
+        link.wait_for_outgoing(mark, cx)
+    }
+}
+
+impl BrowserInternal {
+    /// Ready once at most `bytes` are waiting to be sent, or the Browser is dead.
+    fn wait_for_outgoing(&mut self, bytes: usize, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_dead() || self.outgoing_len() <= bytes {
+            return Poll::Ready(());
+        }
+        let waker = cx.waker();
+        if !self.drain_wakers.iter().any(|w| w.will_wake(waker)) {
+            self.drain_wakers.push(waker.to_owned());
+        }
+        self.force_flush();
+        Poll::Pending
+    }
+    /// Have the transport take the buffered commands now, regardless of the [FlushPolicy].
+    ///
+    /// Call this when waiting for the client.
+    pub(crate) fn force_flush(&mut self) {
+        if !self.raw_commands_buf().is_empty() {
+            self.wake_outgoing();
+        }
+    }
+    /// Wake the transport as the [FlushPolicy] says, after writing a command.
+    pub(crate) fn wake_outgoing_lazy(&mut self) {
+        match self.flush_policy {
+            FlushPolicy::Immediate => self.wake_transport(),
+            // the batch may have been woken already, to poll the delay
+            FlushPolicy::Size(bytes) if self.outgoing_len() >= bytes => self.wake_transport(),
+            FlushPolicy::Size(_) if self.flush_timer.is_none() => {
+                if let Some(timer) = self.timer() {
+                    self.flush_timer = Some(timer.sleep(SIZE_MAX_DELAY));
+                    self.wake_batch();
+                }
+            }
+            FlushPolicy::Size(_) => {}
+            FlushPolicy::Interval(interval) if self.flush_timer.is_none() => {
+                if let Some(timer) = self.timer() {
+                    self.flush_timer = Some(timer.sleep(interval));
+                }
+                self.wake_batch();
+            }
+            _ => self.wake_batch(),
+        }
+    }
+    /// Wake the transport, if not done already for this batch.
+    fn wake_batch(&mut self) {
+        if !self.batch_woken {
+            self.batch_woken = true;
+            self.wake_transport();
+        }
+    }
+    /// Whether the transport should take the buffered commands now.
+    pub(crate) fn should_send(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.must_flush {
+            return true;
+        }
+        match self.flush_policy {
+            FlushPolicy::Immediate | FlushPolicy::OnYield => true,
+            FlushPolicy::Size(bytes) => {
+                self.outgoing_len() >= bytes
+                    || self
+                        .flush_timer
+                        .as_mut()
+                        .is_some_and(|sleep| sleep.as_mut().poll(cx).is_ready())
+            }
+            FlushPolicy::Interval(_) => self
+                .flush_timer
+                .as_mut()
+                .is_none_or(|sleep| sleep.as_mut().poll(cx).is_ready()),
+        }
+    }
+    /// Start a new batch, after the transport took the buffered commands.
+    pub(crate) fn batch_sent(&mut self) {
+        self.must_flush = false;
+        self.batch_woken = false;
+        self.flush_timer = None;
+    }
+    /// Wake the [Flush] and [Ready] Futures, after the transport took something or the Browser died.
+    pub(crate) fn wake_drained(&mut self) {
+        for waker in self.drain_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{pending, ready},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        time::Duration,
+    };
+
+    use futures_util::{FutureExt, StreamExt};
+
+    use super::FlushPolicy;
+    use crate::{
+        timeout::{Sleep, Timer},
+        Browser,
+    };
+
+    /// Counts how many times the transport was woken.
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Sleeps that end right away.
+    struct Instant;
+    impl Timer for Instant {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(ready(()))
+        }
+    }
+
+    /// Sleeps that never end.
+    struct Never;
+    impl Timer for Never {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(pending())
+        }
+    }
+
+    /// A Browser whose transport has been polled once, with nothing to send.
+    fn polled(policy: FlushPolicy, timer: Option<Arc<dyn Timer>>) -> (Browser, Arc<Wakes>) {
+        let browser = Browser::new();
+        browser.set_flush_policy(policy);
+        browser.0.lock().timer = timer;
+        let wakes = Arc::new(Wakes::default());
+        assert_eq!(poll(&browser, &wakes), Poll::Pending);
+        (browser, wakes)
+    }
+
+    fn poll(browser: &Browser, wakes: &Arc<Wakes>) -> Poll<Option<String>> {
+        let waker = Waker::from(wakes.clone());
+        browser
+            .clone()
+            .poll_next_unpin(&mut Context::from_waker(&waker))
+    }
+
+    fn woken(wakes: &Wakes) -> usize {
+        wakes.0.load(Ordering::SeqCst)
+    }
+
+    fn write(browser: &Browser, n: usize) {
+        for i in 0..n {
+            browser.run_raw_code(format_args!("f({i})"));
+        }
+    }
+
+    #[test]
+    fn immediate_wakes_for_every_command() {
+        let (browser, wakes) = polled(FlushPolicy::Immediate, None);
+        write(&browser, 3);
+        assert_eq!(woken(&wakes), 3);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+    }
+
+    #[test]
+    fn on_yield_wakes_once_per_batch() {
+        let (browser, wakes) = polled(FlushPolicy::OnYield, None);
+        write(&browser, 3);
+        assert_eq!(woken(&wakes), 1);
+        let Poll::Ready(Some(batch)) = poll(&browser, &wakes) else {
+            panic!("the batch wasn't sent");
+        };
+        assert_eq!(batch, "{ f(0) }\n{ f(1) }\n{ f(2) }\n");
+        assert_eq!(poll(&browser, &wakes), Poll::Pending);
+        write(&browser, 2);
+        assert_eq!(woken(&wakes), 2);
+    }
+
+    #[test]
+    fn size_waits_for_the_size() {
+        let (browser, wakes) = polled(FlushPolicy::Size(100), Some(Arc::new(Never)));
+        write(&browser, 1);
+        // woken once, to poll the delay
+        assert_eq!(woken(&wakes), 1);
+        assert_eq!(poll(&browser, &wakes), Poll::Pending);
+        while browser.0.lock().outgoing_len() < 100 {
+            write(&browser, 1);
+        }
+        assert_eq!(woken(&wakes), 2);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+        assert_eq!(browser.0.lock().outgoing_len(), 0);
+    }
+
+    #[test]
+    fn size_sends_small_batches_after_the_delay() {
+        let (browser, wakes) = polled(FlushPolicy::Size(100), Some(Arc::new(Instant)));
+        write(&browser, 1);
+        assert_eq!(woken(&wakes), 1);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+        // the next batch gets a delay of its own
+        write(&browser, 1);
+        assert_eq!(woken(&wakes), 2);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+    }
+
+    // with `async-io`, there's always a default Timer
+    #[cfg(not(feature = "async-io"))]
+    #[test]
+    fn size_without_a_timer_waits_for_a_flush() {
+        let (browser, wakes) = polled(FlushPolicy::Size(100), None);
+        write(&browser, 1);
+        assert_eq!(woken(&wakes), 0);
+        assert_eq!(poll(&browser, &wakes), Poll::Pending);
+        let mut flush = browser.flush();
+        assert!((&mut flush).now_or_never().is_none());
+        assert_eq!(woken(&wakes), 1);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+        assert_eq!(flush.now_or_never(), Some(()));
+    }
+
+    #[test]
+    fn ready_waits_for_the_high_water_mark() {
+        let (browser, wakes) = polled(FlushPolicy::Size(1000), Some(Arc::new(Never)));
+        browser.set_high_water_mark(20);
+        write(&browser, 2);
+        assert_eq!(browser.ready().now_or_never(), Some(()));
+        write(&browser, 2);
+        let woken_before = woken(&wakes);
+        let mut ready = browser.ready();
+        assert!((&mut ready).now_or_never().is_none());
+        // waiting sends the batch regardless of the policy
+        assert_eq!(woken(&wakes), woken_before + 1);
+        assert!(matches!(poll(&browser, &wakes), Poll::Ready(Some(_))));
+        assert_eq!(ready.now_or_never(), Some(()));
+    }
+
+    #[test]
+    fn dead_browsers_are_always_ready() {
+        let (browser, _) = polled(FlushPolicy::Size(1000), None);
+        browser.set_high_water_mark(0);
+        write(&browser, 1);
+        browser.0.lock().kill(crate::Error::Timeout);
+        assert_eq!(browser.ready().now_or_never(), Some(()));
+        assert_eq!(browser.flush().now_or_never(), Some(()));
+    }
+}
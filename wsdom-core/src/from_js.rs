@@ -0,0 +1,93 @@
+use std::{future::Future, pin::Pin, task::Poll};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    data::from_wire,
+    js::value::JsValue,
+    link::Error,
+    reply::Payload,
+    retrieve::{Projection, RetrieveFuture},
+    timeout::Timeout,
+};
+
+/// Rust types that can be built from a JS value in a single retrieval.
+///
+/// You usually don't implement this trait by hand. Use `#[derive(FromJs)]` instead.
+///
+/// ```rust
+/// # use wsdom::dom::Event;
+/// #[derive(wsdom::FromJs)]
+/// struct InputChanged {
+///     #[js(path = "target.value")]
+///     value: String,
+///     #[js(path = "timeStamp")]
+///     time_stamp: f64,
+/// }
+/// async fn example(event: &Event) {
+///     // only `target.value` and `timeStamp` are sent back, in one roundtrip
+///     let _changed: Option<InputChanged> = event.retrieve_as().await.ok();
+/// }
+/// ```
+pub trait FromJs: Sized {
+    /// What gets sent back over the network.
+    #[doc(hidden)]
+    type Raw: DeserializeOwned;
+    /// Dotted property paths (like `target.value`) whose values, in an array, [FromJs::Raw] deserializes from.
+    ///
+    /// Each name is a property name, never code: ones that aren't identifiers are sent quoted.
+    #[doc(hidden)]
+    const PATHS: &'static [&'static str];
+    /// Build the value from what was sent back.
+    #[doc(hidden)]
+    fn from_raw(raw: Self::Raw) -> Self;
+}
+
+/// A [Future] for retrieving a [FromJs] value from the JS side to the Rust side.
+///
+/// Properties missing along a path are `undefined`, rather than a JS error.
+/// If any property doesn't fit its field (like a missing one for a field that isn't an [Option]),
+/// this fails with [Error::DataDeserialize] (or [Error::MessagePackDeserialize] for binary replies),
+/// and the Browser carries on.
+/// Otherwise, like [RetrieveFuture], this Future will pend forever if something goes wrong.
+pub struct FromJsFuture<'a, T: FromJs>(RetrieveFuture<'a, Result<T::Raw, Error>>);
+
+impl<'a, T: FromJs> FromJsFuture<'a, T> {
+    /// Turn this into a Future that doesn't borrow the value being retrieved.
+    /// See [RetrieveFuture::into_owned].
+    pub fn into_owned(self) -> FromJsFuture<'static, T> {
+        FromJsFuture(self.0.into_owned())
+    }
+    /// Fail with [Error::Timeout](crate::Error::Timeout) if the value isn't retrieved within `duration`.
+    pub fn timeout(self, duration: std::time::Duration) -> Timeout<Self> {
+        let browser = self.0.browser().clone();
+        Timeout::new(&browser, duration, self)
+    }
+}
+
+impl<'a, T: FromJs> Future for FromJsFuture<'a, T> {
+    type Output = Result<T, Error>;
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        Pin::new(&mut this.0)
+            .poll(cx)
+            .map(|raw| raw.map(T::from_raw))
+    }
+}
+
+impl JsValue {
+    /// Retrieve this value from the JS side as a [FromJs] type.
+    ///
+    /// Only the parts of the value that the [FromJs] type asks for are sent back.
+    pub fn retrieve_as<T: FromJs>(&self) -> FromJsFuture<'_, T> {
+        FromJsFuture(
+            RetrieveFuture::with_decoder(self, decode_raw::<T::Raw>)
+                .with_projection(Projection::Paths(T::PATHS)),
+        )
+    }
+}
+
+/// Decode, leaving errors to the caller to handle, rather than killing the Browser.
+fn decode_raw<R: DeserializeOwned>(payload: Payload<'_>) -> Result<Result<R, Error>, Error> {
+    Ok(from_wire(payload))
+}
@@ -0,0 +1,163 @@
+use serde::Deserialize;
+
+use crate::link::{Browser, Error};
+use crate::protocol::PROTOCOL_VERSION;
+
+/// What the JS client told us about itself when it connected.
+///
+/// Get this from [Browser::client_info].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientInfo {
+    /// The wire protocol version the client speaks.
+    /// Clients that don't speak [PROTOCOL_VERSION](crate::PROTOCOL_VERSION) are rejected.
+    #[serde(rename = "version")]
+    pub protocol_version: u32,
+    /// `navigator.userAgent`
+    #[serde(default)]
+    pub user_agent: String,
+    /// `location.href`
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub viewport: Viewport,
+    /// Optional features the client supports, such as `"bigint"` and `"resume"`.
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+/// Size of the client's window when it connected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Viewport {
+    /// `window.innerWidth`, in CSS pixels.
+    pub width: f64,
+    /// `window.innerHeight`, in CSS pixels.
+    pub height: f64,
+    /// `window.devicePixelRatio`
+    pub device_pixel_ratio: f64,
+}
+
+impl ClientInfo {
+    /// What a client that doesn't introduce itself is taken for: one from before hellos,
+    /// which runs code and has no optional features.
+    pub(crate) fn legacy() -> Self {
+        Self {
+            protocol_version: 0,
+            user_agent: String::new(),
+            url: String::new(),
+            viewport: Viewport::default(),
+            features: vec!["eval".to_owned()],
+        }
+    }
+    /// Whether the client announced support for the given feature.
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+/// The client's first message on every connection, `h:Token:Count:Info`.
+pub(crate) struct Hello<'a> {
+    pub(crate) token: &'a str,
+    pub(crate) count: u64,
+    pub(crate) info: Result<ClientInfo, Error>,
+}
+
+impl<'a> Hello<'a> {
+    /// Parse a hello message, without the `h:` prefix.
+    pub(crate) fn parse(hello: &'a str) -> Self {
+        let mut parts = hello.splitn(3, ':');
+        let token = parts.next().unwrap_or_default();
+        let count = parts.next().and_then(|c| c.parse().ok()).unwrap_or(0);
+        let info = match parts.next().map(serde_json::from_str::<ClientInfo>) {
+            Some(Ok(info)) if info.protocol_version == PROTOCOL_VERSION => Ok(info),
+            Some(Ok(info)) => Err(Error::IncompatibleClient(Some(info.protocol_version))),
+            _ => Err(Error::IncompatibleClient(None)),
+        };
+        Self { token, count, info }
+    }
+}
+
+impl Browser {
+    /// Information the JS client sent about itself when it connected:
+    /// its user agent, URL, viewport, and supported features.
+    ///
+    /// This is `None` until the client's first message arrives.
+    /// Integration libraries like `wsdom-axum` wait for it before running your app,
+    /// so there it is always available.
+    ///
+    /// A client whose first message isn't a hello is from before hellos,
+    /// and is given a [ClientInfo] with protocol version 0, no user agent or URL, and only the `"eval"` feature.
+    ///
+    /// If the client speaks a different protocol version than this library
+    /// (for example, because the browser cached an old copy of the JS client),
+    /// the Browser dies with [Error::IncompatibleClient] instead.
+    ///
+    /// ```rust
+    /// # use wsdom_core::Browser;
+    /// fn example(browser: &Browser) {
+    ///     if let Some(info) = browser.client_info() {
+    ///         println!("{} connected from {}", info.user_agent, info.url);
+    ///         if !info.supports("bigint") {
+    ///             // avoid sending BigInts
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn client_info(&self) -> Option<ClientInfo> {
+        self.0.lock().client_info.clone()
+    }
+    /// Take the client for one from before hellos (see [client_info](Self::client_info))
+    /// if it hasn't introduced itself.
+    ///
+    /// Such clients don't say anything until asked, so integration libraries call this
+    /// when no hello arrives in time, rather than waiting for the client forever.
+    pub fn assume_legacy_client(&self) {
+        let mut link = self.0.lock();
+        if link.client_info.is_none() {
+            link.client_info = Some(ClientInfo::legacy());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use crate::{js_types::*, Browser, CommandFormat, PROTOCOL_VERSION};
+
+    #[test]
+    fn replies_before_a_hello_are_from_a_legacy_client() {
+        let browser = Browser::new();
+        let value: JsNumber = browser.new_value(&1.5);
+        let mut retrieval = std::pin::pin!(value.retrieve_float());
+        assert!(retrieval.as_mut().now_or_never().is_none());
+        browser.receive_incoming_message("1:1.5".to_owned());
+        assert!(browser.take_error().is_none());
+        let info = browser.client_info().unwrap();
+        assert_eq!(info.protocol_version, 0);
+        assert!(info.supports("eval"));
+        assert_eq!(retrieval.now_or_never(), Some(1.5));
+    }
+
+    #[test]
+    fn legacy_clients_are_assumed_only_without_a_hello() {
+        let browser = Browser::new();
+        browser.receive_incoming_message(format!("h::0:{{\"version\":{PROTOCOL_VERSION}}}"));
+        browser.assume_legacy_client();
+        assert_eq!(
+            browser.client_info().unwrap().protocol_version,
+            PROTOCOL_VERSION
+        );
+        // no "eval", so it can't run code
+        assert_eq!(browser.command_format(), CommandFormat::Ops);
+    }
+
+    #[test]
+    fn clients_that_cant_eval_get_ops_only_if_nothing_was_written() {
+        let browser = Browser::new();
+        let _value: JsValue = browser.new_value(&1);
+        browser.receive_incoming_message(format!("h::0:{{\"version\":{PROTOCOL_VERSION}}}"));
+        assert_eq!(browser.command_format(), CommandFormat::Code);
+    }
+}
@@ -0,0 +1,240 @@
+/// A JS identifier, like `addEventListener`. Used for method names.
+///
+/// Names are put into JS code as-is, so they are checked to be made of only
+/// ASCII letters, digits, `_` and `$` (and not start with a digit).
+/// A string that passes can't inject code.
+///
+/// Make one with [js_ident!](crate::js_ident) for names known at compile time,
+/// or with [JsIdent::new] otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JsIdent<'a>(&'a str);
+
+/// A dotted path of JS identifiers, like `Intl.NumberFormat`. Used for global functions and classes.
+///
+/// Like [JsIdent], but the parts are separated by `.`, and the first part can't be a keyword
+/// (so `new.target` or `delete` are rejected).
+///
+/// Make one with [js_path!](crate::js_path) for paths known at compile time,
+/// or with [JsPath::new] otherwise.
+///
+/// ```rust
+/// # use wsdom_core::JsPath;
+/// assert!(JsPath::new("Intl.NumberFormat").is_some());
+/// assert!(JsPath::new("alert(document.cookie)").is_none());
+/// assert!(JsPath::new("Math..max").is_none());
+/// assert!(JsPath::new("delete").is_none());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JsPath<'a>(&'a str);
+
+impl<'a> JsIdent<'a> {
+    /// Check that the name is an identifier.
+    pub const fn new(name: &'a str) -> Option<Self> {
+        let bytes = name.as_bytes();
+        if is_ident(bytes, 0, bytes.len()) {
+            Some(Self(name))
+        } else {
+            None
+        }
+    }
+    /// Like [new](JsIdent::new), but panics if the name is not an identifier.
+    ///
+    /// In a const context, such as in [js_ident!](crate::js_ident), the panic is a compile error.
+    pub const fn from_static(name: &'static str) -> JsIdent<'static> {
+        match JsIdent::new(name) {
+            Some(ident) => ident,
+            None => panic!("not a valid JS identifier"),
+        }
+    }
+    pub const fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl<'a> JsPath<'a> {
+    /// Check that the path is made of identifiers separated by `.`.
+    pub const fn new(path: &'a str) -> Option<Self> {
+        if is_path(path.as_bytes()) {
+            Some(Self(path))
+        } else {
+            None
+        }
+    }
+    /// Like [new](JsPath::new), but panics if the path is not valid.
+    ///
+    /// In a const context, such as in [js_path!](crate::js_path), the panic is a compile error.
+    pub const fn from_static(path: &'static str) -> JsPath<'static> {
+        match JsPath::new(path) {
+            Some(path) => path,
+            None => panic!("not a valid JS path"),
+        }
+    }
+    pub const fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl<'a> From<JsIdent<'a>> for JsPath<'a> {
+    fn from(ident: JsIdent<'a>) -> Self {
+        Self(ident.0)
+    }
+}
+
+impl std::fmt::Display for JsIdent<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::fmt::Display for JsPath<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Make a [JsPath], checked at compile time.
+///
+/// ```rust
+/// # use wsdom_core::{js_path, Browser};
+/// fn example(browser: &Browser) {
+///     let _formatter = browser.call_constructor(js_path!("Intl.NumberFormat"), [], false);
+/// }
+/// ```
+///
+/// Invalid paths don't compile.
+///
+/// ```compile_fail
+/// # use wsdom_core::js_path;
+/// let _ = js_path!("alert(1);Math.max");
+/// ```
+#[macro_export]
+macro_rules! js_path {
+    ($path:literal) => {{
+        const PATH: $crate::JsPath<'static> = $crate::JsPath::from_static($path);
+        PATH
+    }};
+}
+
+/// Make a [JsIdent], checked at compile time.
+///
+/// ```rust
+/// # use wsdom_core::{js_ident, js_types::JsObject};
+/// fn example(console: &JsObject) {
+///     console.js_call_method(js_ident!("log"), [&"hello" as &_], false);
+/// }
+/// ```
+#[macro_export]
+macro_rules! js_ident {
+    ($name:literal) => {{
+        const IDENT: $crate::JsIdent<'static> = $crate::JsIdent::from_static($name);
+        IDENT
+    }};
+}
+
+/// Words that would change the meaning of the code if a path started with them.
+const KEYWORDS: &[&str] = &[
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+const fn is_path(bytes: &[u8]) -> bool {
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() && bytes[i] != b'.' {
+        i += 1;
+    }
+    if is_keyword(bytes, i) {
+        return false;
+    }
+    i = 0;
+    loop {
+        if i == bytes.len() || bytes[i] == b'.' {
+            if !is_ident(bytes, start, i) {
+                return false;
+            }
+            if i == bytes.len() {
+                return true;
+            }
+            start = i + 1;
+        }
+        i += 1;
+    }
+}
+
+/// Whether the name can be written after a `.`, like `value` or `class`.
+pub(crate) fn is_property_name(name: &str) -> bool {
+    is_ident(name.as_bytes(), 0, name.len())
+}
+
+/// Whether `bytes[start..end]` is an identifier.
+const fn is_ident(bytes: &[u8], start: usize, end: usize) -> bool {
+    if start >= end || bytes[start].is_ascii_digit() {
+        return false;
+    }
+    let mut i = start;
+    while i < end {
+        let c = bytes[i];
+        if !(c.is_ascii_alphanumeric() || c == b'_' || c == b'$') {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Whether `bytes[..end]` is a keyword.
+const fn is_keyword(bytes: &[u8], end: usize) -> bool {
+    let mut k = 0;
+    while k < KEYWORDS.len() {
+        let keyword = KEYWORDS[k].as_bytes();
+        if keyword.len() == end {
+            let mut i = 0;
+            while i < end && keyword[i] == bytes[i] {
+                i += 1;
+            }
+            if i == end {
+                return true;
+            }
+        }
+        k += 1;
+    }
+    false
+}
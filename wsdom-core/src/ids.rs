@@ -0,0 +1,77 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+use crate::link::BrowserInternal;
+
+/// The largest integer JS numbers can hold exactly (`Number.MAX_SAFE_INTEGER`).
+///
+/// Ids never go above it, so the client can't confuse them.
+pub(crate) const MAX_ID: u64 = (1 << 53) - 1;
+
+/// Allocates ids for value slots and for retrievals, which are separate.
+///
+/// Slot ids are reused once the slot is freed, smallest first, so that they stay short
+/// (and below [MAX_ID], as there can't be that many values alive),
+/// except retired ones, which something on the client may still write into after they're deleted.
+/// Retrieval ids count up, wrapping around at [MAX_ID].
+#[derive(Debug)]
+pub(crate) struct Ids {
+    next_slot: u64,
+    free_slots: BinaryHeap<Reverse<u64>>,
+    retired_slots: HashSet<u64>,
+    last_retrieval: u64,
+}
+
+impl Default for Ids {
+    fn default() -> Self {
+        Self {
+            // 0 is never a slot; see `lazy`
+            next_slot: 1,
+            free_slots: BinaryHeap::new(),
+            retired_slots: HashSet::new(),
+            // 0 is never a retrieval; see `RetrieveFuture`
+            last_retrieval: 0,
+        }
+    }
+}
+
+impl BrowserInternal {
+    /// An id for a new value's slot.
+    pub(crate) fn new_slot_id(&mut self) -> u64 {
+        let ids = &mut self.ids;
+        match ids.free_slots.pop() {
+            Some(Reverse(id)) => id,
+            None => {
+                let id = ids.next_slot;
+                ids.next_slot += 1;
+                id
+            }
+        }
+    }
+    /// Let the slot be reused, once nothing sent later can free it.
+    pub(crate) fn free_slot_id(&mut self, id: u64) {
+        if !self.ids.retired_slots.remove(&id) {
+            self.ids.free_slots.push(Reverse(id));
+        }
+    }
+    /// Never reuse the slot once it's freed.
+    pub(crate) fn retire_slot_id(&mut self, id: u64) {
+        self.ids.retired_slots.insert(id);
+    }
+    /// An id for a retrieval or a [Callback](crate::callback::Callback), not used by any pending one.
+    pub(crate) fn new_retrieval_id(&mut self) -> u64 {
+        let ids = &mut self.ids;
+        loop {
+            ids.last_retrieval = if ids.last_retrieval >= MAX_ID {
+                1
+            } else {
+                ids.last_retrieval + 1
+            };
+            if !self.retrievals.contains_key(&ids.last_retrieval) {
+                return ids.last_retrieval;
+            }
+        }
+    }
+}
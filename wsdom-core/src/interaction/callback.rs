@@ -22,13 +22,16 @@ async fn example(browser: &Browser, button: wsdom::dom::HTMLButtonElement) {
 ```
 */
 
-use std::{fmt::Write, marker::PhantomData, pin::Pin, task::Poll};
+use std::{future::Future, marker::PhantomData, pin::Pin, task::Poll, time::Duration};
 
 use crate::{
+    command::{Call, CallbackFunction, Callee, Command},
     js::value::JsValue,
     js_cast::JsCast,
     link::{Browser, RetrievalState},
-    protocol::{DEL, GET, REP, SET},
+    reply::Reply,
+    serialize::SerdeToJs,
+    timeout::Timeout,
 };
 
 /// Listens for JavaScript callbacks.
@@ -53,8 +56,11 @@ impl<E: JsCast> futures_core::Stream for Callback<E> {
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
-        let mut link = this.browser.0.lock().unwrap();
+        let mut link = this.browser.0.lock();
         let ret_id = this.ret_id;
+        if !link.retrievals.contains_key(&ret_id) && !link.allows_retrieval() {
+            return Poll::Pending;
+        }
         match link.retrievals.entry(ret_id) {
             std::collections::hash_map::Entry::Occupied(mut occ) => {
                 let state = occ.get_mut();
@@ -66,40 +72,66 @@ impl<E: JsCast> futures_core::Stream for Callback<E> {
 
                 if state.times > this.consumed {
                     this.consumed += 1;
-                    let val_id = link.get_new_id();
-                    let arr_id = this.arr_id;
-                    writeln!(
-                        link.raw_commands_buf(),
-                        "{SET}({val_id}, {GET}({arr_id}).shift());"
-                    )
-                    .unwrap();
+                    let val_id = link.new_slot_id();
+                    let shift = Call {
+                        callee: Callee::Method(this.arr_id, "shift"),
+                        args: Vec::new(),
+                        spread: false,
+                    };
+                    link.push_command(Command::Set(val_id, &shift));
                     link.wake_outgoing_lazy();
                     Poll::Ready(Some(JsCast::unchecked_from_js(JsValue {
                         id: val_id,
                         browser: this.browser.to_owned(),
                     })))
                 } else {
+                    // the client can't call us back until it has everything
+                    link.force_flush();
                     Poll::Pending
                 }
             }
             std::collections::hash_map::Entry::Vacant(vac) => {
                 vac.insert(RetrievalState {
                     waker: cx.waker().to_owned(),
-                    last_value: String::new(),
+                    last_value: Reply::default(),
                     times: 0,
                 });
+                link.force_flush();
                 Poll::Pending
             }
         }
     }
 }
+impl<E: JsCast> Callback<E> {
+    /// Wait for the next callback event,
+    /// failing with [Error::Timeout](crate::Error::Timeout) if there is none within `duration`.
+    ///
+    /// See the [timeout module](crate::timeout) for how to set up the timer.
+    pub fn next_timeout(&mut self, duration: Duration) -> Timeout<CallbackNext<'_, E>> {
+        let browser = self.browser.clone();
+        Timeout::new(&browser, duration, CallbackNext(self))
+    }
+}
+
+/// Future for the next event of a [Callback]. See [Callback::next_timeout].
+pub struct CallbackNext<'a, E>(&'a mut Callback<E>);
+
+impl<'a, E: JsCast> Future for CallbackNext<'a, E> {
+    type Output = Option<E>;
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        futures_core::Stream::poll_next(Pin::new(&mut *self.get_mut().0), cx)
+    }
+}
+
 impl<E> Drop for Callback<E> {
     fn drop(&mut self) {
-        let mut link = self.browser.0.lock().unwrap();
+        let mut link = self.browser.0.lock();
         let ret_id = self.ret_id;
         link.retrievals.remove(&ret_id);
-        let arr_id = self.arr_id;
-        writeln!(link.raw_commands_buf(), "{DEL}({arr_id});").unwrap();
+        // the JS function may still be called and push into the array's slot
+        link.retire_slot_id(self.arr_id);
+        link.push_command(Command::Delete(self.arr_id));
+        link.wake_outgoing_lazy();
     }
 }
 
@@ -108,16 +140,23 @@ impl<E> Drop for Callback<E> {
 /// The returned Callback object is a stream. Every time the returned function is called,
 /// the stream will yield the call argument as value.
 pub fn new_callback<E>(browser: &Browser) -> (Callback<E>, JsValue) {
-    let mut link = browser.0.lock().unwrap();
-    let arr_id = link.get_new_id();
-    let ret_id = link.get_new_id();
-    let func_id = link.get_new_id();
+    let mut link = browser.0.lock();
+    let arr_id = link.new_slot_id();
+    let ret_id = link.new_retrieval_id();
+    let func_id = link.new_slot_id();
     let func = JsValue {
         browser: browser.to_owned(),
         id: func_id,
     };
-    writeln!(link.raw_commands_buf(),
-"{SET}({arr_id}, []); {SET}({func_id}, function(e) {{ {GET}({arr_id}).push(e); {REP}({ret_id}, 0) }});").unwrap();
+    let empty: [u8; 0] = [];
+    link.push_command(Command::Set(arr_id, &SerdeToJs(&empty)));
+    link.push_command(Command::Set(
+        func_id,
+        &CallbackFunction {
+            array: arr_id,
+            ret: ret_id,
+        },
+    ));
     link.wake_outgoing_lazy();
     let callback = Callback {
         browser: browser.to_owned(),
@@ -128,3 +167,19 @@ pub fn new_callback<E>(browser: &Browser) -> (Callback<E>, JsValue) {
     };
     (callback, func)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::new_callback;
+    use crate::{js_types::JsValue, Browser};
+
+    #[test]
+    fn dropped_callback_array_is_not_reused() {
+        let browser = Browser::new();
+        let (callback, _func) = new_callback::<JsValue>(&browser);
+        let arr_id = callback.arr_id;
+        drop(callback);
+        let values: Vec<JsValue> = (0..3).map(|i| browser.new_value(&i)).collect();
+        assert!(values.iter().all(|value| value.id != arr_id));
+    }
+}
@@ -0,0 +1,164 @@
+/*!
+Interning strings that are sent again and again, like `"innerText"` or `"appendChild"`.
+
+The second time a string is written, it's given a token,
+with `_w.i(token, "string")` (which also evaluates to the string).
+After that, `_w.I(token)` is written instead of the string.
+Strings that are long, or whose token wouldn't be shorter, are always written as they are.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+/// Longer strings are most likely content, not names.
+const MAX_LEN: usize = 64;
+/// How many strings a session may intern, and remember having seen once.
+const MAX_STRINGS: usize = 4096;
+
+/// The strings interned on the client, per session.
+#[derive(Debug, Default)]
+pub(crate) struct Strings {
+    /// Tokens by JSON string literal.
+    tokens: HashMap<String, u64>,
+    /// Strings written once, to be interned if written again.
+    seen: HashSet<String>,
+    /// Strings given a token in the command being written.
+    defined: Vec<String>,
+}
+
+/// How to write a string.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Interned {
+    Literal,
+    Define(u64),
+    Reference(u64),
+}
+
+impl Strings {
+    /// How to write the JSON string literal, given whether a reference with the token would be shorter.
+    pub(crate) fn intern(&mut self, json: &str, worth: impl Fn(u64) -> bool) -> Interned {
+        if let Some(token) = self.tokens.get(json) {
+            return if worth(*token) {
+                Interned::Reference(*token)
+            } else {
+                Interned::Literal
+            };
+        }
+        let token = self.tokens.len() as u64;
+        if json.len() > MAX_LEN || self.tokens.len() >= MAX_STRINGS || !worth(token) {
+            return Interned::Literal;
+        }
+        if !self.seen.remove(json) {
+            if self.seen.len() >= MAX_STRINGS {
+                self.seen.clear();
+            }
+            self.seen.insert(json.to_owned());
+            return Interned::Literal;
+        }
+        self.tokens.insert(json.to_owned(), token);
+        self.defined.push(json.to_owned());
+        Interned::Define(token)
+    }
+    /// Keep the tokens given in the command just written. Returns whether there were any.
+    pub(crate) fn commit(&mut self) -> bool {
+        let defined = !self.defined.is_empty();
+        self.defined.clear();
+        defined
+    }
+    /// Forget the tokens given in a command that wasn't sent after all.
+    pub(crate) fn rollback(&mut self) {
+        for json in self.defined.drain(..) {
+            self.tokens.remove(&json);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{js_types::JsValue, Browser, JsPath, Quotas, UseInJsCode};
+
+    fn buf(browser: &Browser) -> String {
+        browser.0.lock().commands_buf.clone()
+    }
+
+    /// Kept by the caller, so that the commands aren't rewritten.
+    fn log(browser: &Browser, text: &str) -> JsValue {
+        browser.call_function(
+            JsPath::from_static("log"),
+            [&text as &dyn UseInJsCode],
+            false,
+        )
+    }
+
+    #[test]
+    fn defined_on_the_second_write_and_referenced_after() {
+        let browser = Browser::new();
+        let _values: Vec<_> = (0..3).map(|_| log(&browser, "innerText")).collect();
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,log(\"innerText\",));\n_w.s(2,log(_w.i(0,\"innerText\"),));\n_w.s(3,log(_w.I(0),));\n"
+        );
+    }
+
+    #[test]
+    fn short_and_long_strings_are_not_interned() {
+        let browser = Browser::new();
+        let long = "x".repeat(65);
+        let _values: Vec<_> = (0..3)
+            .flat_map(|_| [log(&browser, "a"), log(&browser, &long)])
+            .collect();
+        assert!(!buf(&browser).contains("_w.i("));
+    }
+
+    #[test]
+    fn refused_commands_roll_back_their_tokens() {
+        let browser = Browser::new();
+        let mut values = vec![log(&browser, "innerText")];
+        let len = buf(&browser).len();
+        browser.set_quotas(Quotas {
+            max_outgoing_bytes: Some(len),
+            close_on_breach: false,
+            ..Default::default()
+        });
+        // would define the token
+        values.push(log(&browser, "innerText"));
+        assert_eq!(buf(&browser).len(), len);
+        browser.set_quotas(Quotas::default());
+        // seen once again, then defined, then referenced
+        values.extend((0..3).map(|_| log(&browser, "innerText")));
+        assert_eq!(
+            &buf(&browser)[len..],
+            "_w.s(3,log(\"innerText\",));\n_w.s(4,log(_w.i(0,\"innerText\"),));\n_w.s(5,log(_w.I(0),));\n"
+        );
+    }
+
+    #[test]
+    fn tokens_are_kept_after_a_refused_command_that_references_them() {
+        let browser = Browser::new();
+        let mut values = vec![log(&browser, "innerText"), log(&browser, "innerText")];
+        let len = buf(&browser).len();
+        browser.set_quotas(Quotas {
+            max_outgoing_bytes: Some(len),
+            close_on_breach: false,
+            ..Default::default()
+        });
+        values.push(log(&browser, "innerText"));
+        browser.set_quotas(Quotas::default());
+        values.push(log(&browser, "innerText"));
+        assert_eq!(&buf(&browser)[len..], "_w.s(4,log(_w.I(0),));\n");
+    }
+
+    #[test]
+    fn defining_values_are_not_inlined() {
+        let browser = Browser::new();
+        let _seen = log(&browser, "innerText");
+        let defining = log(&browser, "innerText");
+        let len = buf(&browser).len();
+        let _user = browser.call_function(
+            JsPath::from_static("use"),
+            [&defining as &dyn UseInJsCode],
+            false,
+        );
+        drop(defining);
+        assert_eq!(&buf(&browser)[len..], "_w.s(3,use(_w.g(2),));\n_w.d(2);\n");
+    }
+}
@@ -1,4 +1,4 @@
-use crate::serialize::{ToJs, UseInJsCode};
+use crate::serialize::{JsWriter, ToJs, UseInJsCode};
 
 pub struct UpcastWorkaround<'a, T: ?Sized, JsType> {
     ty: &'a T,
@@ -18,7 +18,7 @@ impl<'a, T: ToJs<JsType> + ?Sized, JsType> UpcastWorkaround<'a, T, JsType> {
 }
 
 impl<'a, T: ToJs<JsType> + ?Sized, JsType> UseInJsCode for UpcastWorkaround<'a, T, JsType> {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.ty.serialize_to(buf)
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        ToJs::<JsType>::serialize_as(self.ty, buf)
     }
 }
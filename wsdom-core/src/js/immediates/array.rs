@@ -1,18 +1,163 @@
-use std::fmt::Write;
+use std::{cell::Cell, marker::PhantomData};
 
-use crate::serialize::UseInJsCode;
+use crate::{
+    js::{object::JsObject, value::JsValue},
+    serialize::{JsWriter, ToJs, UseInJsCode},
+};
 
 impl<'a, U, const N: usize> UseInJsCode for [&'a U; N]
 where
     U: UseInJsCode,
 {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        buf.write_char('[')?;
-        for item in self.iter() {
-            item.serialize_to(buf)?;
-            buf.write_char(',')?;
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_array(self)
+    }
+}
+
+impl<U: UseInJsCode> UseInJsCode for [U] {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_array(self)
+    }
+}
+
+impl<U: UseInJsCode> UseInJsCode for Vec<U> {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_array(self)
+    }
+}
+
+/// Call `$impl!([item types] collection; items)` for each collection that is written as a JS array,
+/// so that the `ToJs<Array<T>>` impls in wsdom-javascript cover the same ones as ours.
+///
+/// `items` is `iter` if the items are got with `.iter()`, or else a pattern of the items, like `(a, b,)`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! for_each_array {
+    ($impl:ident) => {
+        $impl!([U] [U]; iter);
+        $impl!([U] &[U]; iter);
+        $impl!([U] Vec<U>; iter);
+        $impl!([U] &Vec<U>; iter);
+        $impl!([A] (A,); (a,));
+        $impl!([A, B] (A, B); (a, b,));
+        $impl!([A, B, C] (A, B, C); (a, b, c,));
+        $impl!([A, B, C, D] (A, B, C, D); (a, b, c, d,));
+        $impl!([A, B, C, D, E] (A, B, C, D, E); (a, b, c, d, e,));
+        $impl!([A, B, C, D, E, F] (A, B, C, D, E, F); (a, b, c, d, e, f,));
+        $impl!([A, B, C, D, E, F, G] (A, B, C, D, E, F, G); (a, b, c, d, e, f, g,));
+        $impl!([A, B, C, D, E, F, G, H] (A, B, C, D, E, F, G, H); (a, b, c, d, e, f, g, h,));
+        $impl!([A, B, C, D, E, F, G, H, I] (A, B, C, D, E, F, G, H, I); (a, b, c, d, e, f, g, h, i,));
+        $impl!([A, B, C, D, E, F, G, H, I, J] (A, B, C, D, E, F, G, H, I, J); (a, b, c, d, e, f, g, h, i, j,));
+        $impl!([A, B, C, D, E, F, G, H, I, J, K] (A, B, C, D, E, F, G, H, I, J, K); (a, b, c, d, e, f, g, h, i, j, k,));
+        $impl!([A, B, C, D, E, F, G, H, I, J, K, L] (A, B, C, D, E, F, G, H, I, J, K, L); (a, b, c, d, e, f, g, h, i, j, k, l,));
+    };
+}
+
+macro_rules! impl_to_js {
+    ([$($item:ident),*] $ty:ty; $($items:tt)*) => {
+        impl<$($item: UseInJsCode),*> ToJs<JsValue> for $ty {}
+        impl<$($item: UseInJsCode),*> ToJs<JsObject> for $ty {}
+    };
+}
+
+crate::for_each_array!(impl_to_js);
+
+macro_rules! impl_tuple {
+    ($($ty:ident),*) => {
+        impl<$($ty: UseInJsCode),*> UseInJsCode for ($($ty,)*) {
+            #[allow(non_snake_case)]
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                let ($($ty,)*) = self;
+                buf.write_array([$($ty as &dyn UseInJsCode,)*])
+            }
         }
-        buf.write_char(']')?;
-        Ok(())
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+impl_tuple!(A, B, C, D, E, F, G, H, I);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// An array created from an iterator. See [array_from_iter].
+pub struct IterImmediate<I>(Cell<Option<I>>);
+
+impl<I> UseInJsCode for IterImmediate<I>
+where
+    I: IntoIterator,
+    I::Item: UseInJsCode,
+{
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        // used up by an earlier command
+        let iter = self.0.take().ok_or(std::fmt::Error)?;
+        buf.write_array(iter)
     }
 }
+
+impl<I> IterImmediate<I>
+where
+    I: IntoIterator,
+    I::Item: UseInJsCode,
+{
+    /// Write the array as an `Array<T>`, for the impls in wsdom-javascript.
+    #[doc(hidden)]
+    pub fn serialize_items_as<T>(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result
+    where
+        I::Item: ToJs<T>,
+    {
+        /// An item written as a `T`.
+        struct Item<V, T>(V, PhantomData<T>);
+        impl<V: ToJs<T>, T> UseInJsCode for Item<V, T> {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                self.0.serialize_as(buf)
+            }
+        }
+        let iter = self.0.take().ok_or(std::fmt::Error)?;
+        buf.write_array(iter.into_iter().map(|item| Item::<_, T>(item, PhantomData)))
+    }
+}
+
+impl<I> ToJs<JsValue> for IterImmediate<I>
+where
+    I: IntoIterator,
+    I::Item: UseInJsCode,
+{
+}
+impl<I> ToJs<JsObject> for IterImmediate<I>
+where
+    I: IntoIterator,
+    I::Item: UseInJsCode,
+{
+}
+
+/// Serialize the items of an iterator into a JS array, without collecting them into a `Vec` first.
+///
+/// The return value implements `ToJs<Array<T>>` if the items implement `ToJs<T>`.
+///
+/// The iterator is used up when the array is written,
+/// so using the return value in a second call fails like any command that can't be written:
+/// the Browser dies with [Error::CommandSerialize](crate::Error::CommandSerialize).
+///
+/// ```rust
+/// # use wsdom::Browser;
+/// fn example(browser: &Browser, names: &[String]) {
+///     let upper = names.iter().map(|name| name.to_uppercase());
+///     let _arr: wsdom::js::Array<wsdom::js_types::JsString> =
+///         browser.new_value(&wsdom::array_from_iter(upper));
+/// }
+/// ```
+pub fn array_from_iter<I>(iter: I) -> IterImmediate<I>
+where
+    I: IntoIterator,
+    I::Item: UseInJsCode,
+{
+    IterImmediate(Cell::new(Some(iter)))
+}
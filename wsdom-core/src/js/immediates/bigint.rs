@@ -0,0 +1,48 @@
+use crate::{
+    js::{primitives::JsBigInt, value::JsValue},
+    serialize::{JsWriter, ToJs, UseInJsCode},
+};
+
+/// An integer serialized as a JS BigInt literal (`123n`). See [bigint].
+pub struct BigIntImmediate<T>(T);
+
+impl<T: std::fmt::Display> UseInJsCode for BigIntImmediate<T> {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.bigint(&self.0);
+        Ok(())
+    }
+}
+
+macro_rules! impl_bigint {
+    ($($name:ty),*) => {
+        $(
+            impl ToJs<JsBigInt> for BigIntImmediate<$name> {}
+            impl ToJs<JsBigInt> for &BigIntImmediate<$name> {}
+            impl ToJs<JsValue> for BigIntImmediate<$name> {}
+            impl ToJs<JsValue> for &BigIntImmediate<$name> {}
+        )*
+    };
+}
+
+impl_bigint!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// Pass an integer as a JS BigInt instead of a number.
+///
+/// Integers are normally serialized as JS numbers, which lose precision above 2^53.
+/// The return value implements `ToJs<JsBigInt>`.
+/// (`i64` and `u64` are serialized as BigInts where a [JsBigInt] is expected,
+/// and `i128` and `u128` always are, so they can be used directly there.)
+///
+/// ```rust
+/// # use wsdom::Browser;
+/// # use wsdom::js_types::JsBigInt;
+/// fn example(browser: &Browser) {
+///     let _big: JsBigInt = browser.new_value(&wsdom::bigint(u64::MAX));
+/// }
+/// ```
+pub fn bigint<T>(value: T) -> BigIntImmediate<T>
+where
+    BigIntImmediate<T>: ToJs<JsBigInt>,
+{
+    BigIntImmediate(value)
+}
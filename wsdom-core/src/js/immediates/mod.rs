@@ -1,5 +1,9 @@
 mod array;
+mod bigint;
 mod nullable;
+mod object;
 mod primitives;
 
+pub use array::{array_from_iter, IterImmediate};
+pub use bigint::{bigint, BigIntImmediate};
 pub use nullable::{null, undefined};
@@ -1,29 +1,33 @@
 use crate::{
     js::nullable::{JsNullable, JsNullish},
     js_cast::JsCast,
-    serialize::{RawCodeImmediate, ToJs, UseInJsCode},
+    serialize::{JsWriter, RawCodeImmediate, ToJs, UseInJsCode},
 };
 
 pub struct NullImmediate;
 
 impl UseInJsCode for NullImmediate {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
         RawCodeImmediate("null").serialize_to(buf)
     }
 }
 
 impl ToJs<JsNullish> for NullImmediate {}
 impl<T> ToJs<JsNullable<T>> for NullImmediate {}
+impl ToJs<JsNullish> for &NullImmediate {}
+impl<T> ToJs<JsNullable<T>> for &NullImmediate {}
 
 pub struct UndefinedImmediate;
 impl UseInJsCode for UndefinedImmediate {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
         RawCodeImmediate("undefined").serialize_to(buf)
     }
 }
 
 impl ToJs<JsNullish> for UndefinedImmediate {}
 impl<T> ToJs<JsNullable<T>> for UndefinedImmediate {}
+impl ToJs<JsNullish> for &UndefinedImmediate {}
+impl<T> ToJs<JsNullable<T>> for &UndefinedImmediate {}
 
 /// The return value implements `ToJs<JsNullish>` and `ToJs<JsNullable<T>>`.
 pub const fn null() -> NullImmediate {
@@ -35,8 +39,8 @@ pub const fn undefined() -> UndefinedImmediate {
     UndefinedImmediate
 }
 
-impl<'a, T: UseInJsCode> UseInJsCode for Option<&'a T> {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T: UseInJsCode> UseInJsCode for Option<T> {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
         match self {
             Some(t) => t.serialize_to(buf),
             None => NullImmediate.serialize_to(buf),
@@ -44,9 +48,15 @@ impl<'a, T: UseInJsCode> UseInJsCode for Option<&'a T> {
     }
 }
 
-impl<'a, T, U> ToJs<JsNullable<T>> for Option<&'a U>
+impl<T, U> ToJs<JsNullable<T>> for Option<U>
 where
     T: JsCast,
-    U: UseInJsCode + ToJs<T>,
+    U: ToJs<T>,
 {
+    fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        match self {
+            Some(t) => t.serialize_as(buf),
+            None => NullImmediate.serialize_to(buf),
+        }
+    }
 }
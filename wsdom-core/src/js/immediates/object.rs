@@ -0,0 +1,24 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    js::{object::JsObject, value::JsValue},
+    serialize::{JsWriter, ToJs, UseInJsCode},
+};
+
+macro_rules! impl_map {
+    ($($extra:ident)?; $map:ty) => {
+        /// Serialized to a JS object. The keys become property names.
+        impl<K: AsRef<str>, V: UseInJsCode, $($extra)?> UseInJsCode for $map {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                buf.write_object(self)
+            }
+        }
+        impl<K: AsRef<str>, V: UseInJsCode, $($extra)?> ToJs<JsObject> for $map {}
+        impl<K: AsRef<str>, V: UseInJsCode, $($extra)?> ToJs<JsValue> for $map {}
+        impl<K: AsRef<str>, V: UseInJsCode, $($extra)?> ToJs<JsObject> for &$map {}
+        impl<K: AsRef<str>, V: UseInJsCode, $($extra)?> ToJs<JsValue> for &$map {}
+    };
+}
+
+impl_map!(S; HashMap<K, V, S>);
+impl_map!(; BTreeMap<K, V>);
@@ -1,47 +1,104 @@
+use std::borrow::Cow;
+
 use crate::{
     js::primitives::*,
     js::value::JsValue,
-    serialize::{SerdeToJs, ToJs, UseInJsCode},
+    serialize::{JsWriter, SerdeToJs, ToJs, UseInJsCode},
 };
 
 macro_rules! impl_use_in_js {
-    ($name:ident) => {
+    ($name:ty) => {
         impl UseInJsCode for $name {
-            fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
                 SerdeToJs(self).serialize_to(buf)
             }
         }
-        impl ToJs<JsValue> for $name {}
+        impl_to_js!(JsValue; $name);
+    };
+}
+
+macro_rules! impl_to_js {
+    ($js:ty; $($name:ty),*) => {
+        $(
+            impl ToJs<$js> for $name {}
+            impl ToJs<$js> for &$name {}
+        )*
     };
 }
 
-impl ToJs<JsBoolean> for bool {}
+impl_to_js!(JsBoolean; bool);
 impl_use_in_js!(bool);
-impl ToJs<JsString> for str {}
+impl_to_js!(JsString; str, String, Cow<'_, str>);
 impl_use_in_js!(str);
-impl<'a> UseInJsCode for &'a str {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        SerdeToJs(self).serialize_to(buf)
-    }
+impl_use_in_js!(String);
+impl_use_in_js!(Cow<'_, str>);
+// so that `&"hello"` can be used where a `&str` could
+impl ToJs<JsString> for &&str {}
+impl ToJs<JsValue> for &&str {}
+
+impl_to_js!(JsNumber; i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+// JSON would turn non-finite numbers into `null`
+macro_rules! impl_use_in_js_float {
+    ($name:ty) => {
+        impl UseInJsCode for $name {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                let special = if self.is_nan() {
+                    "NaN"
+                } else if self.is_infinite() {
+                    if *self > 0.0 { "Infinity" } else { "-Infinity" }
+                } else {
+                    return SerdeToJs(self).serialize_to(buf);
+                };
+                buf.special_number(special);
+                Ok(())
+            }
+        }
+        impl_to_js!(JsValue; $name);
+    };
+}
+
+// JSON numbers would lose precision, so these become BigInts
+macro_rules! impl_use_in_js_bigint {
+    ($name:ty) => {
+        impl UseInJsCode for $name {
+            fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                buf.bigint(self);
+                Ok(())
+            }
+        }
+        impl_to_js!(JsValue; $name);
+    };
+}
+
+impl_to_js!(JsBigInt; i128, u128);
+impl_use_in_js_bigint!(i128);
+impl_use_in_js_bigint!(u128);
+
+// numbers, unless passed as BigInts
+macro_rules! impl_to_js_bigint {
+    ($($name:ty),*) => {
+        $(
+            impl ToJs<JsBigInt> for $name {
+                fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                    buf.bigint(self);
+                    Ok(())
+                }
+            }
+            impl ToJs<JsBigInt> for &$name {
+                fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                    buf.bigint(self);
+                    Ok(())
+                }
+            }
+        )*
+    };
 }
-impl<'a> ToJs<JsString> for &'a str {}
-impl<'a> ToJs<JsValue> for &'a str {}
-
-impl ToJs<JsNumber> for i8 {}
-impl ToJs<JsNumber> for i16 {}
-impl ToJs<JsNumber> for i32 {}
-impl ToJs<JsNumber> for i64 {}
-impl ToJs<JsNumber> for isize {}
-impl ToJs<JsNumber> for u8 {}
-impl ToJs<JsNumber> for u16 {}
-impl ToJs<JsNumber> for u32 {}
-impl ToJs<JsNumber> for u64 {}
-impl ToJs<JsNumber> for usize {}
-impl ToJs<JsNumber> for f32 {}
-impl ToJs<JsNumber> for f64 {}
-
-impl_use_in_js!(f64);
-impl_use_in_js!(f32);
+
+impl_to_js_bigint!(i64, u64);
+
+impl_use_in_js_float!(f64);
+impl_use_in_js_float!(f32);
 impl_use_in_js!(usize);
 impl_use_in_js!(u64);
 impl_use_in_js!(u32);
@@ -23,7 +23,7 @@ macro_rules! impl_basic {
         }
 
         impl $(<$generic>)? UseInJsCode for $name $(<$generic>)? {
-            fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fn serialize_to(&self, buf: &mut crate::serialize::JsWriter<'_>) -> std::fmt::Result {
                 self.0.serialize_to(buf)
             }
         }
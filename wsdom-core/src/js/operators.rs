@@ -1,62 +1,76 @@
 use super::primitives::{JsBoolean, JsNumber, JsString};
 use super::value::JsValue;
+use crate::command::{Command, Operator};
 use crate::link::Browser;
-use crate::serialize::{UseInJsCode, UseInJsCodeWriter};
+use crate::serialize::UseInJsCode;
 use crate::{JsCast, ToJs};
 
+impl Browser {
+    fn value_from_operator(&self, operator: Operator<'_>) -> JsValue {
+        let mut link = self.0.lock();
+        let out_id = link.new_slot_id();
+        link.push_command(Command::Set(out_id, &operator));
+        link.wake_outgoing_lazy();
+        JsValue {
+            id: out_id,
+            browser: self.to_owned(),
+        }
+    }
+}
+
 #[rustfmt::skip]
 impl Browser {
     /// The JavaScript `+` operator
-    pub fn op_add           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_raw_code(format_args!("(({}) + ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_add           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_operator(Operator::Binary("+", a, b)).unchecked_into()}
     /// The JavaScript `-` operator
-    pub fn op_sub           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) - ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_sub           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("-", a, b)).unchecked_into()}
     /// The JavaScript `*` operator
-    pub fn op_mul           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) * ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_mul           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("*", a, b)).unchecked_into()}
     /// The JavaScript `/` operator
-    pub fn op_div           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) / ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_div           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("/", a, b)).unchecked_into()}
     /// The JavaScript `%` operator
-    pub fn op_remainder     (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) % ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_remainder     (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("%", a, b)).unchecked_into()}
     /// The JavaScript `**` operator
-    pub fn op_exponent      (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) ** ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_exponent      (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("**", a, b)).unchecked_into()}
     /// The JavaScript `&` operator
-    pub fn op_bitwise_and   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) & ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_bitwise_and   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("&", a, b)).unchecked_into()}
     /// The JavaScript `|` operator
-    pub fn op_bitwise_or    (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) | ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_bitwise_or    (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("|", a, b)).unchecked_into()}
     /// The JavaScript `^` operator
-    pub fn op_bitwise_xor   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) ^ ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_bitwise_xor   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("^", a, b)).unchecked_into()}
     /// The JavaScript `<<` operator
-    pub fn op_lshift2       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) << ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_lshift2       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary("<<", a, b)).unchecked_into()}
     /// The JavaScript `>>` operator
-    pub fn op_rshift2       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) >> ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_rshift2       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary(">>", a, b)).unchecked_into()}
     /// The JavaScript `>>>` operator
-    pub fn op_rshift3       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_raw_code(format_args!("(({}) >>> ({}))",   UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_rshift3       (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsNumber   {self.value_from_operator(Operator::Binary(">>>", a, b)).unchecked_into()}
     /// The JavaScript `&&` operator
-    pub fn op_logical_and   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_raw_code(format_args!("(({}) && ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_logical_and   (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_operator(Operator::Binary("&&", a, b)).unchecked_into()}
     /// The JavaScript `||` operator
-    pub fn op_logical_or    (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_raw_code(format_args!("(({}) || ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_logical_or    (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsValue    {self.value_from_operator(Operator::Binary("||", a, b)).unchecked_into()}
     /// The JavaScript `==` operator
-    pub fn op_eq2           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) == ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_eq2           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("==", a, b)).unchecked_into()}
     /// The JavaScript `!=` operator
-    pub fn op_neq1          (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) != ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_neq1          (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("!=", a, b)).unchecked_into()}
     /// The JavaScript `===` operator
-    pub fn op_eq3           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) === ({}))",   UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_eq3           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("===", a, b)).unchecked_into()}
     /// The JavaScript `!==` operator
-    pub fn op_neq2          (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) !== ({}))",   UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_neq2          (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("!==", a, b)).unchecked_into()}
     /// The JavaScript `>` operator
-    pub fn op_gt            (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) > ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_gt            (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary(">", a, b)).unchecked_into()}
     /// The JavaScript `>=` operator
-    pub fn op_gte           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) >= ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_gte           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary(">=", a, b)).unchecked_into()}
     /// The JavaScript `<` operator
-    pub fn op_lt            (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) < ({}))",     UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_lt            (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("<", a, b)).unchecked_into()}
     /// The JavaScript `<=` operator
-    pub fn op_lte           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_raw_code(format_args!("(({}) <= ({}))",    UseInJsCodeWriter(a), UseInJsCodeWriter(b))).unchecked_into()}
+    pub fn op_lte           (&self, a: &dyn UseInJsCode, b: &dyn UseInJsCode) -> JsBoolean  {self.value_from_operator(Operator::Binary("<=", a, b)).unchecked_into()}
 
     /// The JavaScript `-` operator
-    pub fn op_neg           (&self, a: &dyn UseInJsCode) -> JsNumber    {self.value_from_raw_code(format_args!("(-({}))", UseInJsCodeWriter(a))).unchecked_into()}
+    pub fn op_neg           (&self, a: &dyn UseInJsCode) -> JsNumber    {self.value_from_operator(Operator::Unary("-", a)).unchecked_into()}
     /// The JavaScript `~` operator
-    pub fn op_bitwise_not   (&self, a: &dyn UseInJsCode) -> JsNumber    {self.value_from_raw_code(format_args!("(~({}))", UseInJsCodeWriter(a))).unchecked_into()}
+    pub fn op_bitwise_not   (&self, a: &dyn UseInJsCode) -> JsNumber    {self.value_from_operator(Operator::Unary("~", a)).unchecked_into()}
     /// The JavaScript `!` operator
-    pub fn op_logical_not   (&self, a: &dyn UseInJsCode) -> JsBoolean   {self.value_from_raw_code(format_args!("(!({}))", UseInJsCodeWriter(a))).unchecked_into()}
+    pub fn op_logical_not   (&self, a: &dyn UseInJsCode) -> JsBoolean   {self.value_from_operator(Operator::Unary("!", a)).unchecked_into()}
 }
 
 macro_rules! impl_number_op {
@@ -1,5 +1,10 @@
+use std::str::FromStr;
+
+use serde::de::Unexpected;
+
 use crate::{
-    js::value::JsValue, js_cast::JsCast, retrieve::RetrieveFuture, serialize::UseInJsCode,
+    data::from_wire, js::value::JsValue, js_cast::JsCast, link::Error, reply::Payload,
+    retrieve::RetrieveFuture, serialize::UseInJsCode,
 };
 
 include!("impl_basic.rs");
@@ -7,25 +12,169 @@ include!("impl_basic.rs");
 impl_basic!(JsBoolean);
 impl_basic!(JsString);
 impl_basic!(JsNumber);
+impl_basic!(JsBigInt);
 impl_basic!(JsSymbol);
 
 impl JsBoolean {
     pub fn retrieve(&self) -> RetrieveFuture<'_, bool> {
         self.0.retrieve_and_deserialize()
     }
+    pub fn retrieve_owned(self) -> RetrieveFuture<'static, bool> {
+        self.0.retrieve_and_deserialize_owned()
+    }
 }
 
 impl JsString {
     pub fn retrieve(&self) -> RetrieveFuture<'_, String> {
         self.0.retrieve_and_deserialize()
     }
+    pub fn retrieve_owned(self) -> RetrieveFuture<'static, String> {
+        self.0.retrieve_and_deserialize_owned()
+    }
 }
 
 impl JsNumber {
     pub fn retrieve_float(&self) -> RetrieveFuture<'_, f64> {
         self.0.retrieve_and_deserialize()
     }
+    pub fn retrieve_float_owned(self) -> RetrieveFuture<'static, f64> {
+        self.0.retrieve_and_deserialize_owned()
+    }
     pub fn retrieve_int(&self) -> RetrieveFuture<'_, i64> {
         self.0.retrieve_and_deserialize()
     }
+    pub fn retrieve_int_owned(self) -> RetrieveFuture<'static, i64> {
+        self.0.retrieve_and_deserialize_owned()
+    }
+}
+
+impl JsBigInt {
+    /// Retrieve the BigInt in decimal, like [JsData::BigInt](crate::JsData::BigInt).
+    ///
+    /// BigInts can be any size, so this can't fail;
+    /// use [retrieve_i64](JsBigInt::retrieve_i64) and the like for Rust integers.
+    ///
+    /// ```rust
+    /// # use wsdom::js_types::JsBigInt;
+    /// async fn example(id: &JsBigInt) -> String {
+    ///     id.retrieve().await
+    /// }
+    /// ```
+    pub fn retrieve(&self) -> RetrieveFuture<'_, String> {
+        self.0.retrieve_and_deserialize()
+    }
+    pub fn retrieve_owned(self) -> RetrieveFuture<'static, String> {
+        self.0.retrieve_and_deserialize_owned()
+    }
+    /// Retrieve the BigInt as an `i64`, or [Error::DataDeserialize] if it doesn't fit.
+    ///
+    /// ```rust
+    /// # use wsdom::js_types::JsBigInt;
+    /// async fn example(id: &JsBigInt) {
+    ///     match id.retrieve_i64().await {
+    ///         Ok(id) => println!("id is {id}"),
+    ///         Err(_) => println!("id is too big"),
+    ///     }
+    /// }
+    /// ```
+    pub fn retrieve_i64(&self) -> RetrieveFuture<'_, Result<i64, Error>> {
+        RetrieveFuture::with_decoder(&self.0, bigint_from_wire)
+    }
+    /// Retrieve the BigInt as a `u64`, or [Error::DataDeserialize] if it doesn't fit.
+    pub fn retrieve_u64(&self) -> RetrieveFuture<'_, Result<u64, Error>> {
+        RetrieveFuture::with_decoder(&self.0, bigint_from_wire)
+    }
+    /// Retrieve the BigInt as an `i128`, or [Error::DataDeserialize] if it doesn't fit.
+    pub fn retrieve_i128(&self) -> RetrieveFuture<'_, Result<i128, Error>> {
+        RetrieveFuture::with_decoder(&self.0, bigint_from_wire)
+    }
+    /// Retrieve the BigInt as a `u128`, or [Error::DataDeserialize] if it doesn't fit.
+    pub fn retrieve_u128(&self) -> RetrieveFuture<'_, Result<u128, Error>> {
+        RetrieveFuture::with_decoder(&self.0, bigint_from_wire)
+    }
+}
+
+/// Decode a BigInt as an integer type. Not fitting is an error for the caller, not one that kills the Browser.
+fn bigint_from_wire<T: FromStr>(payload: Payload<'_>) -> Result<Result<T, Error>, Error> {
+    let digits: String = from_wire(payload)?;
+    Ok(digits.parse().map_err(|_| {
+        Error::DataDeserialize(serde::de::Error::invalid_value(
+            Unexpected::Other(&format!("BigInt {digits}")),
+            &std::any::type_name::<T>(),
+        ))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{FutureExt, StreamExt};
+
+    use crate::{js_types::*, Browser, Error};
+
+    #[test]
+    fn bigints_of_any_size_round_trip() {
+        let mut browser = Browser::new();
+        let id: JsBigInt = browser.new_value(&u128::MAX);
+        let mut retrieval = std::pin::pin!(id.retrieve());
+        assert!(retrieval.as_mut().now_or_never().is_none());
+        assert_eq!(
+            browser.next().now_or_never().flatten().unwrap(),
+            format!("_w.s(1,{}n);\n_w.r(1,(_w.g(1)));\n", u128::MAX)
+        );
+        browser.receive_incoming_message(format!(r#"1:{{"$w":"b","v":"{}"}}"#, u128::MAX));
+        let retrieved = retrieval.now_or_never().unwrap();
+        assert_eq!(retrieved.parse(), Ok(u128::MAX));
+
+        // larger than any Rust integer, as it can be if computed on the JS side
+        let huge = "9".repeat(60);
+        let mut retrieval = std::pin::pin!(id.retrieve());
+        assert!(retrieval.as_mut().now_or_never().is_none());
+        browser.receive_incoming_message(format!(r#"2:{{"$w":"b","v":"{huge}"}}"#));
+        assert_eq!(retrieval.now_or_never(), Some(huge));
+        assert!(browser.take_error().is_none());
+    }
+
+    #[test]
+    fn wide_integers_are_bigints_where_bigints_are_expected() {
+        let browser = Browser::new();
+        let _big: JsBigInt = browser.new_value(&i64::MIN);
+        let _number: JsNumber = browser.new_value(&i64::MIN);
+        let _big: JsNullable<JsBigInt> = browser.new_value(&Some(&u64::MAX));
+        assert_eq!(
+            browser.0.lock().commands_buf,
+            format!(
+                "_w.s(1,{}n);\n_w.s(2,{});\n_w.s(3,{}n);\n",
+                i64::MIN,
+                i64::MIN,
+                u64::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn typed_bigint_retrievals_fail_if_it_does_not_fit() {
+        let browser = Browser::new();
+        let id: JsBigInt = browser.new_value(&u64::MAX);
+        let mut too_big = std::pin::pin!(id.retrieve_i64());
+        let mut fits = std::pin::pin!(id.retrieve_u64());
+        let mut wide = std::pin::pin!(id.retrieve_i128());
+        assert!(too_big.as_mut().now_or_never().is_none());
+        assert!(fits.as_mut().now_or_never().is_none());
+        assert!(wide.as_mut().now_or_never().is_none());
+        for ret_id in 1..=3 {
+            browser
+                .receive_incoming_message(format!(r#"{ret_id}:{{"$w":"b","v":"{}"}}"#, u64::MAX));
+        }
+        assert!(matches!(
+            too_big.now_or_never(),
+            Some(Err(Error::DataDeserialize(_)))
+        ));
+        assert_eq!(fits.now_or_never().map(Result::ok), Some(Some(u64::MAX)));
+        assert_eq!(
+            wide.now_or_never().map(Result::ok),
+            Some(Some(u64::MAX.into()))
+        );
+        // not fitting is only an error for the retrieval
+        assert!(browser.take_error().is_none());
+    }
 }
@@ -1,10 +1,15 @@
+use crate::command::{Command, Slot};
 use crate::js_cast::JsCast;
 use crate::link::Browser;
-use crate::protocol::{DEL, GET, SET};
-use std::fmt::Write;
 
 /// Represents a value that exists on the JavaScript side.
 /// Value can be anything - number, string, object, undefined, null, ...
+///
+/// A value belongs to the [Browser] that created it.
+///
+/// JS values live in one web page, so passing one to another Browser kills that Browser
+/// with [Error::WrongBrowser](crate::Error::WrongBrowser), without sending the command.
+/// To send the same thing to many Browsers, create a value in each of them.
 pub struct JsValue {
     pub(crate) id: u64,
     pub(crate) browser: Browser,
@@ -12,20 +17,20 @@ pub struct JsValue {
 
 impl Drop for JsValue {
     fn drop(&mut self) {
-        let self_id = self.id;
-        let mut link = self.browser.0.lock().unwrap();
-        writeln!(link.raw_commands_buf(), "{DEL}({self_id});",).unwrap();
+        let mut link = self.browser.0.lock();
+        if !link.forget_value(self.id) {
+            link.push_command(Command::Delete(self.id));
+        }
         link.wake_outgoing_lazy();
     }
 }
 
 impl Clone for JsValue {
     fn clone(&self) -> Self {
-        let self_id = self.id;
         let out_id = {
-            let mut link = self.browser.0.lock().unwrap();
-            let out_id = link.get_new_id();
-            writeln!(link.raw_commands_buf(), "{SET}({out_id},{GET}({self_id}));").unwrap();
+            let mut link = self.browser.0.lock();
+            let out_id = link.new_slot_id();
+            link.push_command(Command::Set(out_id, &Slot(self.id)));
             link.wake_outgoing_lazy();
             out_id
         };
@@ -37,7 +42,6 @@ impl Clone for JsValue {
 }
 
 impl JsValue {
-    // const MAX_ID: u64 = (1 << 53) - 1;
     pub fn browser(&self) -> &Browser {
         &self.browser
     }
@@ -0,0 +1,359 @@
+/*!
+Not giving slots to values that don't need them.
+
+Every [JsValue](crate::js_types::JsValue) is put in a slot when it's created (`_w.s(id, expr)`),
+and freed when it's dropped (`_w.d(id)`). Most method call results are never used,
+and many others are only used once, by the very next command.
+So when a value is dropped while its Set is still buffered, the commands are rewritten instead:
+
+-   if nothing used the value, the Set becomes a plain statement (`expr;`), and no Delete is sent.
+-   if only the next command used it, and evaluated it before anything else,
+    the expression is inlined there, and no Delete is sent.
+
+`document.get_body().append_child(&node)` is sent as `((document)["body"]).appendChild(_w.g(3))`
+rather than two Sets and two Deletes.
+
+Nothing is reordered: an expression is only inlined where it would have been evaluated anyway.
+*/
+
+use std::ops::Range;
+
+use crate::command::{Command, CommandFormat, Verbatim};
+use crate::link::BrowserInternal;
+use crate::scope::Deleted;
+use crate::serialize::JsWriter;
+
+/// Stands for whatever an inlined expression or raw code does, in [Written::slots].
+pub(crate) const EFFECT: u64 = 0;
+
+/// A command in the outgoing buffer, remembered so that it can be rewritten.
+#[derive(Debug)]
+pub(crate) struct Written {
+    pub start: usize,
+    pub end: usize,
+    /// The slot filled by a [Command::Set].
+    pub set: Option<u64>,
+    /// Where the value of a [Command::Set] or [Command::Eval] is. Always there for a Set.
+    pub value: Option<Range<usize>>,
+    /// Whether the command evaluates its expressions once, right away, so that values can be inlined into it.
+    pub inlinable: bool,
+    /// The slots used, in the order they are evaluated.
+    pub slots: Vec<u64>,
+    /// Whether the command has raw code in it, so can't be made a [template](crate::template).
+    pub raw: bool,
+    /// Where the literals that [templates](crate::template) take out as parameters are.
+    pub params: Vec<Range<usize>>,
+    /// Whether the command gives [interned strings](crate::intern) their tokens,
+    /// so can't be inlined after commands that might use them.
+    pub defines: bool,
+    /// The slots freed by a delete command, which later deletes can be added to.
+    pub deleted: Option<Deleted>,
+}
+
+impl BrowserInternal {
+    /// Free the slot of a dropped value by rewriting the buffered commands, if possible.
+    ///
+    /// Returns `false` if the slot must be freed with a [Command::Delete].
+    pub(crate) fn forget_value(&mut self, id: u64) -> bool {
+        if self.forget_refused(id) {
+            // nothing to free on the client
+            self.free_slot_id(id);
+            return true;
+        }
+        let forgotten = self.discard_value(id) || self.inline_value(id);
+        if forgotten {
+            self.value_deleted();
+            self.free_slot_id(id);
+        }
+        forgotten
+    }
+    /// Turn the last command, if it's the unused value's Set, into a plain statement.
+    fn discard_value(&mut self, id: u64) -> bool {
+        match self.written.last() {
+            Some(last) if last.set == Some(id) && last.end == self.commands_buf.len() => {}
+            _ => return false,
+        }
+        let last = self.written.pop().unwrap();
+        let buf = &mut self.commands_buf;
+        let old_value = last.value.unwrap();
+        let value = buf[old_value.clone()].to_owned();
+        buf.truncate(last.start);
+        let command = Command::Eval(&Verbatim(&value));
+        let (prefix, suffix) = self.format.value_affixes(self.style, &command).unwrap();
+        self.format.write(self.style, buf, command);
+        let end = buf.len();
+        let value_start = last.start + prefix;
+        let params = last
+            .params
+            .into_iter()
+            .filter(|param| old_value.start <= param.start && param.end <= old_value.end)
+            .map(|param| {
+                param.start - old_value.start + value_start
+                    ..param.end - old_value.start + value_start
+            })
+            .collect();
+        self.written.push(Written {
+            start: last.start,
+            end,
+            set: None,
+            value: Some(value_start..end - suffix),
+            inlinable: last.inlinable,
+            slots: last.slots,
+            raw: last.raw,
+            params,
+            defines: last.defines,
+            deleted: None,
+        });
+        true
+    }
+    /// Put the value's expression in place of its only use, in the command right after its Set.
+    fn inline_value(&mut self, id: u64) -> bool {
+        let [.., set, user] = &self.written[..] else {
+            return false;
+        };
+        if set.set != Some(id)
+            || set.defines
+            || set.end != user.start
+            || user.end != self.commands_buf.len()
+            || !user.inlinable
+            || user.slots.first() != Some(&id)
+            || user.slots.iter().filter(|slot| **slot == id).count() != 1
+        {
+            return false;
+        }
+        let value = &self.commands_buf[set.value.clone().unwrap()];
+        let mut reference = String::new();
+        JsWriter::new(&mut reference, self.format, self.style, 0, None).slot(id);
+        let (replacement, open) = match self.format {
+            CommandFormat::Code | CommandFormat::Templates => (format!("({value})"), "(".len()),
+            CommandFormat::Ops => (value.to_owned(), 0),
+        };
+        let used = &self.commands_buf[user.start..user.end];
+        // a string literal could look like the reference too
+        let Some(at) = used
+            .find(&reference)
+            .filter(|at| used.rfind(&reference) == Some(*at))
+        else {
+            return false;
+        };
+        let after = at + reference.len();
+        let text = format!("{}{replacement}{}", &used[..at], &used[after..]);
+        let user = self.written.pop().unwrap();
+        let set = self.written.pop().unwrap();
+        // where a position in the user's command ends up
+        let moved = |pos: usize| {
+            let pos = pos - user.start;
+            if pos < after {
+                set.start + pos
+            } else {
+                set.start + pos - reference.len() + replacement.len()
+            }
+        };
+        let raw = set.raw || user.raw;
+        // the value's parameters go where the reference was, which had the slot id as its parameter
+        let value = set.value.clone().unwrap();
+        let value_start = set.start + at + open;
+        let mut params: Vec<_> = user
+            .params
+            .iter()
+            .filter(|param| param.end - user.start <= at)
+            .map(|param| moved(param.start)..moved(param.end))
+            .collect();
+        params.extend(
+            set.params
+                .iter()
+                .filter(|param| value.start <= param.start && param.end <= value.end)
+                .map(|param| {
+                    param.start - value.start + value_start..param.end - value.start + value_start
+                }),
+        );
+        params.extend(
+            user.params
+                .iter()
+                .filter(|param| param.start - user.start >= after)
+                .map(|param| moved(param.start)..moved(param.end)),
+        );
+        let mut slots = set.slots;
+        slots.push(EFFECT);
+        slots.extend_from_slice(&user.slots[1..]);
+        self.written.push(Written {
+            start: set.start,
+            end: set.start + text.len(),
+            set: user.set,
+            value: user.value.map(|value| moved(value.start)..moved(value.end)),
+            inlinable: true,
+            slots,
+            raw,
+            params,
+            defines: user.defines,
+            deleted: None,
+        });
+        self.commands_buf.truncate(set.start);
+        self.commands_buf.push_str(&text);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::{CodeStyle, CommandFormat},
+        js_types::JsValue,
+        serialize::RawCodeImmediate,
+        Browser, JsPath, UseInJsCode,
+    };
+
+    fn buf(browser: &Browser) -> String {
+        browser.0.lock().commands_buf.clone()
+    }
+
+    fn call(browser: &Browser, name: &'static str, args: &[&JsValue]) -> JsValue {
+        browser.call_function(
+            JsPath::from_static(name),
+            args.iter().map(|arg| *arg as &dyn UseInJsCode),
+            false,
+        )
+    }
+
+    #[test]
+    fn unused_values_are_discarded() {
+        let browser = Browser::new();
+        drop(call(&browser, "f", &[]));
+        assert_eq!(buf(&browser), "(f());\n");
+        // the slot is free again
+        let value = call(&browser, "g", &[]);
+        assert_eq!(buf(&browser), "(f());\n_w.s(1,g());\n");
+        drop(value);
+    }
+
+    #[test]
+    fn discarding_in_every_format() {
+        for (format, style, expected) in [
+            (CommandFormat::Code, CodeStyle::Compact, ";(f())"),
+            (
+                CommandFormat::Ops,
+                CodeStyle::Readable,
+                r#"["e",["c",["v","f"],[],false]],"#,
+            ),
+            (CommandFormat::Templates, CodeStyle::Readable, "(f());\n"),
+        ] {
+            let browser = Browser::new();
+            browser.set_command_format(format);
+            browser.set_code_style(style);
+            drop(call(&browser, "f", &[]));
+            assert_eq!(buf(&browser), expected);
+        }
+    }
+
+    #[test]
+    fn values_used_by_the_next_command_are_inlined() {
+        let browser = Browser::new();
+        let body = call(&browser, "f", &[]);
+        let child = call(&browser, "g", &[&body]);
+        drop(body);
+        assert_eq!(buf(&browser), "_w.s(2,g((f()),));\n");
+        drop(child);
+        assert_eq!(buf(&browser), "(g((f()),));\n");
+    }
+
+    #[test]
+    fn inlining_ops() {
+        let browser = Browser::new();
+        browser.set_command_format(CommandFormat::Ops);
+        let body = call(&browser, "f", &[]);
+        let child = call(&browser, "g", &[&body]);
+        drop(body);
+        assert_eq!(
+            buf(&browser),
+            r#"["s",2,["c",["v","g"],[["c",["v","f"],[],false]],false]],"#
+        );
+        drop(child);
+    }
+
+    #[test]
+    fn values_used_twice_or_later_are_not_inlined() {
+        let browser = Browser::new();
+        let value = call(&browser, "f", &[]);
+        let twice = call(&browser, "g", &[&value, &value]);
+        drop(value);
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,f());\n_w.s(2,g(_w.g(1),_w.g(1),));\n_w.d(1);\n"
+        );
+        let len = buf(&browser).len();
+        let value = call(&browser, "f", &[]);
+        let _first = call(&browser, "g", &[]);
+        let later = call(&browser, "h", &[&value]);
+        drop(value);
+        assert_eq!(
+            &buf(&browser)[len..],
+            "_w.s(1,f());\n_w.s(3,g());\n_w.s(4,h(_w.g(1),));\n_w.d(1);\n"
+        );
+        drop((twice, later));
+    }
+
+    #[test]
+    fn values_used_after_other_arguments_are_not_inlined() {
+        let browser = Browser::new();
+        let first = call(&browser, "f", &[]);
+        let second = call(&browser, "g", &[]);
+        let user = call(&browser, "h", &[&first, &second]);
+        // inlined, `g()` would run after `_w.g(1)` is evaluated rather than before
+        drop(second);
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,f());\n_w.s(2,g());\n_w.s(3,h(_w.g(1),_w.g(2),));\n_w.d(2);\n"
+        );
+        drop((first, user));
+    }
+
+    #[test]
+    fn values_are_not_inlined_where_they_may_not_be_evaluated() {
+        for (op, expected) in [
+            (
+                "&&",
+                "_w.s(1,f());\n_w.s(2,((false) && (_w.g(1))));\n_w.d(1);\n",
+            ),
+            (
+                "||",
+                "_w.s(1,f());\n_w.s(2,((true) || (_w.g(1))));\n_w.d(1);\n",
+            ),
+        ] {
+            let browser = Browser::new();
+            let value = call(&browser, "f", &[]);
+            let result = match op {
+                "&&" => browser.op_logical_and(&false, &value),
+                _ => browser.op_logical_or(&true, &value),
+            };
+            drop(value);
+            assert_eq!(buf(&browser), expected);
+            drop(result);
+        }
+    }
+
+    #[test]
+    fn values_are_not_inlined_after_raw_code() {
+        for (format, expected) in [
+            (
+                CommandFormat::Code,
+                "_w.s(1,f());\n_w.s(2,g(x(),_w.g(1),));\n_w.d(1);\n",
+            ),
+            (
+                CommandFormat::Ops,
+                r#"["s",1,["c",["v","f"],[],false]],["s",2,["c",["v","g"],[["E","x()"],["g",1]],false]],["d",1],"#,
+            ),
+        ] {
+            let browser = Browser::new();
+            browser.set_command_format(format);
+            let value = call(&browser, "f", &[]);
+            let result = browser.call_function(
+                JsPath::from_static("g"),
+                [&RawCodeImmediate("x()") as &dyn UseInJsCode, &value],
+                false,
+            );
+            drop(value);
+            assert_eq!(buf(&browser), expected);
+            drop(result);
+        }
+    }
+}
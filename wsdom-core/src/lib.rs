@@ -1,14 +1,35 @@
+mod command;
+mod data;
+pub mod flow;
+mod from_js;
+mod handshake;
+mod ident;
+mod ids;
 mod interaction;
+mod intern;
 mod internal;
 mod js;
 mod js_cast;
+mod lazy;
 mod link;
+mod msgpack;
 mod operations;
+mod policy;
 mod protocol;
+mod quota;
+mod reply;
 mod retrieve;
+mod scope;
 mod serialize;
+mod session;
+pub mod shared;
+mod snapshot;
+mod template;
+pub mod timeout;
 
 pub use link::{Browser, Error};
+pub use msgpack::MessagePackError;
+pub use protocol::PROTOCOL_VERSION;
 
 pub mod js_types {
     //! Stubs for primitive JS types including number, string, null, undefined, object.
@@ -19,11 +40,22 @@ pub mod js_types {
         value::JsValue,
     };
 }
+pub use command::{CodeStyle, CommandFormat};
+pub use data::JsData;
+pub use from_js::FromJs;
+pub use handshake::{ClientInfo, Viewport};
+pub use ident::{JsIdent, JsPath};
 pub use interaction::callback;
 pub use js_cast::JsCast;
-pub use serialize::{ToJs, UseInJsCode};
+pub use policy::{Capability, Policy};
+pub use quota::{Quota, Quotas};
+pub use reply::ReplyFormat;
+pub use serialize::{FormatJsCode, JsWriter, ToJs, UseInJsCode};
+pub use snapshot::{Snapshot, SnapshotOptions};
 pub mod immediates {
-    pub use super::js::immediates::{null, undefined};
+    pub use super::js::immediates::{
+        array_from_iter, bigint, null, undefined, BigIntImmediate, IterImmediate,
+    };
 }
 
 #[doc(hidden)]
@@ -31,4 +63,5 @@ pub mod for_macro {
     pub use super::internal::upcast_workaround::UpcastWorkaround;
     pub use super::link::BrowserInternal;
     pub use super::serialize::RawCodeImmediate;
+    pub use serde::de::DeserializeOwned;
 }
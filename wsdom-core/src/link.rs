@@ -1,9 +1,25 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::Arc,
     task::{Poll, Waker},
 };
 
+use crate::command::{CodeStyle, CommandFormat};
+use crate::flow::{FlushPolicy, DEFAULT_HIGH_WATER_MARK};
+use crate::handshake::{ClientInfo, Hello};
+use crate::ids::Ids;
+use crate::intern::Strings;
+use crate::lazy::Written;
+use crate::msgpack::MessagePackError;
+use crate::policy::{Capability, Policy};
+use crate::protocol::{CLIENT_ACK, CLIENT_HELLO};
+use crate::quota::{Quota, QuotaState};
+use crate::reply::{Reply, ReplyFormat};
+use crate::session::{Control, Session};
+use crate::shared::Shared;
+use crate::template::Templates;
+use crate::timeout::{Sleep, Timer};
+
 /// A WSDOM client.
 ///
 /// You can use this to call JS functions on the JS client (the web browser).
@@ -25,11 +41,18 @@ use std::{
 ///     You must take items from the stream and send it to the WSDOM JS client
 ///     over WebSocket or other transport of your choice.
 /// -   Browser has a `receive_incoming_message(msg: String)` method.
-///     Everything sent by the WSDOM JS client must be fed into this method.
+///     Everything sent by the WSDOM JS client must be fed into this method
+///     (or, for binary messages, `receive_incoming_binary_message`; see [ReplyFormat]).
 ///
 /// The `counter-manual` example in our repo shows manual usage with Tokio.
+///
+/// ## Single-threaded Use
+///
+/// Every call on a Browser or a JsValue locks a Mutex.
+/// If every Browser stays on one thread, enable the `local` cargo feature to skip that;
+/// see the [shared module](crate::shared).
 #[derive(Clone, Debug)]
-pub struct Browser(pub(crate) Arc<Mutex<BrowserInternal>>);
+pub struct Browser(pub(crate) Shared<BrowserInternal>);
 
 impl Browser {
     /// Create a new Browser object.
@@ -38,26 +61,71 @@ impl Browser {
     pub fn new() -> Self {
         let link = BrowserInternal {
             retrievals: HashMap::new(),
-            last_id: 1,
+            ids: Ids::default(),
             commands_buf: String::new(),
+            written: Vec::new(),
+            scope_depth: 0,
+            scope_deleted: Vec::new(),
+            templates: Templates::default(),
+            strings: Strings::default(),
             outgoing_waker: None,
             dead: ErrorState::NoError,
+            timer: None,
+            session: None,
+            client_info: None,
+            format: CommandFormat::default(),
+            style: CodeStyle::default(),
+            reply_format: ReplyFormat::default(),
+            policy: None,
+            quota: QuotaState::default(),
+            key: 0,
+            high_water_mark: DEFAULT_HIGH_WATER_MARK,
+            drain_wakers: Vec::new(),
+            flush_policy: FlushPolicy::default(),
+            must_flush: false,
+            batch_woken: false,
+            flush_timer: None,
         };
-        Self(Arc::new(Mutex::new(link)))
+        let this = Self(Shared::new(link));
+        this.0.lock().key = this.key();
+        this
+    }
+    /// Identifies this Browser (and its clones) without locking it.
+    pub(crate) fn key(&self) -> usize {
+        self.0.addr()
+    }
+    /// Create a new Browser whose session can be resumed if the client reconnects.
+    ///
+    /// Messages are kept until the client acknowledges them, up to `replay_limit` bytes,
+    /// so that they can be sent again to a reconnected client.
+    /// Pending retrievals and [Callback](crate::callback::Callback)s continue where they left off.
+    ///
+    /// When resuming, feed the reconnected client's messages into the same Browser as before.
+    /// The client identifies itself with [session_token](Browser::session_token)
+    /// in its first message, `h:<token>:<count>`.
+    pub fn new_resumable(replay_limit: usize) -> Self {
+        let this = Self::new();
+        this.0.lock().session = Some(Session::new(replay_limit));
+        this
+    }
+    /// The token a reconnecting client presents, if this Browser is resumable.
+    pub fn session_token(&self) -> Option<String> {
+        let link = self.0.lock();
+        link.session.as_ref().map(|session| session.token.clone())
     }
     /// Receive a message sent from the WSDOM JS client.
     ///
     /// This is only needed if you intend to go the "manual" route described above.
     /// If you use an integration library, messages are handled automatically.
     pub fn receive_incoming_message(&self, message: String) {
-        self.0.lock().unwrap().receive(message);
+        self.0.lock().receive(message);
     }
     /// If the Browser has errored, this will return the error.
     ///
     /// The [Error] type is not [Clone], so after the first call returning `Some(_)`,
     /// this method will return `None`.
     pub fn take_error(&self) -> Option<Error> {
-        let mut link = self.0.lock().unwrap();
+        let mut link = self.0.lock();
         match std::mem::replace(&mut link.dead, ErrorState::ErrorTaken) {
             ErrorState::NoError => {
                 link.dead = ErrorState::NoError;
@@ -78,7 +146,7 @@ impl futures_core::Stream for Browser {
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
-        let mut link = this.0.lock().unwrap();
+        let mut link = this.0.lock();
 
         if !matches!(&link.dead, ErrorState::NoError) {
             return Poll::Ready(None);
@@ -92,30 +160,125 @@ impl futures_core::Stream for Browser {
         {
             link.outgoing_waker = Some(new_waker.to_owned());
         }
-        if !link.commands_buf.is_empty() {
-            Poll::Ready(Some(std::mem::take(&mut link.commands_buf)))
+        let link = &mut *link;
+        if let Some(session) = link.session.as_mut() {
+            if let Some(msg) = session.resend.pop_front() {
+                link.wake_drained();
+                return Poll::Ready(Some(msg));
+            }
+        }
+        if !link.commands_buf.is_empty() && link.should_send(cx) {
+            link.batch_sent();
+            let mut commands = std::mem::take(&mut link.commands_buf);
+            let mut format = link.format;
+            if format == CommandFormat::Templates {
+                commands = link.templatize(&commands);
+                format = CommandFormat::Ops;
+            }
+            link.written.clear();
+            let message = match link.session.as_mut() {
+                Some(session) => session.outgoing(commands, format, link.style),
+                None => format.finish(commands),
+            };
+            link.wake_drained();
+            Poll::Ready(Some(message))
         } else {
             Poll::Pending
         }
     }
 }
 
-#[derive(Debug)]
 pub struct BrowserInternal {
     pub(crate) retrievals: HashMap<u64, RetrievalState>,
-    last_id: u64,
-    commands_buf: String,
+    pub(crate) ids: Ids,
+    pub(crate) commands_buf: String,
+    /// The commands in `commands_buf`, for [lazy](crate::lazy) rewriting.
+    pub(crate) written: Vec<Written>,
+    /// How many [scopes](Browser::scope) are running.
+    pub(crate) scope_depth: usize,
+    /// Slots freed in the scopes, to be deleted when they end.
+    pub(crate) scope_deleted: Vec<u64>,
+    pub(crate) templates: Templates,
+    pub(crate) strings: Strings,
     outgoing_waker: Option<Waker>,
     dead: ErrorState,
+    pub(crate) timer: Option<Arc<dyn Timer>>,
+    session: Option<Session>,
+    pub(crate) client_info: Option<ClientInfo>,
+    pub(crate) format: CommandFormat,
+    pub(crate) style: CodeStyle,
+    pub(crate) reply_format: ReplyFormat,
+    pub(crate) policy: Option<Policy>,
+    pub(crate) quota: QuotaState,
+    /// [Browser::key] of the Browser this belongs to.
+    pub(crate) key: usize,
+    pub(crate) high_water_mark: usize,
+    pub(crate) drain_wakers: Vec<Waker>,
+    pub(crate) flush_policy: FlushPolicy,
+    /// Whether the buffered commands must be sent regardless of the [FlushPolicy].
+    pub(crate) must_flush: bool,
+    /// Whether the transport was woken for the commands buffered so far.
+    pub(crate) batch_woken: bool,
+    pub(crate) flush_timer: Option<Sleep>,
+}
+
+impl std::fmt::Debug for BrowserInternal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BrowserInternal")
+            .field("retrievals", &self.retrievals)
+            .field("ids", &self.ids)
+            .field("commands_buf", &self.commands_buf)
+            .field("outgoing_waker", &self.outgoing_waker)
+            .field("dead", &self.dead)
+            .field("session", &self.session)
+            .field("client_info", &self.client_info)
+            .field("format", &self.format)
+            .field("style", &self.style)
+            .field("reply_format", &self.reply_format)
+            .field("policy", &self.policy)
+            .field("quota", &self.quota)
+            .field("high_water_mark", &self.high_water_mark)
+            .field("flush_policy", &self.flush_policy)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Error that could happen in WSDOM.
 ///
-/// Currently, the only errors that could happen are from [serde] serialization and deserialization.
+/// Serialization and deserialization errors kill the Browser and are returned by [Browser::take_error].
+/// So are [Error::ResumeFailed] and [Error::IncompatibleClient], which are caused by the JS client,
+/// and [Error::PolicyViolation] and [Error::QuotaExceeded].
+/// [Error::Timeout] and [Error::NoTimer] are only returned from [timeouts](crate::timeout).
+///
+/// More kinds of errors may be added, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     CommandSerialize(std::fmt::Error),
     DataDeserialize(serde_json::Error),
+    /// A binary reply (see [ReplyFormat::MessagePack](crate::ReplyFormat::MessagePack))
+    /// didn't fit the type it was retrieved as.
+    MessagePackDeserialize(MessagePackError),
+    Timeout,
+    /// A timeout was used on a Browser without a [Timer](crate::timeout::Timer).
+    /// Call [Browser::set_timer], or enable the `async-io` feature,
+    /// or the `tokio` feature and use timeouts inside a Tokio runtime.
+    NoTimer,
+    /// A client tried to resume the session, but the messages it missed are no longer kept
+    /// (or it presented the wrong token).
+    ResumeFailed,
+    /// The JS client speaks a different protocol version (given, if the client said it;
+    /// `None` if its hello didn't make sense),
+    /// most likely because the web browser cached an old copy of it.
+    /// See [Browser::client_info].
+    IncompatibleClient(Option<u32>),
+    /// Something forbidden by the Browser's [Policy] was attempted, and not sent to the client.
+    PolicyViolation(Capability),
+    /// A limit in the Browser's [Quotas](crate::Quotas) was broken.
+    QuotaExceeded(Quota),
+    /// A [JsValue](crate::js_types::JsValue) of another Browser was passed to this one.
+    /// The command it was passed to was not sent.
+    WrongBrowser,
 }
 #[derive(Debug)]
 enum ErrorState {
@@ -127,20 +290,92 @@ enum ErrorState {
 #[derive(Debug)]
 pub(crate) struct RetrievalState {
     pub(crate) waker: Waker,
-    pub(crate) last_value: String,
+    pub(crate) last_value: Reply,
     pub(crate) times: usize,
 }
 
 impl BrowserInternal {
     pub fn receive(&mut self, message: String) {
-        match message
-            .split_once(':')
-            .and_then(|(id, _)| id.parse::<u64>().ok())
-        {
+        let control = message.starts_with(CLIENT_HELLO) || message.starts_with(CLIENT_ACK);
+        if !self.allows_incoming(message.len(), !control) {
+            self.wake_outgoing();
+            return;
+        }
+        if let Some(hello) = message.strip_prefix(CLIENT_HELLO) {
+            let hello = Hello::parse(hello);
+            let new_client = hello.token.is_empty();
+            if new_client {
+                // a new client, which doesn't have any templates or interned strings
+                self.templates.clear();
+                self.strings = Strings::default();
+            }
+            let resumed = hello.info.and_then(|info| {
+                if self.client_info.is_none()
+                    && self.commands_buf.is_empty()
+                    && !info.supports("eval")
+                {
+                    // probably blocked by Content-Security-Policy;
+                    // commands already written as code can't be rewritten, so only if there are none yet
+                    self.format = CommandFormat::Ops;
+                    self.send_policy();
+                }
+                self.client_info = Some(info);
+                match self.session.as_mut() {
+                    Some(session) => session.resume(
+                        hello.token,
+                        hello.count,
+                        &mut self.commands_buf,
+                        self.format,
+                        self.style,
+                    ),
+                    None => Ok(()),
+                }
+            });
+            if let Err(e) = resumed {
+                self.kill(e);
+            }
+            // clients start out replying in JSON
+            if new_client && self.reply_format != ReplyFormat::Json {
+                self.send_reply_format();
+            }
+            self.wake_outgoing();
+            return;
+        }
+        if self.client_info.is_none() {
+            // the client didn't introduce itself, so it must be from before hellos
+            self.client_info = Some(ClientInfo::legacy());
+        }
+        if let Some(session) = self.session.as_mut() {
+            if let Control::Handled = session.receive(&message) {
+                if !session.resend.is_empty() {
+                    self.wake_outgoing();
+                }
+                return;
+            }
+        } else if message.starts_with(CLIENT_ACK) {
+            return;
+        }
+        self.receive_reply(Reply::Text(message));
+    }
+    pub(crate) fn receive_binary(&mut self, message: Vec<u8>) {
+        if !self.allows_incoming(message.len(), true) {
+            self.wake_outgoing();
+            return;
+        }
+        if self.client_info.is_none() {
+            self.client_info = Some(ClientInfo::legacy());
+        }
+        if let Some(session) = self.session.as_mut() {
+            session.received_reply();
+        }
+        self.receive_reply(Reply::Binary(message));
+    }
+    fn receive_reply(&mut self, reply: Reply) {
+        match reply.id() {
             Some(id) => match self.retrievals.get_mut(&id) {
                 Some(s) => {
                     s.times += 1;
-                    s.last_value = message;
+                    s.last_value = reply;
                     s.waker.wake_by_ref();
                 }
                 _ => {}
@@ -151,23 +386,32 @@ impl BrowserInternal {
     pub fn raw_commands_buf(&mut self) -> &mut String {
         &mut self.commands_buf
     }
-    pub(crate) fn get_new_id(&mut self) -> u64 {
-        self.last_id += 1;
-        self.last_id
-    }
     pub(crate) fn kill(&mut self, err: Error) {
         if matches!(self.dead, ErrorState::NoError) {
             self.dead = ErrorState::Error(err);
         }
+        self.wake_drained();
     }
+    pub(crate) fn is_dead(&self) -> bool {
+        !matches!(self.dead, ErrorState::NoError)
+    }
+    /// Bytes waiting to be taken by the transport.
+    pub(crate) fn outgoing_len(&self) -> usize {
+        let resend = self.session.as_ref().map_or(0, |session| {
+            session.resend.iter().map(String::len).sum::<usize>()
+        });
+        self.commands_buf.len() + resend
+    }
+    /// Have the transport take the buffered commands now, regardless of the [FlushPolicy].
     pub(crate) fn wake_outgoing(&mut self) {
+        self.must_flush = true;
+        self.wake_transport();
+    }
+    pub(crate) fn wake_transport(&mut self) {
         if let Some(waker) = self.outgoing_waker.as_ref() {
             waker.wake_by_ref();
         }
     }
-    pub(crate) fn wake_outgoing_lazy(&mut self) {
-        self.wake_outgoing();
-    }
 }
 
 struct InvalidReturn;
@@ -0,0 +1,767 @@
+/*!
+Decoding the MessagePack of binary replies (see [ReplyFormat::MessagePack](crate::ReplyFormat::MessagePack)).
+
+The JS client writes nil, booleans, numbers, strings, arrays, and maps with string keys.
+Values JSON can't represent are tagged like in JSON replies, with the tag as the first key.
+
+Values are deserialized straight from the bytes, and strings are borrowed where the type allows it.
+Only tagged values (which are rare) are decoded into a [JsData] first.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+    EnumAccess, Error as _, MapAccess, SeqAccess, Unexpected, VariantAccess, Visitor,
+};
+
+use crate::data::{visit_integer, visit_number, JsData};
+use crate::protocol::TAG;
+
+/// Why a binary reply couldn't be decoded.
+///
+/// See [Error::MessagePackDeserialize](crate::Error::MessagePackDeserialize).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagePackError(String);
+
+impl fmt::Display for MessagePackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MessagePackError {}
+
+impl de::Error for MessagePackError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Deserialize a whole MessagePack value.
+pub(crate) fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MessagePackError> {
+    whole(bytes, |reader| T::deserialize(reader))
+}
+
+/// Decode a whole MessagePack value into a [JsData], tags and all.
+pub(crate) fn to_data(bytes: &[u8]) -> Result<JsData, MessagePackError> {
+    whole(bytes, Reader::data)
+}
+
+/// Decode a whole MessagePack value as it was written, leaving tags as they are.
+///
+/// Only for snapshots, which have tags of their own.
+pub(crate) fn to_json(bytes: &[u8]) -> Result<serde_json::Value, MessagePackError> {
+    whole(bytes, Reader::json)
+}
+
+fn whole<'de, T>(
+    bytes: &'de [u8],
+    read: impl FnOnce(&mut Reader<'de>) -> Result<T, MessagePackError>,
+) -> Result<T, MessagePackError> {
+    let mut reader = Reader { bytes, depth: 0 };
+    let value = read(&mut reader)?;
+    if !reader.bytes.is_empty() {
+        return Err(MessagePackError::custom("trailing bytes after MessagePack"));
+    }
+    Ok(value)
+}
+
+/// As deep as [serde_json] lets JSON nest, so that a client can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone)]
+struct Reader<'de> {
+    bytes: &'de [u8],
+    depth: usize,
+}
+
+/// What a marker (and the bytes right after it) says.
+/// For arrays and maps, this is only the length; the items follow.
+enum Head<'de> {
+    Nil,
+    Bool(bool),
+    Uint(u64),
+    Int(i64),
+    Float(f64),
+    Str(&'de str),
+    Array(usize),
+    Map(usize),
+}
+
+impl Head<'_> {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match *self {
+            Self::Nil => Unexpected::Unit,
+            Self::Bool(b) => Unexpected::Bool(b),
+            Self::Uint(n) => Unexpected::Unsigned(n),
+            Self::Int(n) => Unexpected::Signed(n),
+            Self::Float(n) => Unexpected::Float(n),
+            Self::Str(s) => Unexpected::Str(s),
+            Self::Array(_) => Unexpected::Seq,
+            Self::Map(_) => Unexpected::Map,
+        }
+    }
+}
+
+impl<'de> Reader<'de> {
+    fn take(&mut self, len: usize) -> Result<&'de [u8], MessagePackError> {
+        if self.bytes.len() < len {
+            return Err(MessagePackError::custom("MessagePack ended early"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], MessagePackError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    /// A big-endian length of `N` bytes.
+    fn len<const N: usize>(&mut self) -> Result<usize, MessagePackError> {
+        Ok(self
+            .array::<N>()?
+            .iter()
+            .fold(0, |len, &byte| len << 8 | byte as usize))
+    }
+    fn str(&mut self, len: usize) -> Result<&'de str, MessagePackError> {
+        std::str::from_utf8(self.take(len)?).map_err(MessagePackError::custom)
+    }
+    fn head(&mut self) -> Result<Head<'de>, MessagePackError> {
+        let [marker] = self.array()?;
+        Ok(match marker {
+            0x00..=0x7f => Head::Uint(marker.into()),
+            0xe0..=0xff => Head::Int((marker as i8).into()),
+            0xc0 => Head::Nil,
+            0xc2 => Head::Bool(false),
+            0xc3 => Head::Bool(true),
+            0xcc => Head::Uint(u8::from_be_bytes(self.array()?).into()),
+            0xcd => Head::Uint(u16::from_be_bytes(self.array()?).into()),
+            0xce => Head::Uint(u32::from_be_bytes(self.array()?).into()),
+            0xcf => Head::Uint(u64::from_be_bytes(self.array()?)),
+            0xd0 => Head::Int(i8::from_be_bytes(self.array()?).into()),
+            0xd1 => Head::Int(i16::from_be_bytes(self.array()?).into()),
+            0xd2 => Head::Int(i32::from_be_bytes(self.array()?).into()),
+            0xd3 => Head::Int(i64::from_be_bytes(self.array()?)),
+            0xca => Head::Float(f32::from_be_bytes(self.array()?).into()),
+            0xcb => Head::Float(f64::from_be_bytes(self.array()?)),
+            0xa0..=0xbf => Head::Str(self.str((marker & 0x1f).into())?),
+            0xd9 => {
+                let len = self.len::<1>()?;
+                Head::Str(self.str(len)?)
+            }
+            0xda => {
+                let len = self.len::<2>()?;
+                Head::Str(self.str(len)?)
+            }
+            0xdb => {
+                let len = self.len::<4>()?;
+                Head::Str(self.str(len)?)
+            }
+            0x90..=0x9f => Head::Array((marker & 0x0f).into()),
+            0xdc => Head::Array(self.len::<2>()?),
+            0xdd => Head::Array(self.len::<4>()?),
+            0x80..=0x8f => Head::Map((marker & 0x0f).into()),
+            0xde => Head::Map(self.len::<2>()?),
+            0xdf => Head::Map(self.len::<4>()?),
+            _ => {
+                return Err(MessagePackError::custom(format_args!(
+                    "unsupported MessagePack type 0x{marker:02x}"
+                )))
+            }
+        })
+    }
+    fn key(&mut self) -> Result<&'de str, MessagePackError> {
+        match self.head()? {
+            Head::Str(key) => Ok(key),
+            _ => Err(MessagePackError::custom(
+                "MessagePack map key is not a string",
+            )),
+        }
+    }
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, MessagePackError>,
+    ) -> Result<T, MessagePackError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(MessagePackError::custom("MessagePack nested too deeply"));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+    /// Read `len` items; every item is at least a byte, so a bogus length can't make this allocate much.
+    fn items<T>(
+        &mut self,
+        len: usize,
+        mut item: impl FnMut(&mut Self) -> Result<T, MessagePackError>,
+    ) -> Result<Vec<T>, MessagePackError> {
+        self.nested(|this| {
+            let mut items = Vec::with_capacity(len.min(this.bytes.len()));
+            for _ in 0..len {
+                items.push(item(this)?);
+            }
+            Ok(items)
+        })
+    }
+    fn data(&mut self) -> Result<JsData, MessagePackError> {
+        Ok(match self.head()? {
+            Head::Nil => JsData::Null,
+            Head::Bool(b) => JsData::Boolean(b),
+            Head::Uint(n) => JsData::Number(n as f64),
+            Head::Int(n) => JsData::Number(n as f64),
+            Head::Float(n) => JsData::Number(n),
+            Head::Str(s) => JsData::String(s.to_owned()),
+            Head::Array(len) => JsData::Array(self.items(len, Self::data)?),
+            Head::Map(len) => {
+                let entries = self.items(len, |this| Ok((this.key()?.to_owned(), this.data()?)))?;
+                JsData::from_object(entries.into_iter().collect::<BTreeMap<_, _>>())
+            }
+        })
+    }
+    fn json(&mut self) -> Result<serde_json::Value, MessagePackError> {
+        use serde_json::Value;
+        Ok(match self.head()? {
+            Head::Nil => Value::Null,
+            Head::Bool(b) => Value::Bool(b),
+            Head::Uint(n) => n.into(),
+            Head::Int(n) => n.into(),
+            // the client tags non-finite numbers, so they shouldn't come here; they become `null` like in JSON
+            Head::Float(n) => serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+            Head::Str(s) => s.into(),
+            Head::Array(len) => Value::Array(self.items(len, Self::json)?),
+            Head::Map(len) => Value::Object(
+                self.items(len, |this| Ok((this.key()?.to_owned(), this.json()?)))?
+                    .into_iter()
+                    .collect(),
+            ),
+        })
+    }
+    /// If the next value is tagged, decode it.
+    fn tagged(&mut self) -> Result<Option<JsData>, MessagePackError> {
+        let mut ahead = self.clone();
+        let tagged = matches!(ahead.head(), Ok(Head::Map(1..)))
+            && matches!(ahead.head(), Ok(Head::Str(TAG)));
+        if tagged {
+            self.data().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+    fn collection<V: Visitor<'de>>(
+        &mut self,
+        len: usize,
+        visitor: V,
+        visit: impl FnOnce(V, &mut Access<'_, 'de>) -> Result<V::Value, MessagePackError>,
+    ) -> Result<V::Value, MessagePackError> {
+        self.nested(|this| {
+            let mut access = Access {
+                reader: this,
+                left: len,
+            };
+            let value = visit(visitor, &mut access)?;
+            match access.left {
+                0 => Ok(value),
+                _ => Err(MessagePackError::invalid_length(len, &"fewer items")),
+            }
+        })
+    }
+}
+
+/// Decode a tagged value, and deserialize it like [JsData] would.
+macro_rules! forward_tagged {
+    ($reader:ident, $method:ident($($arg:expr),*)) => {
+        if let Some(data) = $reader.tagged()? {
+            return data.$method($($arg),*).map_err(MessagePackError::custom);
+        }
+    };
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                forward_tagged!(self, $method(visitor));
+                let mut ahead = self.clone();
+                match ahead.head()? {
+                    Head::Float(n) => {
+                        *self = ahead;
+                        visit_integer(n, visitor)
+                    }
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+/// Gives values the same way [JsData] does.
+impl<'de> Deserializer<'de> for &mut Reader<'de> {
+    type Error = MessagePackError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_any(visitor));
+        match self.head()? {
+            Head::Nil => visitor.visit_none(),
+            Head::Bool(b) => visitor.visit_bool(b),
+            Head::Uint(n) => visitor.visit_u64(n),
+            Head::Int(n) if n < 0 => visitor.visit_i64(n),
+            Head::Int(n) => visitor.visit_u64(n as u64),
+            Head::Float(n) => visit_number(n, visitor),
+            Head::Str(s) => visitor.visit_borrowed_str(s),
+            Head::Array(len) => self.collection(len, visitor, |v, access| v.visit_seq(access)),
+            Head::Map(len) => self.collection(len, visitor, |v, access| v.visit_map(access)),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_f64(visitor));
+        let mut ahead = self.clone();
+        let n = match ahead.head()? {
+            Head::Uint(n) => n as f64,
+            Head::Int(n) => n as f64,
+            Head::Float(n) => n,
+            _ => return self.deserialize_any(visitor),
+        };
+        *self = ahead;
+        visitor.visit_f64(n)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    deserialize_integer!(
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64 deserialize_i128
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+    );
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_str(visitor));
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_option(visitor));
+        let mut ahead = self.clone();
+        match ahead.head()? {
+            Head::Nil => {
+                *self = ahead;
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_unit(visitor));
+        match self.head()? {
+            Head::Nil => visitor.visit_unit(),
+            other => Err(Self::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        forward_tagged!(self, deserialize_enum(name, variants, visitor));
+        match self.head()? {
+            Head::Str(variant) => visitor.visit_enum(BorrowedStrDeserializer::new(variant)),
+            Head::Map(1) => self.collection(1, visitor, |v, access| v.visit_enum(access)),
+            other => Err(Self::Error::invalid_type(other.unexpected(), &visitor)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool char bytes byte_buf seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The items of an array or the entries of a map, `left` of which are yet to be read.
+struct Access<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    left: usize,
+}
+
+impl<'de> SeqAccess<'de> for Access<'_, 'de> {
+    type Error = MessagePackError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.reader).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de> MapAccess<'de> for Access<'_, 'de> {
+    type Error = MessagePackError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        let key = self.reader.key()?;
+        seed.deserialize(BorrowedStrDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(&mut *self.reader)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+/// A map with one entry, from the variant name to its content.
+impl<'a, 'de> EnumAccess<'de> for &mut Access<'a, 'de> {
+    type Error = MessagePackError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        self.left -= 1;
+        let variant = self.reader.key()?;
+        Ok((
+            seed.deserialize(BorrowedStrDeserializer::new(variant))?,
+            self,
+        ))
+    }
+}
+
+impl<'a, 'de> VariantAccess<'de> for &mut Access<'a, 'de> {
+    type Error = MessagePackError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        de::Deserialize::deserialize(&mut *self.reader)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(&mut *self.reader)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.reader.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.reader.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::{from_slice, to_data, to_json, MAX_DEPTH};
+    use crate::data::{from_wire, JsData};
+    use crate::reply::Payload;
+
+    /// A header with a big-endian length of `width` bytes.
+    fn sized(marker: u8, width: usize, len: usize) -> Vec<u8> {
+        let mut bytes = vec![marker];
+        bytes.extend_from_slice(&(len as u64).to_be_bytes()[8 - width..]);
+        bytes
+    }
+
+    fn str_of(len: usize) -> String {
+        "x".repeat(len)
+    }
+
+    #[test]
+    fn every_marker() {
+        let num = JsData::Number;
+        let cases: Vec<(Vec<u8>, JsData)> = vec![
+            (vec![0x00], num(0.0)),
+            (vec![0x7f], num(127.0)),
+            (vec![0xe0], num(-32.0)),
+            (vec![0xff], num(-1.0)),
+            (vec![0xc0], JsData::Null),
+            (vec![0xc2], JsData::Boolean(false)),
+            (vec![0xc3], JsData::Boolean(true)),
+            (vec![0xcc, 0xff], num(255.0)),
+            (vec![0xcd, 0xff, 0xfe], num(65534.0)),
+            (vec![0xce, 0xff, 0xff, 0xff, 0xff], num(4294967295.0)),
+            (
+                [&[0xcf][..], &(1u64 << 40).to_be_bytes()].concat(),
+                num((1u64 << 40) as f64),
+            ),
+            (vec![0xd0, 0x80], num(-128.0)),
+            (vec![0xd1, 0x80, 0x00], num(-32768.0)),
+            (
+                [&[0xd2][..], &(-100000i32).to_be_bytes()].concat(),
+                num(-100000.0),
+            ),
+            (
+                [&[0xd3][..], &(-(1i64 << 40)).to_be_bytes()].concat(),
+                num(-((1u64 << 40) as f64)),
+            ),
+            ([&[0xca][..], &1.5f32.to_be_bytes()].concat(), num(1.5)),
+            ([&[0xcb][..], &0.1f64.to_be_bytes()].concat(), num(0.1)),
+            (vec![0xa0], JsData::String(String::new())),
+            (vec![0xa2, b'h', b'i'], JsData::String("hi".into())),
+            (
+                [sized(0xd9, 1, 40), str_of(40).into_bytes()].concat(),
+                JsData::String(str_of(40)),
+            ),
+            (
+                [sized(0xda, 2, 300), str_of(300).into_bytes()].concat(),
+                JsData::String(str_of(300)),
+            ),
+            (
+                [sized(0xdb, 4, 70000), str_of(70000).into_bytes()].concat(),
+                JsData::String(str_of(70000)),
+            ),
+            (vec![0x90], JsData::Array(vec![])),
+            (
+                vec![0x92, 0x01, 0xc0],
+                JsData::Array(vec![num(1.0), JsData::Null]),
+            ),
+            (
+                [sized(0xdc, 2, 20), vec![0x01; 20]].concat(),
+                JsData::Array(vec![num(1.0); 20]),
+            ),
+            (
+                [sized(0xdd, 4, 2), vec![0x02, 0x03]].concat(),
+                JsData::Array(vec![num(2.0), num(3.0)]),
+            ),
+            (vec![0x80], JsData::Object(BTreeMap::new())),
+            (
+                vec![0x81, 0xa1, b'a', 0xc3],
+                JsData::Object([("a".into(), JsData::Boolean(true))].into()),
+            ),
+            (
+                [sized(0xde, 2, 1), vec![0xa1, b'b', 0x05]].concat(),
+                JsData::Object([("b".into(), num(5.0))].into()),
+            ),
+            (
+                [sized(0xdf, 4, 1), vec![0xa1, b'c', 0xa0]].concat(),
+                JsData::Object([("c".into(), JsData::String(String::new()))].into()),
+            ),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(to_data(&bytes), Ok(expected.clone()), "{bytes:02x?}");
+            assert_eq!(
+                from_slice::<JsData>(&bytes).map(JsData::into_json),
+                Ok(expected.into_json()),
+                "{bytes:02x?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_markers() {
+        // never used, bin 8, ext 8, fixext 1
+        for marker in [0xc1, 0xc4, 0xc7, 0xd4] {
+            assert!(to_data(&[marker, 0, 0, 0]).is_err());
+        }
+        // a map with a key that isn't a string
+        assert!(to_data(&[0x81, 0x01, 0x02]).is_err());
+        assert!(from_slice::<BTreeMap<String, u8>>(&[0x81, 0x01, 0x02]).is_err());
+        // invalid UTF-8
+        assert!(to_data(&[0xa1, 0xff]).is_err());
+    }
+
+    #[test]
+    fn tagged_values() {
+        fn tagged(tag: &str, v: Option<&str>) -> Vec<u8> {
+            let mut bytes = vec![if v.is_some() { 0x82 } else { 0x81 }, 0xa2, b'$', b'w'];
+            for s in [Some(tag), v.map(|_| "v"), v].into_iter().flatten() {
+                bytes.extend_from_slice(&[0xd9, s.len() as u8]);
+                bytes.extend_from_slice(s.as_bytes());
+            }
+            bytes
+        }
+        assert_eq!(to_data(&tagged("u", None)), Ok(JsData::Undefined));
+        let nan = to_data(&tagged("n", Some("NaN")));
+        assert!(matches!(nan, Ok(JsData::Number(n)) if n.is_nan()));
+        assert_eq!(
+            to_data(&tagged("n", Some("-Infinity"))),
+            Ok(JsData::Number(f64::NEG_INFINITY))
+        );
+        let zero = from_slice::<f64>(&tagged("n", Some("-0"))).unwrap();
+        assert!(zero == 0.0 && zero.is_sign_negative());
+        assert_eq!(
+            from_slice::<u128>(&tagged(
+                "b",
+                Some("340282366920938463463374607431768211455")
+            )),
+            Ok(u128::MAX)
+        );
+        assert_eq!(from_slice::<Option<u8>>(&tagged("u", None)), Ok(None));
+        assert_eq!(
+            from_slice::<Vec<Option<u8>>>(&[&[0x92, 0x01][..], &tagged("u", None)].concat()),
+            Ok(vec![Some(1), None])
+        );
+        // an object with a property named like the tag
+        let escaped = [
+            &[0x82, 0xa2, b'$', b'w', 0xa1, b'o', 0xa1, b'v', 0x91, 0x92][..],
+            &[0xa2, b'$', b'w', 0x07],
+        ]
+        .concat();
+        assert_eq!(
+            to_data(&escaped),
+            Ok(JsData::Object([("$w".into(), JsData::Number(7.0))].into()))
+        );
+    }
+
+    #[test]
+    fn deserializes_like_json() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Kind {
+            Plain,
+            Sized(u32),
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Event {
+            key: String,
+            x: f64,
+            count: u8,
+            tags: Vec<String>,
+            kind: Kind,
+            other: Kind,
+            missing: Option<()>,
+        }
+        let json = r#"{"key":"Enter","x":1,"count":2.0,"tags":["a"],"kind":"Plain","other":{"Sized":3},"ignored":[1,{"$w":"u"}],"missing":null}"#;
+        let mut msgpack = vec![0x88];
+        fn str(msgpack: &mut Vec<u8>, s: &str) {
+            msgpack.push(0xa0 | s.len() as u8);
+            msgpack.extend_from_slice(s.as_bytes());
+        }
+        str(&mut msgpack, "key");
+        str(&mut msgpack, "Enter");
+        str(&mut msgpack, "x");
+        msgpack.push(0x01);
+        str(&mut msgpack, "count");
+        msgpack.push(0xcb);
+        msgpack.extend_from_slice(&2.0f64.to_be_bytes());
+        str(&mut msgpack, "tags");
+        msgpack.push(0x91);
+        str(&mut msgpack, "a");
+        str(&mut msgpack, "kind");
+        str(&mut msgpack, "Plain");
+        str(&mut msgpack, "other");
+        msgpack.push(0x81);
+        str(&mut msgpack, "Sized");
+        msgpack.push(0x03);
+        str(&mut msgpack, "ignored");
+        msgpack.extend_from_slice(&[0x92, 0x01, 0x81, 0xa2, b'$', b'w', 0xa1, b'u']);
+        str(&mut msgpack, "missing");
+        msgpack.push(0xc0);
+
+        let from_json: Event = from_wire(Payload::Json(json)).unwrap();
+        let from_msgpack: Event = from_wire(Payload::MessagePack(&msgpack)).unwrap();
+        assert_eq!(from_json, from_msgpack);
+        assert_eq!(
+            JsData::from_wire(Payload::Json(json)).unwrap(),
+            JsData::from_wire(Payload::MessagePack(&msgpack)).unwrap()
+        );
+        assert_eq!(to_json(&msgpack).unwrap(), {
+            let mut value: serde_json::Value = serde_json::from_str(json).unwrap();
+            value["count"] = 2.0.into();
+            value
+        });
+    }
+
+    #[test]
+    fn errors_are_not_fatal_to_the_format() {
+        assert!(from_slice::<String>(&[0x01]).is_err());
+        assert!(from_slice::<()>(&[0x01]).is_err());
+        // more items than the tuple takes
+        assert!(from_slice::<(u8, u8)>(&[0x93, 0x01, 0x02, 0x03]).is_err());
+        assert!(to_data(&[0x01, 0x02]).is_err());
+        assert!(from_slice::<u8>(&[0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let whole = [
+            &[0x83, 0xa1, b'a', 0xcb][..],
+            &1.5f64.to_be_bytes(),
+            &[0xa1, b'b', 0xdc, 0x00, 0x02, 0xa2, b'h', b'i', 0xc3],
+            &[0xa1, b'c', 0xd9, 0x01, b'z'],
+        ]
+        .concat();
+        assert!(to_data(&whole).is_ok());
+        for len in 0..whole.len() {
+            let part = &whole[..len];
+            assert!(to_data(part).is_err(), "{len}");
+            assert!(to_json(part).is_err(), "{len}");
+            assert!(from_slice::<serde_json::Value>(part).is_err(), "{len}");
+        }
+        // lengths far beyond what's there don't allocate that much
+        assert!(to_data(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(from_slice::<Vec<u8>>(&[0xdf, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn max_depth() {
+        let nested = |depth: usize| [vec![0x91; depth], vec![0xc0]].concat();
+        assert!(to_data(&nested(MAX_DEPTH)).is_ok());
+        assert!(to_data(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(to_json(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(from_slice::<JsData>(&nested(MAX_DEPTH)).is_ok());
+        assert!(from_slice::<JsData>(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(from_slice::<serde::de::IgnoredAny>(&nested(100_000)).is_err());
+        let maps = [[0x81, 0xa1, b'a'].repeat(MAX_DEPTH + 1), vec![0xc0]].concat();
+        assert!(to_data(&maps).is_err());
+        assert!(from_slice::<serde_json::Value>(&maps).is_err());
+    }
+}
@@ -1,22 +1,27 @@
-use std::fmt::Write;
+use std::borrow::Cow;
 
 use crate::{
+    command::{Call, Callee, Command, Field, RawCode, Slot},
+    data::JsData,
+    ident::{JsIdent, JsPath},
+    internal::upcast_workaround::UpcastWorkaround,
     js::{object::JsObject, value::JsValue},
     js_cast::JsCast,
     link::{Browser, Error},
-    protocol::{GET, SET},
+    policy::{field_capability, normalize_global, raw_code_capability, Capability},
+    reply::Payload,
     retrieve::RetrieveFuture,
-    serialize::{ToJs, UseInJsCode, UseInJsCodeWriter},
+    serialize::{ToJs, UseInJsCode},
 };
 
 impl Browser {
     /// Call a standalone JavaScript function.
     ///
     /// ```rust
-    /// # use wsdom_core::Browser;
+    /// # use wsdom_core::{js_path, Browser};
     /// fn example(browser: Browser) {
     ///     let _return_value = browser.call_function(
-    ///         "alert",
+    ///         js_path!("alert"),
     ///         [&"hello world" as &_],
     ///         false
     ///     );
@@ -27,24 +32,29 @@ impl Browser {
     /// Instead, use the `wsdom` crate which provides mostly type-safe wrappers to the Web API.
     ///
     /// If you still want to use `call_function`,
-    /// be aware that the first argument (`function_name`) is NOT escaped.
-    /// Do NOT allow user-supplied function name.
+    /// be aware that everything on `window` is reachable this way, unless restricted by a [Policy](crate::Policy).
+    /// The function name is a [JsPath](crate::JsPath), so it can't inject code,
+    /// but a user-supplied one can still call any function.
     pub fn call_function<'a>(
         &'a self,
-        function_name: &'a str,
+        function_name: JsPath<'a>,
         args: impl IntoIterator<Item = &'a dyn UseInJsCode>,
         last_arg_variadic: bool,
     ) -> JsValue {
-        self.call_function_inner(&format_args!("{}", function_name), args, last_arg_variadic)
+        self.call_function_inner(
+            Callee::Function(function_name.as_str()),
+            args,
+            last_arg_variadic,
+        )
     }
 
     /// Call constructor for a class.
     ///
     /// ```rust
-    /// # use wsdom_core::Browser;
+    /// # use wsdom_core::{js_path, Browser};
     /// fn example(browser: Browser) {
     ///     let _regexp_object = browser.call_constructor(
-    ///         "RegExp",
+    ///         js_path!("RegExp"),
     ///         [&"hello" as &_],
     ///         false
     ///     );
@@ -55,41 +65,48 @@ impl Browser {
     /// Instead, use the `wsdom` crate which provides mostly type-safe wrappers to the Web API.
     ///
     /// If you still want to use `call_constructor`,
-    /// be aware that the first argument (`class_name`) is NOT escaped.
-    /// Do NOT allow user-supplied class name.
+    /// be aware that every class on `window` is reachable this way, unless restricted by a [Policy](crate::Policy).
+    /// The class name is a [JsPath](crate::JsPath), so it can't inject code,
+    /// but a user-supplied one can still construct any class.
     pub fn call_constructor<'a>(
         &'a self,
-        class_name: &'a str,
+        class_name: JsPath<'a>,
         args: impl IntoIterator<Item = &'a dyn UseInJsCode>,
         last_arg_variadic: bool,
     ) -> JsValue {
-        self.call_function_inner(&format_args!("new {}", class_name), args, last_arg_variadic)
+        self.call_function_inner(
+            Callee::Constructor(class_name.as_str()),
+            args,
+            last_arg_variadic,
+        )
     }
 
     fn call_function_inner<'a>(
         &'a self,
-        function: &std::fmt::Arguments<'_>,
+        callee: Callee<'a>,
         args: impl IntoIterator<Item = &'a dyn UseInJsCode>,
         last_arg_variadic: bool,
     ) -> JsValue {
+        let capability = match callee {
+            Callee::Function(path) => Some(Capability::Global(normalize_global(path).to_owned())),
+            Callee::Constructor(path) => {
+                Some(Capability::Constructor(normalize_global(path).to_owned()))
+            }
+            Callee::Method(_, name) => Some(Capability::Member(name.to_owned())),
+            Callee::Value(_) => None,
+        };
+        let call = Call {
+            callee,
+            args: args.into_iter().collect(),
+            spread: last_arg_variadic,
+        };
         let id = {
-            let mut link = self.0.lock().unwrap();
-            let out_id = link.get_new_id();
-            write!(link.raw_commands_buf(), "{SET}({out_id},{function}(").unwrap();
-            let mut iter = args.into_iter().peekable();
-            while let Some(arg) = iter.next() {
-                let arg = UseInJsCodeWriter(arg);
-                let res = if last_arg_variadic && iter.peek().is_none() {
-                    write!(link.raw_commands_buf(), "...{arg},")
-                } else {
-                    write!(link.raw_commands_buf(), "{arg},")
-                };
-                if let Err(e) = res {
-                    link.kill(Error::CommandSerialize(e));
-                }
+            let mut link = self.0.lock();
+            let out_id = link.new_slot_id();
+            if capability.is_none_or(|capability| link.permits(capability)) {
+                link.push_command(Command::Set(out_id, &call));
             }
-            write!(link.raw_commands_buf(), "));\n").unwrap();
-            link.wake_outgoing();
+            link.wake_outgoing_lazy();
             out_id
         };
         JsValue {
@@ -104,15 +121,11 @@ impl Browser {
     pub fn get_field(&self, base_obj: &dyn UseInJsCode, property: &dyn UseInJsCode) -> JsValue {
         let browser = self.clone();
         let id = {
-            let mut link = browser.0.lock().unwrap();
-            let out_id = link.get_new_id();
-            let base_obj = UseInJsCodeWriter(base_obj);
-            let property = UseInJsCodeWriter(property);
-            if let Err(e) = writeln!(
-                link.raw_commands_buf(),
-                "{SET}({out_id},({base_obj})[{property}]);"
-            ) {
-                link.kill(Error::CommandSerialize(e));
+            let mut link = browser.0.lock();
+            let capability = field_capability(link.key, base_obj, property);
+            let out_id = link.new_slot_id();
+            if capability.is_none_or(|capability| link.permits(capability)) {
+                link.push_command(Command::Set(out_id, &Field(base_obj, property)));
             }
             link.wake_outgoing_lazy();
             out_id
@@ -129,43 +142,57 @@ impl Browser {
         property: &dyn UseInJsCode,
         value: &dyn UseInJsCode,
     ) {
-        let mut link = self.0.lock().unwrap();
-        let (base_obj, property, value) = (
-            UseInJsCodeWriter(base_obj),
-            UseInJsCodeWriter(property),
-            UseInJsCodeWriter(value),
-        );
-        if let Err(e) = writeln!(link.raw_commands_buf(), "({base_obj})[{property}]={value};") {
-            link.kill(Error::CommandSerialize(e));
+        let mut link = self.0.lock();
+        let capability = field_capability(link.key, base_obj, property);
+        if !capability.is_none_or(|capability| link.permits(capability)) {
+            return;
         }
-        link.wake_outgoing();
+        link.push_command(Command::SetField(base_obj, property, value));
+        link.wake_outgoing_lazy();
     }
 
     /// Create a new value on the JavaScript side from a [ToJs] type.
     pub fn new_value<'a, T: JsCast>(&'a self, value: &'a dyn ToJs<T>) -> T {
-        let val = self.value_from_raw_code(format_args!("{}", UseInJsCodeWriter(value)));
-        JsCast::unchecked_from_js(val)
+        let id = {
+            let mut link = self.0.lock();
+            let out_id = link.new_slot_id();
+            link.push_command(Command::Set(out_id, UpcastWorkaround::new(value).cast()));
+            link.wake_outgoing_lazy();
+            out_id
+        };
+        JsCast::unchecked_from_js(JsValue {
+            id,
+            browser: self.clone(),
+        })
     }
 
     /// Executes arbitrary JavaScript code.
     ///
     /// Don't use this unless you really have to.
     pub fn run_raw_code<'a>(&'a self, code: std::fmt::Arguments<'a>) {
-        let mut link = self.0.lock().unwrap();
-        if let Err(e) = writeln!(link.raw_commands_buf(), "{{ {code} }}") {
-            link.kill(Error::CommandSerialize(e));
+        let mut link = self.0.lock();
+        if !link.permits(Capability::Global("eval".to_owned())) {
+            return;
         }
-        link.wake_outgoing();
+        link.push_command(Command::Raw(&code));
+        link.wake_outgoing_lazy();
     }
 
     /// Executes arbitrary JavaScript expression and return the result.
     ///
     /// Don't use this unless you really have to.
     pub fn value_from_raw_code<'a>(&'a self, code: std::fmt::Arguments<'a>) -> JsValue {
-        let mut link = self.0.lock().unwrap();
-        let out_id = link.get_new_id();
-        writeln!(link.raw_commands_buf(), "{SET}({out_id},{code});").unwrap();
-        link.wake_outgoing();
+        let mut link = self.0.lock();
+        let out_id = link.new_slot_id();
+        let text = code
+            .as_str()
+            .map_or_else(|| code.to_string().into(), Cow::Borrowed);
+        let permitted =
+            raw_code_capability(&text).is_none_or(|capability| link.permits(capability));
+        if permitted {
+            link.push_command(Command::Set(out_id, &RawCode(&text)));
+        }
+        link.wake_outgoing_lazy();
         JsValue {
             id: out_id,
             browser: self.to_owned(),
@@ -177,11 +204,14 @@ impl JsValue {
     pub(crate) fn retrieve_and_deserialize<U: serde::de::DeserializeOwned>(
         &self,
     ) -> RetrieveFuture<'_, U> {
-        RetrieveFuture::new(self.id, &self.browser.0)
+        RetrieveFuture::new(self)
     }
     /// Retrive this value from the JS side to the Rust side.
     /// Returns Future whose output is a [serde_json::Value].
     ///
+    /// What JSON can't represent is converted like [JsData::into_json] does;
+    /// use [retrieve_data](JsValue::retrieve_data) to keep it.
+    ///
     /// ```rust
     /// # use wsdom::Browser;
     /// # use wsdom::dom::HTMLInputElement;
@@ -190,9 +220,44 @@ impl JsValue {
     /// }
     /// ```
     pub fn retrieve_json(&self) -> RetrieveFuture<'_, serde_json::Value> {
-        self.retrieve_and_deserialize()
+        RetrieveFuture::with_decoder(self, json_from_wire)
+    }
+    /// Retrive this value from the JS side to the Rust side.
+    /// Returns Future whose output is a [JsData].
+    ///
+    /// Unlike JSON, [JsData] tells `undefined` and `null` apart,
+    /// and keeps `NaN`, `Infinity`, `-0`, BigInts, `Date`s, and `RegExp`s.
+    ///
+    /// ```rust
+    /// # use wsdom::Browser;
+    /// # use wsdom::dom::HTMLInputElement;
+    /// async fn example(input: &HTMLInputElement) {
+    ///     let _val = input.get_value().retrieve_data().await;
+    /// }
+    /// ```
+    pub fn retrieve_data(&self) -> RetrieveFuture<'_, JsData> {
+        RetrieveFuture::with_decoder(self, JsData::from_wire)
+    }
+    pub(crate) fn retrieve_and_deserialize_owned<U: serde::de::DeserializeOwned>(
+        self,
+    ) -> RetrieveFuture<'static, U> {
+        RetrieveFuture::new(self)
     }
+    /// Like [retrieve_json](JsValue::retrieve_json), but consumes this value
+    /// so that the returned Future is `'static`.
+    pub fn retrieve_json_owned(self) -> RetrieveFuture<'static, serde_json::Value> {
+        RetrieveFuture::with_decoder(self, json_from_wire)
+    }
+    /// Like [retrieve_data](JsValue::retrieve_data), but consumes this value
+    /// so that the returned Future is `'static`.
+    pub fn retrieve_data_owned(self) -> RetrieveFuture<'static, JsData> {
+        RetrieveFuture::with_decoder(self, JsData::from_wire)
+    }
+}
+fn json_from_wire(payload: Payload<'_>) -> Result<serde_json::Value, Error> {
+    JsData::from_wire(payload).map(JsData::into_json)
 }
+
 impl JsObject {
     /// Get a field value of in this object.
     ///
@@ -214,15 +279,11 @@ impl JsObject {
     pub fn js_get_field(&self, property: &dyn UseInJsCode) -> JsValue {
         let browser = self.browser.clone();
         let id = {
-            let mut link = browser.0.lock().unwrap();
-            let out_id = link.get_new_id();
-            let self_id = self.id;
-            let property = UseInJsCodeWriter(property);
-            if let Err(e) = writeln!(
-                link.raw_commands_buf(),
-                "{SET}({out_id},{GET}({self_id})[{property}]);"
-            ) {
-                link.kill(Error::CommandSerialize(e));
+            let mut link = browser.0.lock();
+            let capability = field_capability(link.key, self, property);
+            let out_id = link.new_slot_id();
+            if capability.is_none_or(|capability| link.permits(capability)) {
+                link.push_command(Command::Set(out_id, &Field(&Slot(self.id), property)));
             }
             link.wake_outgoing_lazy();
             out_id
@@ -247,16 +308,13 @@ impl JsObject {
     /// }
     /// ```
     pub fn js_set_field(&self, property: &dyn UseInJsCode, value: &dyn UseInJsCode) {
-        let self_id = self.id;
-        let mut link = self.browser.0.lock().unwrap();
-        let (property, value) = (UseInJsCodeWriter(property), UseInJsCodeWriter(value));
-        if let Err(e) = writeln!(
-            link.raw_commands_buf(),
-            "{GET}({self_id})[{property}]={value};"
-        ) {
-            link.kill(Error::CommandSerialize(e));
+        let mut link = self.browser.0.lock();
+        let capability = field_capability(link.key, self, property);
+        if !capability.is_none_or(|capability| link.permits(capability)) {
+            return;
         }
-        link.wake_outgoing();
+        link.push_command(Command::SetField(&Slot(self.id), property, value));
+        link.wake_outgoing_lazy();
     }
 
     /// Call a method on this object.
@@ -264,29 +322,28 @@ impl JsObject {
     /// Most types in WSDOM already come with safe Rust wrappers for their methods, so you should use those instead.
     ///
     /// ```rust
-    /// # use wsdom_core::Browser;
+    /// # use wsdom_core::{js_ident, Browser};
     /// fn example(browser: &Browser) {
     ///     let console = wsdom::dom::console(browser);
     ///     // you can call console.log like this
-    ///     console.js_call_method("log", [&"hello" as &_], false);
+    ///     console.js_call_method(js_ident!("log"), [&"hello" as &_], false);
     ///     
     ///     // but the better way is to use
     ///     wsdom::dom::console(&browser).log(&[&"Hello" as &_]);
     /// }
     /// ```
     ///
-    /// Be aware that the first argument (`method_name`) is NOT escaped.
+    /// The method name is a [JsIdent](crate::JsIdent), so it can't inject code.
     ///
     /// Set `last_arg_variadic` to `true` if you want to "spread" the last argument as `obj.method(arg1, arg2, ...arg3)`.
     pub fn js_call_method<'a>(
         &'a self,
-        method_name: &'a str,
+        method_name: JsIdent<'a>,
         args: impl IntoIterator<Item = &'a dyn UseInJsCode>,
         last_arg_variadic: bool,
     ) -> JsValue {
-        let self_id = self.id;
         self.browser.call_function_inner(
-            &format_args!("{GET}({self_id}).{method_name}"),
+            Callee::Method(self.id, method_name.as_str()),
             args,
             last_arg_variadic,
         )
@@ -299,12 +356,8 @@ impl JsObject {
         args: impl IntoIterator<Item = &'a dyn UseInJsCode>,
         last_arg_variadic: bool,
     ) -> JsValue {
-        let self_id = self.id;
-        self.browser.call_function_inner(
-            &format_args!("({GET}({self_id}))"),
-            args,
-            last_arg_variadic,
-        )
+        self.browser
+            .call_function_inner(Callee::Value(self.id), args, last_arg_variadic)
     }
 }
 
@@ -322,3 +375,48 @@ impl std::fmt::Debug for CommandSerializeFailed {
 }
 
 impl std::error::Error for CommandSerializeFailed {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use crate::{js_types::JsValue, Browser, JsData};
+
+    #[test]
+    fn retrieve_json_is_lossy_and_retrieve_data_is_not() {
+        let browser = Browser::new();
+        let value: JsValue = browser.new_value(&1);
+        let mut json = std::pin::pin!(value.retrieve_json());
+        let mut data = std::pin::pin!(value.retrieve_data());
+        assert!(json.as_mut().now_or_never().is_none());
+        assert!(data.as_mut().now_or_never().is_none());
+        let reply = r#"[{"$w":"u"},{"$w":"b","v":"7"}]"#;
+        browser.receive_incoming_message(format!("1:{reply}"));
+        browser.receive_incoming_message(format!("2:{reply}"));
+        assert_eq!(json.now_or_never(), Some(serde_json::json!([null, 7])));
+        assert_eq!(
+            data.now_or_never(),
+            Some(JsData::Array(vec![
+                JsData::Undefined,
+                JsData::BigInt("7".into())
+            ]))
+        );
+    }
+
+    #[test]
+    fn owned_retrievals_decode_the_same() {
+        fn owned<F: std::future::Future + 'static>(future: F) -> F {
+            future
+        }
+        let browser = Browser::new();
+        let value: JsValue = browser.new_value(&1);
+        let mut json = std::pin::pin!(owned(value.clone().retrieve_json_owned()));
+        let mut data = std::pin::pin!(owned(value.retrieve_data_owned()));
+        assert!(json.as_mut().now_or_never().is_none());
+        assert!(data.as_mut().now_or_never().is_none());
+        browser.receive_incoming_message(r#"1:{"$w":"u"}"#.to_owned());
+        browser.receive_incoming_message(r#"2:{"$w":"u"}"#.to_owned());
+        assert_eq!(json.now_or_never(), Some(serde_json::Value::Null));
+        assert_eq!(data.now_or_never(), Some(JsData::Undefined));
+    }
+}
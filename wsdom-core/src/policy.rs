@@ -0,0 +1,358 @@
+use serde::Serialize;
+
+use crate::command::{is_path, CodeStyle, CommandFormat};
+use crate::link::{Browser, BrowserInternal, Error};
+use crate::retrieve::Projection;
+use crate::serialize::{JsWriter, UseInJsCode};
+
+/// Which globals, constructors and members a [Browser] may use.
+///
+/// Everything in the web page is reachable from WSDOM by default,
+/// including `eval`, `fetch` and `document.cookie`.
+/// Attach a Policy with [Browser::set_policy] to narrow that down.
+/// Anything the policy forbids kills the Browser with [Error::PolicyViolation]
+/// instead of being sent to the client.
+///
+/// Rules match names, not values:
+/// -   globals are dotted paths like `fetch`, `Math.random` or `document`
+///     (a leading `window.`, `globalThis.` or `self.` is ignored).
+///     Raw code ([Browser::run_raw_code] and the like) counts as the global `eval`,
+///     except for plain paths like `document`.
+/// -   constructors are class names like `WebSocket` or `Worker`.
+/// -   members are property and method names like `cookie` or `innerHTML`, on any object.
+///
+/// A pattern is a name, `*` for everything, or a prefix like `Math.*`.
+/// Denying wins over allowing; names matched by neither get the default.
+/// Denied members are denied in global paths too (`deny_member("cookie")` covers `document.cookie`),
+/// and denied globals are denied as members too (`deny_global("eval")` covers `window.eval`),
+/// since any object may turn out to be `window`.
+///
+/// With [CommandFormat::Ops], the client is sent the policy and enforces it too.
+///
+/// ## What a Policy can't stop
+///
+/// A policy checks the names WSDOM uses, not what the page does with them.
+/// -   Arguments are not checked, so to keep the page from talking to other origins,
+///     deny `fetch` and friends altogether.
+/// -   Denying `eval` only stops WSDOM's raw code. JS has other ways to run a string as code:
+///     the `Function` constructor (also reachable as the `constructor` member of any function),
+///     `setTimeout` and `setInterval` with a string, `<script>` elements, `on*` attributes
+///     set through `innerHTML` or `setAttribute`, and `javascript:` URLs.
+///     Deny those you can, but only the page's Content-Security-Policy closes them all.
+/// -   Member rules apply to the names WSDOM reads, not to what getters and methods do inside.
+///
+/// ```rust
+/// # use wsdom_core::{Browser, Policy};
+/// fn example(browser: &Browser) {
+///     browser.set_policy(
+///         Policy::allow_all()
+///             .deny_global("eval")
+///             .deny_global("fetch")
+///             .deny_constructor("XMLHttpRequest")
+///             .deny_constructor("WebSocket")
+///             .deny_global("Function")
+///             .deny_constructor("Function")
+///             .deny_member("constructor")
+///             .deny_member("cookie"),
+///     );
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct Policy {
+    #[serde(rename = "g")]
+    globals: Rules,
+    #[serde(rename = "c")]
+    constructors: Rules,
+    #[serde(rename = "m")]
+    members: Rules,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Rules {
+    #[serde(rename = "d")]
+    default: bool,
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+/// Something a [Policy] can allow or forbid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capability {
+    /// Reading, writing or calling a global, like `fetch` or `document`.
+    Global(String),
+    /// Constructing a class, like `WebSocket`.
+    Constructor(String),
+    /// Reading, writing or calling a member of an object, like `cookie`.
+    Member(String),
+    /// A member whose name is computed on the JS side, so can't be checked in Rust.
+    ///
+    /// This is allowed only if every member is, and no global is denied (as it may name one),
+    /// except with [CommandFormat::Ops], where the client checks the actual name.
+    ComputedMember,
+}
+
+impl Rules {
+    fn new(default: bool) -> Self {
+        Self {
+            default,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+    fn permits(&self, name: &str) -> bool {
+        !self.denies(name) && (self.default || self.allow.iter().any(|p| matches(p, name)))
+    }
+    fn denies(&self, name: &str) -> bool {
+        self.deny.iter().any(|p| matches(p, name))
+    }
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    pattern == "*"
+        || pattern == name
+        || pattern
+            .strip_suffix('*')
+            .is_some_and(|prefix| prefix.ends_with('.') && name.starts_with(prefix))
+}
+
+impl Policy {
+    /// A policy allowing everything not explicitly denied.
+    pub fn allow_all() -> Self {
+        Self {
+            globals: Rules::new(true),
+            constructors: Rules::new(true),
+            members: Rules::new(true),
+        }
+    }
+    /// A policy denying everything not explicitly allowed.
+    pub fn deny_all() -> Self {
+        Self {
+            globals: Rules::new(false),
+            constructors: Rules::new(false),
+            members: Rules::new(false),
+        }
+    }
+    pub fn allow_global(mut self, pattern: impl Into<String>) -> Self {
+        self.globals.allow.push(pattern.into());
+        self
+    }
+    pub fn deny_global(mut self, pattern: impl Into<String>) -> Self {
+        self.globals.deny.push(pattern.into());
+        self
+    }
+    pub fn allow_constructor(mut self, pattern: impl Into<String>) -> Self {
+        self.constructors.allow.push(pattern.into());
+        self
+    }
+    pub fn deny_constructor(mut self, pattern: impl Into<String>) -> Self {
+        self.constructors.deny.push(pattern.into());
+        self
+    }
+    pub fn allow_member(mut self, pattern: impl Into<String>) -> Self {
+        self.members.allow.push(pattern.into());
+        self
+    }
+    pub fn deny_member(mut self, pattern: impl Into<String>) -> Self {
+        self.members.deny.push(pattern.into());
+        self
+    }
+    /// Whether this policy allows the capability.
+    pub fn permits(&self, capability: &Capability) -> bool {
+        match capability {
+            Capability::Global(path) => {
+                self.globals.permits(path)
+                    && !path
+                        .split('.')
+                        .skip(1)
+                        .any(|name| self.members.denies(name))
+            }
+            Capability::Constructor(path) => self.constructors.permits(path),
+            Capability::Member(name) => self.members.permits(name) && !self.globals.denies(name),
+            Capability::ComputedMember => {
+                self.members.default && self.members.deny.is_empty() && self.globals.deny.is_empty()
+            }
+        }
+    }
+}
+
+impl Browser {
+    /// Restrict what this Browser may use. See [Policy].
+    ///
+    /// Set this before doing anything with the Browser,
+    /// so that the client receives it before any other command.
+    pub fn set_policy(&self, policy: Policy) {
+        let mut link = self.0.lock();
+        link.policy = Some(policy);
+        link.send_policy();
+        link.wake_outgoing();
+    }
+    /// The current [Policy], if one was set.
+    pub fn policy(&self) -> Option<Policy> {
+        self.0.lock().policy.clone()
+    }
+}
+
+impl BrowserInternal {
+    /// Whether the policy allows the capability, killing the Browser if not.
+    pub(crate) fn permits(&mut self, capability: Capability) -> bool {
+        let Some(policy) = self.policy.as_ref() else {
+            return true;
+        };
+        if policy.permits(&capability)
+            || (capability == Capability::ComputedMember && self.format == CommandFormat::Ops)
+        {
+            true
+        } else {
+            self.kill(Error::PolicyViolation(capability));
+            false
+        }
+    }
+    /// Whether the policy allows reading the properties a [Projection] reads.
+    pub(crate) fn permits_projection(&mut self, projection: Option<&Projection>) -> bool {
+        match projection {
+            Some(Projection::Paths(paths)) => paths
+                .iter()
+                .flat_map(|path| path.split('.'))
+                .all(|name| self.permits(Capability::Member(name.to_owned()))),
+            Some(Projection::Snapshot {
+                properties: Some(properties),
+                ..
+            }) => properties
+                .iter()
+                .all(|name| self.permits(Capability::Member(name.to_owned()))),
+            _ => true,
+        }
+    }
+    /// Give the policy to the client, if it can enforce it.
+    pub(crate) fn send_policy(&mut self) {
+        if let (CommandFormat::Ops, Some(policy)) = (self.format, self.policy.clone()) {
+            self.push_command(crate::command::Command::Policy(&policy));
+        }
+    }
+}
+
+/// `globalThis.document` and `document` are the same global.
+pub(crate) fn normalize_global(mut path: &str) -> &str {
+    while let Some(rest) = ["window.", "globalThis.", "self."]
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix))
+    {
+        path = rest;
+    }
+    path
+}
+
+/// The capability needed for raw code used as an expression.
+pub(crate) fn raw_code_capability(code: &str) -> Option<Capability> {
+    match code {
+        "null" | "undefined" => None,
+        code if is_path(code) => Some(Capability::Global(normalize_global(code).to_owned())),
+        _ => Some(Capability::Global("eval".to_owned())),
+    }
+}
+
+/// The capability needed for `object[property]`.
+///
+/// `None` if either is a value of another Browser, which the command then fails on.
+pub(crate) fn field_capability(
+    key: usize,
+    object: &dyn UseInJsCode,
+    property: &dyn UseInJsCode,
+) -> Option<Capability> {
+    let property = to_code(key, property)?;
+    let name = match serde_json::from_str(&property) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(serde_json::Value::Number(index)) => index.to_string(),
+        _ => return Some(Capability::ComputedMember),
+    };
+    let object = to_code(key, object)?;
+    Some(if is_path(&object) && object != "undefined" {
+        Capability::Global(normalize_global(&format!("{object}.{name}")).to_owned())
+    } else {
+        Capability::Member(name)
+    })
+}
+
+/// The value as plain JS code, or `None` if it's from another Browser.
+fn to_code(key: usize, value: &dyn UseInJsCode) -> Option<String> {
+    let mut code = String::new();
+    let mut writer = JsWriter::new(
+        &mut code,
+        CommandFormat::Code,
+        CodeStyle::Readable,
+        key,
+        None,
+    );
+    let result = value.serialize_to(&mut writer);
+    if writer.wrong_browser {
+        return None;
+    }
+    match result {
+        Ok(()) => Some(code),
+        // not a name or a literal, whatever it was
+        Err(_) => Some(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capability, Policy, Rules};
+    use crate::{for_macro::RawCodeImmediate, Browser, Error};
+
+    fn rules(default: bool, allow: &[&str], deny: &[&str]) -> Rules {
+        Rules {
+            default,
+            allow: allow.iter().map(|p| p.to_string()).collect(),
+            deny: deny.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let rules = rules(false, &["Math.*", "fetch"], &["Math.random"]);
+        assert!(rules.permits("fetch"));
+        assert!(rules.permits("Math.floor"));
+        assert!(!rules.permits("Math.random"));
+        assert!(!rules.permits("Math"));
+        assert!(!rules.permits("Mathematics.x"));
+        assert!(!rules.permits("fetchLater"));
+    }
+
+    #[test]
+    fn deny_wins_and_default_applies() {
+        assert!(!rules(true, &["*"], &["*"]).permits("x"));
+        assert!(rules(true, &[], &["y"]).permits("x"));
+        assert!(!rules(false, &[], &[]).permits("x"));
+    }
+
+    #[test]
+    fn denied_members_are_denied_in_global_paths() {
+        let policy = Policy::allow_all().deny_member("cookie");
+        assert!(!policy.permits(&Capability::Global("document.cookie".into())));
+        assert!(!policy.permits(&Capability::Member("cookie".into())));
+        assert!(policy.permits(&Capability::Global("document.title".into())));
+        // a global named like a denied member is not a member
+        assert!(policy.permits(&Capability::Global("cookie".into())));
+    }
+
+    #[test]
+    fn denied_globals_are_denied_as_members() {
+        let policy = Policy::allow_all().deny_global("eval");
+        assert!(!policy.permits(&Capability::Global("eval".into())));
+        assert!(!policy.permits(&Capability::Member("eval".into())));
+        assert!(!policy.permits(&Capability::ComputedMember));
+        assert!(policy.permits(&Capability::Member("evaluate".into())));
+        assert!(Policy::allow_all().permits(&Capability::ComputedMember));
+    }
+
+    #[test]
+    fn fields_of_globals_are_checked_as_members() {
+        let browser = Browser::new();
+        browser.set_policy(Policy::allow_all().deny_member("cookie"));
+        let _cookie = browser.get_field(&RawCodeImmediate("window.document"), &"cookie");
+        assert!(matches!(
+            browser.take_error(),
+            Some(Error::PolicyViolation(Capability::Global(path))) if path == "document.cookie"
+        ));
+    }
+}
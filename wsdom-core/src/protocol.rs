@@ -1,6 +1,33 @@
+/// Version of the wire protocol between this library and the JS client.
+///
+/// The JS client announces its version when it connects; clients with a different version are rejected.
+/// See [ClientInfo](crate::ClientInfo).
+pub const PROTOCOL_VERSION: u32 = 1;
+
 // function names
 
 pub const GET: &str = "_w.g"; // GET(Id) returns the value at memory slot Id
 pub const DEL: &str = "_w.d"; // DEL(Id) removes the value at memory slot Id
+pub const DEL_LIST: &str = "_w.D"; // DEL_LIST([Id]) removes the values at the memory slots
+pub const DEL_RANGE: &str = "_w.R"; // DEL_RANGE(Lo, Hi) removes the values at memory slots Lo to Hi, inclusive
 pub const SET: &str = "_w.s"; // SET(Id, Value) sets the value at memory slot Id
 pub const REP: &str = "_w.r"; // REP(Id, Value) sends the value back as id:json(value)
+pub const SNAP: &str = "_w.n"; // SNAP(Value, MaxDepth, Properties) makes a cycle-free copy of the value for REP
+pub const TOKEN: &str = "_w.t"; // TOKEN(Token) gives the client the token for resuming the session
+pub const ACK: &str = "_w.k"; // ACK(Count) tells the client how many of its messages have been received
+pub const INTERN: &str = "_w.i"; // INTERN(Token, String) gives the string a token, and returns it
+pub const INTERNED: &str = "_w.I"; // INTERNED(Token) returns the string with the token
+pub const BINARY_REPLIES: &str = "_w.B"; // BINARY_REPLIES(On) makes REP send MessagePack in binary messages (or JSON again); see `reply`
+pub const RAW: &str = "_w.x"; // RAW(Code) runs raw code from the user with only `_w` in scope, not the helpers of `CodeStyle::Compact`
+pub const RAW_EXPR: &str = "_w.e"; // RAW_EXPR(Code) evaluates raw code from the user as an expression, the same way
+
+// code in `CodeStyle::Compact` starts with `;`, and calls the functions above without `_w.`
+
+// wire format of replies
+
+pub const TAG: &str = "$w"; // replies encode values JSON can't represent as {"$w":tag,"v":payload}
+
+// control messages from the client; other messages are replies in the form id:json
+
+pub const CLIENT_HELLO: &str = "h:"; // h:Token:Count:Info sent on every connect; Count is how many messages the client has run, Info is JSON ClientInfo
+pub const CLIENT_ACK: &str = "a:"; // a:Count acknowledges that the client has run Count messages
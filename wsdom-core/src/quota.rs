@@ -0,0 +1,358 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use crate::link::{Browser, BrowserInternal, Error};
+
+/// Limits on what a session may use, to protect the server from misbehaving clients and apps.
+///
+/// Set with [Browser::set_quotas]. Every limit is off by default;
+/// [Quotas::strict] has limits suitable for many anonymous sessions.
+///
+/// When a limit is broken, the Browser dies with [Error::QuotaExceeded]
+/// (and integration libraries close the connection),
+/// unless [close_on_breach](Quotas::close_on_breach) is `false`.
+#[derive(Debug, Clone)]
+pub struct Quotas {
+    /// How many JS values may be held at once. Each [JsValue](crate::js_types::JsValue) holds one.
+    pub max_live_values: Option<usize>,
+    /// How many bytes of commands may wait to be sent to the client,
+    /// including messages sent again to a client that resumed its session.
+    pub max_outgoing_bytes: Option<usize>,
+    /// How long a message from the client may be, in bytes.
+    pub max_incoming_message_bytes: Option<usize>,
+    /// How many messages the client may send per second.
+    ///
+    /// Hellos and acks, which the client sends on its own to keep the connection going, don't count.
+    pub max_incoming_messages_per_second: Option<u32>,
+    /// How many retrievals and [Callback](crate::callback::Callback)s may wait for the client at once.
+    pub max_pending_retrievals: Option<usize>,
+    /// Whether breaking a limit kills the Browser.
+    ///
+    /// If `false`, whatever broke the limit is refused instead:
+    /// the client's message is dropped, or the command is not sent
+    /// (so the new value is `undefined`, or the retrieval never completes).
+    /// The error is then returned by [Browser::take_quota_error].
+    pub close_on_breach: bool,
+}
+
+/// A limit in [Quotas].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    LiveValues,
+    OutgoingBytes,
+    IncomingMessageBytes,
+    IncomingMessagesPerSecond,
+    PendingRetrievals,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Self {
+            max_live_values: None,
+            max_outgoing_bytes: None,
+            max_incoming_message_bytes: None,
+            max_incoming_messages_per_second: None,
+            max_pending_retrievals: None,
+            close_on_breach: true,
+        }
+    }
+}
+
+impl Quotas {
+    /// Limits generous enough for typical apps, but small enough to host many sessions:
+    /// 100 000 live values, 16 MiB of outgoing commands, 1 MiB messages,
+    /// 1000 messages per second, and 10 000 pending retrievals.
+    pub fn strict() -> Self {
+        Self {
+            max_live_values: Some(100_000),
+            max_outgoing_bytes: Some(16 << 20),
+            max_incoming_message_bytes: Some(1 << 20),
+            max_incoming_messages_per_second: Some(1000),
+            max_pending_retrievals: Some(10_000),
+            close_on_breach: true,
+        }
+    }
+}
+
+/// Usage counted against the [Quotas].
+#[derive(Debug, Default)]
+pub(crate) struct QuotaState {
+    quotas: Quotas,
+    live_values: usize,
+    /// Slots of values whose Set was refused, which don't need deleting.
+    unset: HashSet<u64>,
+    window_start: Option<Instant>,
+    window_messages: u32,
+    breach: Option<Quota>,
+}
+
+impl Browser {
+    /// Limit what this Browser may use. See [Quotas].
+    pub fn set_quotas(&self, quotas: Quotas) {
+        self.0.lock().quota.quotas = quotas;
+    }
+    /// The current [Quotas].
+    pub fn quotas(&self) -> Quotas {
+        self.0.lock().quota.quotas.clone()
+    }
+    /// The last [Error::QuotaExceeded] that didn't kill the Browser,
+    /// because [Quotas::close_on_breach] is `false`.
+    pub fn take_quota_error(&self) -> Option<Error> {
+        let mut link = self.0.lock();
+        link.quota.breach.take().map(Error::QuotaExceeded)
+    }
+}
+
+impl BrowserInternal {
+    fn breach(&mut self, quota: Quota) {
+        if self.quota.quotas.close_on_breach {
+            self.kill(Error::QuotaExceeded(quota));
+        } else {
+            self.quota.breach = Some(quota);
+        }
+    }
+    /// Whether another value may be created.
+    pub(crate) fn allows_new_value(&mut self) -> bool {
+        let allowed = self
+            .quota
+            .quotas
+            .max_live_values
+            .is_none_or(|max| self.quota.live_values < max);
+        if allowed {
+            self.quota.live_values += 1;
+        } else {
+            self.breach(Quota::LiveValues);
+        }
+        allowed
+    }
+    pub(crate) fn value_deleted(&mut self) {
+        self.quota.live_values = self.quota.live_values.saturating_sub(1);
+    }
+    /// Remember that the value in the slot was never set, and so isn't counted.
+    pub(crate) fn value_refused(&mut self, id: u64) {
+        self.quota.unset.insert(id);
+    }
+    /// Whether the value was [refused](Self::value_refused), forgetting it if so.
+    pub(crate) fn forget_refused(&mut self, id: u64) -> bool {
+        self.quota.unset.remove(&id)
+    }
+    /// Whether `len` bytes may wait to be sent.
+    pub(crate) fn allows_outgoing(&mut self, len: usize) -> bool {
+        let allowed = self
+            .quota
+            .quotas
+            .max_outgoing_bytes
+            .is_none_or(|max| len <= max);
+        if !allowed {
+            self.breach(Quota::OutgoingBytes);
+        }
+        allowed
+    }
+    /// Whether another retrieval may wait for the client.
+    pub(crate) fn allows_retrieval(&mut self) -> bool {
+        let allowed = self
+            .quota
+            .quotas
+            .max_pending_retrievals
+            .is_none_or(|max| self.retrievals.len() < max);
+        if !allowed {
+            self.breach(Quota::PendingRetrievals);
+        }
+        allowed
+    }
+    /// Whether a message from the client should be handled.
+    ///
+    /// `rated` is whether it counts against the rate limit.
+    pub(crate) fn allows_incoming(&mut self, len: usize, rated: bool) -> bool {
+        let quotas = &self.quota.quotas;
+        if quotas
+            .max_incoming_message_bytes
+            .is_some_and(|max| len > max)
+        {
+            self.breach(Quota::IncomingMessageBytes);
+            return false;
+        }
+        if let Some(max) = quotas.max_incoming_messages_per_second.filter(|_| rated) {
+            let now = Instant::now();
+            let state = &mut self.quota;
+            match state.window_start {
+                Some(start) if now.duration_since(start) < Duration::from_secs(1) => {
+                    state.window_messages += 1;
+                }
+                _ => {
+                    state.window_start = Some(now);
+                    state.window_messages = 1;
+                }
+            }
+            if state.window_messages > max {
+                self.breach(Quota::IncomingMessagesPerSecond);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{FutureExt, StreamExt};
+
+    use super::{Quota, Quotas};
+    use crate::{js_types::*, Browser, Error, PROTOCOL_VERSION};
+
+    fn refusing(quotas: Quotas) -> Browser {
+        let browser = Browser::new();
+        browser.set_quotas(Quotas {
+            close_on_breach: false,
+            ..quotas
+        });
+        browser
+    }
+
+    fn breached(browser: &Browser) -> Option<Quota> {
+        assert!(browser.take_error().is_none());
+        match browser.take_quota_error() {
+            Some(Error::QuotaExceeded(quota)) => Some(quota),
+            _ => None,
+        }
+    }
+
+    fn hello() -> String {
+        format!("h::0:{{\"version\":{PROTOCOL_VERSION},\"features\":[\"eval\"]}}")
+    }
+
+    #[test]
+    fn refused_values_are_not_deleted_or_uncounted() {
+        let browser = refusing(Quotas {
+            max_live_values: Some(1),
+            ..Default::default()
+        });
+        let kept: JsValue = browser.new_value(&1);
+        let refused: JsValue = browser.new_value(&2);
+        assert_eq!(breached(&browser), Some(Quota::LiveValues));
+        drop(refused);
+        assert_eq!(browser.0.lock().commands_buf, "_w.s(1,1);\n");
+        // `kept` still counts
+        let _refused: JsValue = browser.new_value(&3);
+        assert_eq!(breached(&browser), Some(Quota::LiveValues));
+        drop(kept);
+        let _value: JsValue = browser.new_value(&4);
+        assert_eq!(breached(&browser), None);
+    }
+
+    #[test]
+    fn outgoing_bytes() {
+        let browser = refusing(Quotas {
+            max_outgoing_bytes: Some(12),
+            ..Default::default()
+        });
+        let kept: JsValue = browser.new_value(&1);
+        let refused: JsValue = browser.new_value(&2);
+        assert_eq!(breached(&browser), Some(Quota::OutgoingBytes));
+        drop(refused);
+        assert_eq!(browser.0.lock().commands_buf, "_w.s(1,1);\n");
+        assert_eq!(browser.0.lock().quota.live_values, 1);
+        drop(kept);
+        assert_eq!(browser.0.lock().quota.live_values, 0);
+    }
+
+    #[test]
+    fn refused_deletes_keep_the_slot() {
+        let browser = refusing(Quotas::default());
+        let value: JsValue = browser.new_value(&1);
+        assert!(browser.clone().next().now_or_never().is_some());
+        browser.set_quotas(Quotas {
+            max_outgoing_bytes: Some(1),
+            close_on_breach: false,
+            ..Default::default()
+        });
+        drop(value);
+        assert_eq!(breached(&browser), Some(Quota::OutgoingBytes));
+        assert_eq!(browser.0.lock().commands_buf, "");
+        browser.set_quotas(Quotas::default());
+        // slot 1 still holds the value on the client
+        let _value: JsValue = browser.new_value(&2);
+        assert_eq!(browser.0.lock().commands_buf, "_w.s(2,2);\n");
+    }
+
+    #[test]
+    fn outgoing_bytes_count_resent_messages() {
+        let browser = Browser::new_resumable(1 << 20);
+        let _value: JsValue = browser.new_value(&1);
+        let sent = browser.clone().next().now_or_never().flatten().unwrap();
+        // the client reconnects without having run it
+        let token = browser.session_token().unwrap();
+        browser.receive_incoming_message(format!(
+            "h:{token}:0:{{\"version\":{PROTOCOL_VERSION},\"features\":[\"eval\"]}}"
+        ));
+        let waiting = browser.0.lock().outgoing_len();
+        assert!(waiting >= sent.len());
+        browser.set_quotas(Quotas {
+            max_outgoing_bytes: Some(waiting + "_w.s(2,2);\n".len()),
+            close_on_breach: false,
+            ..Default::default()
+        });
+        let _kept: JsValue = browser.new_value(&2);
+        assert_eq!(breached(&browser), None);
+        let _refused: JsValue = browser.new_value(&3);
+        assert_eq!(breached(&browser), Some(Quota::OutgoingBytes));
+    }
+
+    #[test]
+    fn pending_retrievals() {
+        let browser = refusing(Quotas {
+            max_pending_retrievals: Some(0),
+            ..Default::default()
+        });
+        let value: JsNumber = browser.new_value(&1);
+        assert!(value.retrieve_float().now_or_never().is_none());
+        assert_eq!(breached(&browser), Some(Quota::PendingRetrievals));
+    }
+
+    #[test]
+    fn incoming_message_bytes() {
+        let browser = refusing(Quotas {
+            max_incoming_message_bytes: Some(100),
+            ..Default::default()
+        });
+        browser.receive_incoming_message("x".repeat(101));
+        assert_eq!(breached(&browser), Some(Quota::IncomingMessageBytes));
+        browser.receive_incoming_message(hello());
+        assert_eq!(breached(&browser), None);
+        assert!(browser.client_info().is_some());
+    }
+
+    #[test]
+    fn incoming_messages_per_second() {
+        let browser = refusing(Quotas {
+            max_incoming_messages_per_second: Some(1),
+            ..Default::default()
+        });
+        browser.receive_incoming_message(hello());
+        browser.receive_incoming_message("a:0".to_owned());
+        browser.receive_incoming_message("[1,1]".to_owned());
+        assert_eq!(breached(&browser), None);
+        // hellos and acks don't count
+        browser.receive_incoming_message(hello());
+        browser.receive_incoming_message("a:0".to_owned());
+        assert_eq!(breached(&browser), None);
+        browser.receive_incoming_message("[1,2]".to_owned());
+        assert_eq!(breached(&browser), Some(Quota::IncomingMessagesPerSecond));
+    }
+
+    #[test]
+    fn breaches_kill_by_default() {
+        let browser = Browser::new();
+        browser.set_quotas(Quotas {
+            max_live_values: Some(0),
+            ..Default::default()
+        });
+        let _value: JsValue = browser.new_value(&1);
+        assert!(matches!(
+            browser.take_error(),
+            Some(Error::QuotaExceeded(Quota::LiveValues))
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+/*!
+Replies from the JS client, as JSON text or as MessagePack in binary messages.
+
+Text replies are `id:json`. Binary replies have a fixed header instead of a separator
+(the byte `r`, then the id as a big-endian `u64`), followed by the value in MessagePack.
+Either way, the value is the same tree, with the same tags for what JSON can't represent.
+*/
+
+use crate::command::Command;
+use crate::link::{Browser, BrowserInternal, Error};
+use crate::msgpack;
+
+/// How the JS client sends back retrieved values.
+///
+/// Set with [Browser::set_reply_format].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplyFormat {
+    /// `id:json` text messages.
+    #[default]
+    Json,
+    /// Binary messages with a typed header and the value in MessagePack,
+    /// which is faster to write and read than JSON, especially for numbers.
+    ///
+    /// Only clients that announce the `"msgpack"` feature use it; others keep sending JSON.
+    /// The transport must hand binary messages to [Browser::receive_incoming_binary_message]
+    /// (the integration libraries do).
+    MessagePack,
+}
+
+impl Browser {
+    /// Choose how the JS client sends back retrieved values. See [ReplyFormat].
+    pub fn set_reply_format(&self, format: ReplyFormat) {
+        let mut link = self.0.lock();
+        if link.reply_format != format {
+            link.reply_format = format;
+            link.send_reply_format();
+        }
+    }
+    /// The current [ReplyFormat].
+    pub fn reply_format(&self) -> ReplyFormat {
+        self.0.lock().reply_format
+    }
+    /// Receive a binary message sent from the WSDOM JS client.
+    ///
+    /// Like [receive_incoming_message](Browser::receive_incoming_message),
+    /// this is only needed if you pass messages between the WebSocket and the Browser yourself.
+    pub fn receive_incoming_binary_message(&self, message: Vec<u8>) {
+        self.0.lock().receive_binary(message);
+    }
+}
+
+impl BrowserInternal {
+    /// Tell the client which format to reply in, if it can reply in MessagePack at all.
+    pub(crate) fn send_reply_format(&mut self) {
+        let supported = self
+            .client_info
+            .as_ref()
+            .is_some_and(|info| info.supports("msgpack"));
+        if supported {
+            let binary = self.reply_format == ReplyFormat::MessagePack;
+            self.push_command(Command::BinaryReplies(binary));
+            self.wake_outgoing_lazy();
+        }
+    }
+}
+
+/// First byte of a binary reply.
+const BINARY_REPLY: u8 = b'r';
+/// Length of a binary reply's header: the kind and the id.
+const BINARY_HEADER: usize = 1 + 8;
+
+/// A reply, kept until its retrieval is polled.
+#[derive(Debug)]
+pub(crate) enum Reply {
+    /// `id:json`
+    Text(String),
+    /// The header, then MessagePack.
+    Binary(Vec<u8>),
+}
+
+impl Default for Reply {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl Reply {
+    /// The id of the retrieval this replies to.
+    pub(crate) fn id(&self) -> Option<u64> {
+        match self {
+            Self::Text(message) => message.split_once(':')?.0.parse().ok(),
+            Self::Binary(message) => match message.get(..BINARY_HEADER)?.split_first()? {
+                (&BINARY_REPLY, id) => Some(u64::from_be_bytes(id.try_into().ok()?)),
+                _ => None,
+            },
+        }
+    }
+    /// The value sent back.
+    pub(crate) fn payload(&self) -> Payload<'_> {
+        match self {
+            Self::Text(message) => {
+                Payload::Json(message.split_once(':').map_or("", |(_, json)| json))
+            }
+            Self::Binary(message) => {
+                Payload::MessagePack(message.get(BINARY_HEADER..).unwrap_or(&[]))
+            }
+        }
+    }
+}
+
+/// The value in a [Reply].
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Payload<'a> {
+    Json(&'a str),
+    MessagePack(&'a [u8]),
+}
+
+impl Payload<'_> {
+    /// Parse the value as it was written, tags and all.
+    pub(crate) fn parse(self) -> Result<serde_json::Value, Error> {
+        match self {
+            Self::Json(json) => serde_json::from_str(json).map_err(Error::DataDeserialize),
+            Self::MessagePack(bytes) => {
+                msgpack::to_json(bytes).map_err(Error::MessagePackDeserialize)
+            }
+        }
+    }
+}
@@ -1,50 +1,174 @@
 use std::marker::PhantomData;
-use std::sync::Mutex;
-use std::{fmt::Write, future::Future, pin::Pin, task::Poll};
+use std::{future::Future, pin::Pin, task::Poll};
 
 use serde::de::DeserializeOwned;
 
-use crate::link::{BrowserInternal, Error, RetrievalState};
-use crate::protocol::{GET, REP};
+use crate::command::{Command, Projected};
+use crate::js::value::JsValue;
+use crate::link::{Browser, Error, RetrievalState};
+use crate::reply::{Payload, Reply};
+use crate::timeout::Timeout;
 
 /// A [Future] for retrieving value from the JS side to the Rust side.
 ///
 /// If something goes wrong (for example if the network disconnects), this Future will simply pend forever.
-pub struct RetrieveFuture<'a, T: DeserializeOwned> {
-    pub(crate) id: u64,
+///
+/// `RetrieveFuture<'a, T>` borrows the value being retrieved.
+/// A `RetrieveFuture<'static, T>` owns everything it needs, so it can be spawned
+/// (it is [Send], unless the `local` feature is on; see the [shared module](crate::shared)).
+/// Get one by calling the `_owned` retrieve methods, like `retrieve_owned` or `retrieve_json_owned`,
+/// which consume the value,
+/// or by calling [into_owned](RetrieveFuture::into_owned) on a borrowing future.
+///
+/// ```rust
+/// # use std::future::Future;
+/// # use wsdom::js_types::{JsString, JsNumber};
+/// # #[cfg(not(feature = "local"))]
+/// fn spawn<F: Future + Send + 'static>(_fut: F) {}
+/// # #[cfg(feature = "local")]
+/// # fn spawn<F: Future + 'static>(_fut: F) {}
+/// fn example(name: JsString, age: &JsNumber) {
+///     spawn(name.retrieve_owned());
+///     spawn(age.retrieve_float().into_owned());
+/// }
+/// ```
+pub struct RetrieveFuture<'a, T> {
+    value: ValueRef<'a>,
     pub(crate) ret_id: u64,
-    pub(crate) link: &'a Mutex<BrowserInternal>,
+    pub(crate) projection: Option<Projection>,
+    pub(crate) decode: fn(Payload<'_>) -> Result<T, Error>,
     _phantom: PhantomData<Pin<Box<T>>>,
 }
 
+pub(crate) enum ValueRef<'a> {
+    Borrowed(&'a JsValue),
+    Owned(JsValue),
+    /// The request has been sent, so the value itself is no longer needed.
+    Sent(Browser),
+}
+
+impl<'a> ValueRef<'a> {
+    fn browser(&self) -> &Browser {
+        match self {
+            Self::Borrowed(value) => &value.browser,
+            Self::Owned(value) => &value.browser,
+            Self::Sent(browser) => browser,
+        }
+    }
+    fn id(&self) -> Option<u64> {
+        match self {
+            Self::Borrowed(value) => Some(value.id),
+            Self::Owned(value) => Some(value.id),
+            Self::Sent(_) => None,
+        }
+    }
+}
+
+impl<'a> From<&'a JsValue> for ValueRef<'a> {
+    fn from(value: &'a JsValue) -> Self {
+        Self::Borrowed(value)
+    }
+}
+
+impl From<JsValue> for ValueRef<'static> {
+    fn from(value: JsValue) -> Self {
+        Self::Owned(value)
+    }
+}
+
+/// What is done to the value before it is sent back.
+pub(crate) enum Projection {
+    /// Only send these properties (dotted paths like `target.value`), in an array.
+    Paths(&'static [&'static str]),
+    /// Send a cycle-free copy. See [Snapshot](crate::Snapshot).
+    Snapshot {
+        max_depth: u32,
+        properties: Option<Vec<String>>,
+    },
+}
+
 impl<'a, T: DeserializeOwned> RetrieveFuture<'a, T> {
-    pub(crate) fn new(id: u64, link: &'a Mutex<BrowserInternal>) -> Self {
+    pub(crate) fn new(value: impl Into<ValueRef<'a>>) -> Self {
+        Self::with_decoder(value, crate::data::from_wire::<T>)
+    }
+}
+
+impl<'a, T> RetrieveFuture<'a, T> {
+    pub(crate) fn with_decoder(
+        value: impl Into<ValueRef<'a>>,
+        decode: fn(Payload<'_>) -> Result<T, Error>,
+    ) -> Self {
         Self {
-            id,
+            value: value.into(),
             ret_id: 0,
-            link,
+            projection: None,
+            decode,
             _phantom: PhantomData,
         }
     }
+    /// Turn this into a Future that doesn't borrow the value being retrieved.
+    ///
+    /// If this Future hasn't been polled yet, the value is cloned.
+    pub fn into_owned(mut self) -> RetrieveFuture<'static, T> {
+        // taking `ret_id` makes our `Drop` impl a no-op
+        let ret_id = std::mem::take(&mut self.ret_id);
+        let browser = self.value.browser().clone();
+        let value = match std::mem::replace(&mut self.value, ValueRef::Sent(browser)) {
+            ValueRef::Borrowed(value) if ret_id == 0 => ValueRef::Owned(value.clone()),
+            ValueRef::Borrowed(value) => ValueRef::Sent(value.browser.clone()),
+            ValueRef::Owned(value) => ValueRef::Owned(value),
+            ValueRef::Sent(browser) => ValueRef::Sent(browser),
+        };
+        RetrieveFuture {
+            value,
+            ret_id,
+            projection: self.projection.take(),
+            decode: self.decode,
+            _phantom: PhantomData,
+        }
+    }
+    /// Fail with [Error::Timeout] if the value isn't retrieved within `duration`.
+    ///
+    /// See the [timeout module](crate::timeout) for how to set up the timer.
+    pub fn timeout(self, duration: std::time::Duration) -> Timeout<Self> {
+        let browser = self.browser().clone();
+        Timeout::new(&browser, duration, self)
+    }
+    pub(crate) fn browser(&self) -> &Browser {
+        self.value.browser()
+    }
+    pub(crate) fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = Some(projection);
+        self
+    }
 }
 
-impl<'a, T: DeserializeOwned> Future for RetrieveFuture<'a, T> {
+impl<'a, T> Future for RetrieveFuture<'a, T> {
     type Output = T;
     fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
-        let mut link = this.link.lock().unwrap();
+        let mut link = this.value.browser().0.lock();
         match this.ret_id {
             0 => {
-                let ret_id = link.get_new_id();
+                let Some(this_id) = this.value.id() else {
+                    // already completed once; nothing more to retrieve
+                    return Poll::Pending;
+                };
+                if !link.permits_projection(this.projection.as_ref()) || !link.allows_retrieval() {
+                    return Poll::Pending;
+                }
+                let ret_id = link.new_retrieval_id();
                 this.ret_id = ret_id;
-                let this_id = this.id;
-                writeln!(link.raw_commands_buf(), "{REP}({ret_id},{GET}({this_id}));").unwrap();
+                link.push_command(Command::Reply(
+                    ret_id,
+                    &Projected(this.projection.as_ref(), this_id),
+                ));
                 link.wake_outgoing();
                 link.retrievals.insert(
                     ret_id,
                     RetrievalState {
                         waker: cx.waker().to_owned(),
-                        last_value: String::new(),
+                        last_value: Reply::default(),
                         times: 0,
                     },
                 );
@@ -64,14 +188,13 @@ impl<'a, T: DeserializeOwned> Future for RetrieveFuture<'a, T> {
                     RetrievalState { last_value, .. } => {
                         let v = std::mem::take(last_value);
                         occ.remove();
-                        let v = v.split_once(':').unwrap().1;
-                        match serde_json::from_str(v) {
+                        match (this.decode)(v.payload()) {
                             Ok(v) => {
                                 this.ret_id = 0;
                                 Poll::Ready(v)
                             }
                             Err(e) => {
-                                link.kill(Error::DataDeserialize(e));
+                                link.kill(e);
                                 Poll::Pending
                             }
                         }
@@ -83,14 +206,14 @@ impl<'a, T: DeserializeOwned> Future for RetrieveFuture<'a, T> {
     }
 }
 
-impl<'a, T: DeserializeOwned> Drop for RetrieveFuture<'a, T> {
+impl<'a, T> Drop for RetrieveFuture<'a, T> {
     fn drop(&mut self) {
         match self.ret_id {
             0 => {
                 // NO-OP
             }
             ret_id => {
-                let mut link = self.link.lock().unwrap();
+                let mut link = self.value.browser().0.lock();
                 link.retrievals.remove(&ret_id);
             }
         }
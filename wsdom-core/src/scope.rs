@@ -0,0 +1,251 @@
+use std::fmt::Write;
+
+use crate::command::Command;
+use crate::lazy::Written;
+use crate::link::{Browser, BrowserInternal};
+
+impl Browser {
+    /// Run `f`, freeing the values dropped inside it with a single command at the end.
+    ///
+    /// Values are normally freed one by one as they are dropped
+    /// (deletes written back to back are combined, but anything else in between splits them).
+    /// Code that creates and drops many values can run in a scope instead,
+    /// so that they are freed all at once, as `_w.R(first, last)` if their ids are contiguous.
+    ///
+    /// Values that are still alive when the scope ends, like the one returned here, are not freed
+    /// until they are dropped.
+    ///
+    /// ```rust
+    /// # use wsdom_core::{Browser, JsCast, js_types::JsNumber};
+    /// fn sum_of_squares(browser: &Browser, n: u32) -> JsNumber {
+    ///     browser.scope(|browser| {
+    ///         let mut sum: JsNumber = browser.new_value(&0);
+    ///         for i in 0..n {
+    ///             let i: JsNumber = browser.new_value(&i);
+    ///             sum = browser.op_add(&sum, &browser.op_mul(&i, &i)).unchecked_into();
+    ///         }
+    ///         sum
+    ///     })
+    /// }
+    /// ```
+    pub fn scope<R>(&self, f: impl FnOnce(&Browser) -> R) -> R {
+        self.0.lock().scope_depth += 1;
+        let _end = EndScope(self);
+        f(self)
+    }
+}
+
+/// Ends a [Browser::scope], even if it panics.
+struct EndScope<'a>(&'a Browser);
+
+impl Drop for EndScope<'_> {
+    fn drop(&mut self) {
+        self.0 .0.lock().end_scope();
+    }
+}
+
+/// What a delete command in the buffer frees.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Deleted {
+    /// The slots from one id to another, inclusive. A single [Command::Delete] is a range of one.
+    Range(u64, u64),
+    /// A [Command::DeleteList].
+    List,
+}
+
+impl BrowserInternal {
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        let mut ids = std::mem::take(&mut self.scope_deleted);
+        if ids.is_empty() {
+            return;
+        }
+        ids.sort_unstable();
+        for id in &ids {
+            self.free_slot_id(*id);
+        }
+        let (lo, hi) = (ids[0], ids[ids.len() - 1]);
+        let start = self.commands_buf.len();
+        let (command, deleted) = if lo == hi {
+            (Command::Delete(lo), Deleted::Range(lo, lo))
+        } else if hi - lo + 1 == ids.len() as u64 {
+            (Command::DeleteRange(lo, hi), Deleted::Range(lo, hi))
+        } else {
+            (Command::DeleteList(&ids), Deleted::List)
+        };
+        let params = self
+            .format
+            .write(self.style, &mut self.commands_buf, command);
+        self.written.push(Written {
+            start,
+            end: self.commands_buf.len(),
+            set: None,
+            value: None,
+            inlinable: false,
+            slots: Vec::new(),
+            raw: false,
+            params,
+            defines: false,
+            deleted: Some(deleted),
+        });
+        self.wake_outgoing_lazy();
+    }
+    /// Free the slot without a command of its own, by adding it to the delete written just before, if any.
+    pub(crate) fn coalesce_delete(&mut self, id: u64) -> bool {
+        let Some(last) = self.written.last_mut() else {
+            return false;
+        };
+        let Some(deleted) = last.deleted.filter(|_| last.end == self.commands_buf.len()) else {
+            return false;
+        };
+        let buf = &mut self.commands_buf;
+        match deleted {
+            Deleted::Range(lo, hi) if id + 1 == lo || id == hi + 1 => {
+                let (lo, hi) = (lo.min(id), hi.max(id));
+                buf.truncate(last.start);
+                last.params = self
+                    .format
+                    .write(self.style, buf, Command::DeleteRange(lo, hi));
+                last.deleted = Some(Deleted::Range(lo, hi));
+            }
+            Deleted::Range(lo, hi) if lo == hi => {
+                buf.truncate(last.start);
+                last.params = self
+                    .format
+                    .write(self.style, buf, Command::DeleteList(&[lo, id]));
+                last.deleted = Some(Deleted::List);
+            }
+            // a long range is shorter than a list, so start another delete
+            Deleted::Range(..) => return false,
+            Deleted::List => {
+                let suffix = self.format.delete_list_suffix(self.style);
+                buf.truncate(last.end - suffix.len());
+                // writing into a String can't fail
+                let _ = write!(buf, ",{id}{suffix}");
+                // the list is one parameter, which ends with its `]`
+                if let Some(list) = last.params.last_mut() {
+                    list.end = buf.len() - suffix.len() + "]".len();
+                }
+            }
+        }
+        last.end = buf.len();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::{js_types::JsValue, Browser, JsPath};
+
+    fn buf(browser: &Browser) -> String {
+        browser.0.lock().commands_buf.clone()
+    }
+
+    /// Values that can't be discarded or inlined when dropped, because a later command used them.
+    fn used_values(browser: &Browser, n: usize) -> Vec<JsValue> {
+        let values: Vec<JsValue> = (0..n)
+            .map(|_| browser.call_function(JsPath::from_static("f"), [], false))
+            .collect();
+        browser.run_raw_code(format_args!("use()"));
+        values
+    }
+
+    #[test]
+    fn deletes_written_back_to_back_are_merged() {
+        let browser = Browser::new();
+        let mut values = used_values(&browser, 5).into_iter();
+        let len = buf(&browser).len();
+        let [a, b, c, d, e] = std::array::from_fn(|_| values.next().unwrap());
+        drop(b);
+        drop(c);
+        drop(a);
+        assert_eq!(&buf(&browser)[len..], "_w.R(1,3);\n");
+        // a long range is not turned into a list
+        drop(e);
+        assert_eq!(&buf(&browser)[len..], "_w.R(1,3);\n_w.d(5);\n");
+        drop(d);
+        assert_eq!(&buf(&browser)[len..], "_w.R(1,3);\n_w.R(4,5);\n");
+    }
+
+    #[test]
+    fn single_deletes_become_lists() {
+        let browser = Browser::new();
+        let mut values = used_values(&browser, 4).into_iter();
+        let len = buf(&browser).len();
+        let [a, _b, c, d] = std::array::from_fn(|_| values.next().unwrap());
+        drop(a);
+        drop(c);
+        drop(d);
+        assert_eq!(&buf(&browser)[len..], "_w.D([1,3,4]);\n");
+    }
+
+    #[test]
+    fn other_commands_split_deletes() {
+        let browser = Browser::new();
+        let mut values = used_values(&browser, 2).into_iter();
+        let len = buf(&browser).len();
+        drop(values.next());
+        browser.run_raw_code(format_args!("other()"));
+        drop(values.next());
+        assert_eq!(&buf(&browser)[len..], "_w.d(1);\n{ other() }\n_w.d(2);\n");
+    }
+
+    #[test]
+    fn scopes_delete_once() {
+        let browser = Browser::new();
+        let kept = browser.scope(|browser| {
+            let mut values = used_values(browser, 4);
+            let kept = values.remove(1);
+            drop(values);
+            kept
+        });
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,f());\n_w.s(2,f());\n_w.s(3,f());\n_w.s(4,f());\n{ use() }\n_w.D([1,3,4]);\n"
+        );
+        // freed values go into the list
+        drop(kept);
+        assert!(buf(&browser).ends_with("_w.D([1,3,4,2]);\n"));
+        let len = buf(&browser).len();
+        let contiguous = browser.scope(|browser| used_values(browser, 3).pop());
+        assert_eq!(
+            &buf(&browser)[len..],
+            "_w.s(1,f());\n_w.s(2,f());\n_w.s(3,f());\n{ use() }\n_w.R(1,2);\n"
+        );
+        // a delete right after the scope's is merged into it
+        drop(contiguous);
+        assert!(buf(&browser).ends_with("{ use() }\n_w.R(1,3);\n"));
+    }
+
+    #[test]
+    fn nested_scopes_delete_at_their_own_end() {
+        let browser = Browser::new();
+        browser.scope(|browser| {
+            browser.scope(|browser| drop(used_values(browser, 2)));
+            drop(used_values(browser, 1));
+        });
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,f());\n_w.s(2,f());\n{ use() }\n_w.R(1,2);\n_w.s(1,f());\n{ use() }\n_w.d(1);\n"
+        );
+    }
+
+    #[test]
+    fn scopes_end_when_panicking() {
+        let browser = Browser::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            browser.scope(|browser| {
+                let _values = used_values(browser, 2);
+                panic!("oops");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(browser.0.lock().scope_depth, 0);
+        assert_eq!(
+            buf(&browser),
+            "_w.s(1,f());\n_w.s(2,f());\n{ use() }\n_w.R(1,2);\n"
+        );
+    }
+}
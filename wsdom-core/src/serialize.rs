@@ -1,64 +1,464 @@
+use std::fmt::{Display, Write};
+use std::ops::Range;
+
 use serde::Serialize;
 
+use crate::command::{helper_name, is_helper_name, is_path, CodeStyle, CommandFormat};
+use crate::intern::{Interned, Strings};
 use crate::js::value::JsValue;
-use crate::protocol::GET;
+use crate::js_cast::JsCast;
+use crate::lazy::EFFECT;
+use crate::link::Browser;
+use crate::protocol::{GET, INTERN, INTERNED, RAW_EXPR};
 
 /// For values that can be serialized to JS code:
 /// - Rust values that implement `serde::Serialize`
 /// - WRMI stubs ([JsValue]s)
 ///
 /// This trait is used by [ToJs].
+///
+/// Values are written into a [JsWriter], as code or as structured ops depending on the Browser's
+/// [CommandFormat]. Implement it with the writer's methods, or by passing the writer along
+/// to other [UseInJsCode] values:
+///
+/// ```rust
+/// # use wsdom_core::{JsWriter, UseInJsCode};
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+/// impl UseInJsCode for Point {
+///     fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+///         buf.write_object([("x", &self.x), ("y", &self.y)])
+///     }
+/// }
+/// ```
+///
+/// Before 0.1.0, values were written into a [std::fmt::Formatter] instead.
+/// Such implementations keep working as [FormatJsCode], see there for how to move them over.
 pub trait UseInJsCode {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result;
+}
+
+/// The [UseInJsCode] of before 0.1.0, for values that write JS code into a [std::fmt::Formatter].
+///
+/// To keep such an implementation, implement this trait with it instead,
+/// and [UseInJsCode] with [JsWriter::write_formatted]:
+///
+/// ```rust
+/// # use wsdom_core::{FormatJsCode, JsWriter, UseInJsCode};
+/// struct Now;
+/// impl FormatJsCode for Now {
+///     fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         buf.write_str("Date.now()")
+///     }
+/// }
+/// impl UseInJsCode for Now {
+///     fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+///         buf.write_formatted(self)
+///     }
+/// }
+/// ```
+///
+/// The code is sent as [raw code](JsWriter::write_raw_code), so it needs `eval` in [CommandFormat::Ops].
+pub trait FormatJsCode {
     fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
 }
 
-impl UseInJsCode for JsValue {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let self_id = self.id;
-        write!(buf, "{GET}({self_id})").unwrap();
+/// A command being written for the client, which [UseInJsCode] values write themselves into.
+///
+/// What is written depends on the [format](JsWriter::format):
+/// JS code for [CommandFormat::Code] and [CommandFormat::Templates], and JSON ops for [CommandFormat::Ops].
+/// The methods here take care of that, so values that only use them work in every format.
+pub struct JsWriter<'a> {
+    out: &'a mut String,
+    format: CommandFormat,
+    style: CodeStyle,
+    /// [Browser::key] of the Browser the command is for.
+    key: usize,
+    /// Whether a value from another Browser was written.
+    pub(crate) wrong_browser: bool,
+    /// The slots used, in the order they are written.
+    pub(crate) slots: Vec<u64>,
+    /// Whether raw code was written.
+    pub(crate) raw: bool,
+    /// Whether some of what was written may not be evaluated, like the right side of `&&`,
+    /// so that values can't be [inlined](crate::lazy) into it.
+    pub(crate) conditional: bool,
+    /// Where the literals that [templates](crate::template) take out as parameters are,
+    /// in [CommandFormat::Templates].
+    pub(crate) params: Vec<Range<usize>>,
+    /// The session's [interned strings](crate::intern), if strings may be interned.
+    strings: Option<&'a mut Strings>,
+}
+
+impl<'a> JsWriter<'a> {
+    pub(crate) fn new(
+        out: &'a mut String,
+        format: CommandFormat,
+        style: CodeStyle,
+        key: usize,
+        strings: Option<&'a mut Strings>,
+    ) -> Self {
+        Self {
+            out,
+            format,
+            style,
+            key,
+            wrong_browser: false,
+            slots: Vec::new(),
+            raw: false,
+            conditional: false,
+            params: Vec::new(),
+            strings,
+        }
+    }
+    /// How the command is being written.
+    pub fn format(&self) -> CommandFormat {
+        self.format
+    }
+    /// Write a value serialized with serde, as a JSON literal.
+    pub fn write_json<T: Serialize + ?Sized>(&mut self, value: &T) -> std::fmt::Result {
+        let json = serde_json::to_string(value).map_err(|_| std::fmt::Error)?;
+        if json.starts_with('"') {
+            self.string_literal(&json);
+        } else {
+            self.literal(&json);
+        }
+        Ok(())
+    }
+    /// Write an array of the items.
+    pub fn write_array<I>(&mut self, items: I) -> std::fmt::Result
+    where
+        I: IntoIterator,
+        I::Item: UseInJsCode,
+    {
+        if self.ops() {
+            self.push(r#"["a""#);
+            for item in items {
+                self.push(",");
+                item.serialize_to(self)?;
+            }
+        } else {
+            self.push("[");
+            for item in items {
+                item.serialize_to(self)?;
+                self.push(",");
+            }
+        }
+        self.push("]");
+        Ok(())
+    }
+    /// Write an object with the entries, whose keys become property names.
+    pub fn write_object<K, V>(
+        &mut self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> std::fmt::Result
+    where
+        K: AsRef<str>,
+        V: UseInJsCode,
+    {
+        if self.ops() {
+            self.push(r#"["o""#);
+            for (key, value) in entries {
+                self.push(",");
+                write_json(self.out, key.as_ref())?;
+                self.push(",");
+                value.serialize_to(self)?;
+            }
+            self.push("]");
+        } else {
+            self.push("{");
+            for (key, value) in entries {
+                write_json(self.out, key.as_ref())?;
+                self.push(":");
+                value.serialize_to(self)?;
+                self.push(",");
+            }
+            self.push("}");
+        }
+        Ok(())
+    }
+    /// Write raw JS code as an expression.
+    ///
+    /// Structured ops can't carry code, but plain names like `document` are looked up on `globalThis`,
+    /// and `null`/`undefined` become values. Anything else is sent for the client to `eval`.
+    pub fn write_raw_code(&mut self, code: &str) -> std::fmt::Result {
+        if !self.ops() {
+            if code == "null" || code == "undefined" {
+                self.push(code);
+            } else if is_path(code) {
+                self.global(code);
+            } else if self.compact() {
+                self.raw = true;
+                self.slots.push(EFFECT);
+                self.helper(RAW_EXPR);
+                self.push("(");
+                self.json(code)?;
+                self.push(")");
+            } else {
+                self.raw = true;
+                self.slots.push(EFFECT);
+                self.push(code);
+            }
+        } else if code == "null" {
+            self.push(r#"["l",null]"#);
+        } else if code == "undefined" {
+            self.push(r#"["u"]"#);
+        } else if is_path(code) {
+            self.push(r#"["v","#);
+            write_json(self.out, code)?;
+            self.push("]");
+        } else {
+            self.slots.push(EFFECT);
+            self.push(r#"["E","#);
+            write_json(self.out, code)?;
+            self.push("]");
+        }
         Ok(())
     }
-}
 
-pub struct SerdeToJs<'a, T: ?Sized>(pub &'a T);
+    /// Write the code of a [FormatJsCode], as [raw code](JsWriter::write_raw_code).
+    pub fn write_formatted<T: FormatJsCode + ?Sized>(&mut self, value: &T) -> std::fmt::Result {
+        struct Formatted<'a, T: ?Sized>(&'a T);
+        impl<T: FormatJsCode + ?Sized> Display for Formatted<'_, T> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.serialize_to(f)
+            }
+        }
+        let mut code = String::new();
+        write!(code, "{}", Formatted(value))?;
+        self.write_raw_code(&code)
+    }
 
-impl<'a, T: Serialize + ?Sized> UseInJsCode for SerdeToJs<'a, T> {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        struct WriteAdapter<'a, 'b>(&'a mut std::fmt::Formatter<'b>);
-        impl<'a, 'b> std::io::Write for WriteAdapter<'a, 'b> {
-            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-                match self.0.write_str(unsafe {
-                    // WriteAdapter is only to be written to by serde_json,
-                    // which only writes valid utf8
-                    std::str::from_utf8_unchecked(buf)
-                }) {
-                    Ok(()) => Ok(buf.len()),
-                    Err(std::fmt::Error) => Err(std::io::ErrorKind::Other.into()),
-                }
+    pub(crate) fn ops(&self) -> bool {
+        self.format == CommandFormat::Ops
+    }
+    /// Whether code is being written in [CodeStyle::Compact].
+    pub(crate) fn compact(&self) -> bool {
+        !self.ops() && self.style == CodeStyle::Compact
+    }
+    pub(crate) fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+    pub(crate) fn display(&mut self, text: impl Display) {
+        // writing into a String can't fail
+        let _ = write!(self.out, "{text}");
+    }
+    /// Write a helper function of the client, like [GET]; called without `_w.` in [CodeStyle::Compact].
+    pub(crate) fn helper(&mut self, name: &'static str) {
+        self.push(helper_name(self.style, name));
+    }
+    /// Write a global path, like `Intl.NumberFormat`.
+    pub(crate) fn global(&mut self, path: &str) {
+        let root = path.split('.').next().unwrap_or(path);
+        if self.compact() && is_helper_name(root) {
+            self.push("globalThis.");
+        }
+        self.push(path);
+    }
+    /// Write a number that the client only uses as an id.
+    pub(crate) fn id(&mut self, id: u64) {
+        // writing into a String can't fail
+        let _ = self.param(|out| write!(out, "{id}"));
+    }
+    /// Write data as JSON, regardless of format, like the arguments of an op.
+    pub(crate) fn json<T: Serialize + ?Sized>(&mut self, value: &T) -> std::fmt::Result {
+        self.param(|out| write_json(out, value))
+    }
+    /// Write a literal that [templates](crate::template) take out as a parameter, which must be valid JSON.
+    fn param(&mut self, write: impl FnOnce(&mut String) -> std::fmt::Result) -> std::fmt::Result {
+        let start = self.out.len();
+        write(self.out)?;
+        if self.format == CommandFormat::Templates {
+            self.params.push(start..self.out.len());
+        }
+        Ok(())
+    }
+    /// Write the ids of a [DeleteList](crate::command::Command::DeleteList).
+    pub(crate) fn json_ids(&mut self, ids: &[u64]) -> std::fmt::Result {
+        self.json(ids)
+    }
+    /// Write the value in a slot.
+    pub(crate) fn slot(&mut self, id: u64) {
+        self.slots.push(id);
+        if self.ops() {
+            self.push(r#"["g","#);
+            self.id(id);
+            self.push("]");
+        } else {
+            self.helper(GET);
+            self.push("(");
+            self.id(id);
+            self.push(")");
+        }
+    }
+    /// Write the value in a slot of the Browser, failing if the command is for another Browser.
+    pub(crate) fn browser_slot(&mut self, browser: &Browser, id: u64) -> std::fmt::Result {
+        if self.key != browser.key() {
+            self.wrong_browser = true;
+            return Err(std::fmt::Error);
+        }
+        self.slot(id);
+        Ok(())
+    }
+    /// Write data already serialized as JSON, as it is.
+    pub(crate) fn literal(&mut self, json: &str) {
+        let json = |out: &mut String| {
+            out.push_str(json);
+            Ok(())
+        };
+        if self.ops() {
+            self.push(r#"["l","#);
+            let _ = self.param(json);
+            self.push("]");
+        } else {
+            let _ = self.param(json);
+        }
+    }
+    /// Write a JSON string literal, interning it if that's shorter.
+    pub(crate) fn string_literal(&mut self, json: &str) {
+        let literal_len = if self.ops() {
+            r#"["l",]"#.len() + json.len()
+        } else {
+            json.len()
+        };
+        match self.intern(json, literal_len) {
+            Interned::Literal => self.literal(json),
+            interned => self.interned(interned, json),
+        }
+    }
+    /// Write the name of a method called on a value, interning it if that's shorter.
+    ///
+    /// In code, this writes the property access, like `.name` or `[_w.I(token)]`.
+    pub(crate) fn method_name(&mut self, name: &str) -> std::fmt::Result {
+        let mut json = String::new();
+        write_json(&mut json, name)?;
+        // `.name` against `[_w.I(token)]`
+        let literal_len = if self.ops() {
+            json.len()
+        } else {
+            name.len() + 1 - "[]".len()
+        };
+        match (self.intern(&json, literal_len), self.ops()) {
+            (Interned::Literal, true) => self.push(&json),
+            (Interned::Literal, false) => {
+                self.push(".");
+                self.push(name);
             }
-            fn flush(&mut self) -> std::io::Result<()> {
-                Ok(())
+            (interned, true) => self.interned(interned, &json),
+            (interned, false) => {
+                self.push("[");
+                self.interned(interned, &json);
+                self.push("]");
             }
         }
-        match serde_json::to_writer(&mut WriteAdapter(buf), self.0) {
-            Ok(()) => Ok(()),
-            Err(_) => Err(std::fmt::Error),
+        Ok(())
+    }
+    /// How to write the JSON string literal, given how long writing it as it is would be.
+    fn intern(&mut self, json: &str, literal_len: usize) -> Interned {
+        let reference_len = match self.ops() {
+            true => r#"["I",]"#.len(),
+            false => helper_name(self.style, INTERNED).len() + "()".len(),
+        };
+        let Some(strings) = self.strings.as_deref_mut() else {
+            return Interned::Literal;
+        };
+        strings.intern(json, |token| {
+            reference_len + token.to_string().len() < literal_len
+        })
+    }
+    fn interned(&mut self, interned: Interned, json: &str) {
+        match (interned, self.ops()) {
+            (Interned::Literal, _) => self.literal(json),
+            (Interned::Define(token), true) => {
+                self.push(r#"["i","#);
+                self.id(token);
+                self.push(",");
+                self.push(json);
+                self.push("]");
+            }
+            (Interned::Define(token), false) => {
+                self.helper(INTERN);
+                self.push("(");
+                self.id(token);
+                self.push(",");
+                let _ = self.param(|out| {
+                    out.push_str(json);
+                    Ok(())
+                });
+                self.push(")");
+            }
+            (Interned::Reference(token), true) => {
+                self.push(r#"["I","#);
+                self.id(token);
+                self.push("]");
+            }
+            (Interned::Reference(token), false) => {
+                self.helper(INTERNED);
+                self.push("(");
+                self.id(token);
+                self.push(")");
+            }
+        }
+    }
+    /// Write a number JSON can't represent: `NaN`, `Infinity`, or `-Infinity`.
+    pub(crate) fn special_number(&mut self, name: &str) {
+        if self.ops() {
+            self.push(r#"["n",""#);
+            self.push(name);
+            self.push(r#""]"#);
+        } else {
+            self.push(name);
+        }
+    }
+    /// Write a BigInt, given its decimal digits.
+    pub(crate) fn bigint(&mut self, digits: impl Display) {
+        match self.format {
+            CommandFormat::Ops => self.display(format_args!(r#"["b","{digits}"]"#)),
+            CommandFormat::Code => self.display(format_args!("{digits}n")),
+            // `123n` can't be a parameter, but its digits as a string can
+            CommandFormat::Templates => {
+                self.push("BigInt(");
+                let _ = self.param(|out| write!(out, r#""{digits}""#));
+                self.push(")");
+            }
+        }
+    }
+}
+
+impl UseInJsCode for JsValue {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.browser_slot(&self.browser, self.id)
+    }
+}
+
+/// Write the value as JSON.
+pub(crate) fn write_json<T: Serialize + ?Sized>(buf: &mut String, value: &T) -> std::fmt::Result {
+    // serde_json only writes valid UTF-8
+    let vec = unsafe { buf.as_mut_vec() };
+    let len = vec.len();
+    match serde_json::to_writer(&mut *vec, value) {
+        Ok(()) => Ok(()),
+        Err(_) => {
+            vec.truncate(len);
+            Err(std::fmt::Error)
         }
     }
 }
 
-pub(crate) struct UseInJsCodeWriter<'a, T: UseInJsCode + ?Sized>(pub &'a T);
+pub struct SerdeToJs<'a, T: ?Sized>(pub &'a T);
 
-impl<'a, T: UseInJsCode + ?Sized> std::fmt::Display for UseInJsCodeWriter<'a, T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.serialize_to(f)
+impl<'a, T: Serialize + ?Sized> UseInJsCode for SerdeToJs<'a, T> {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_json(self.0)
     }
 }
 
 pub struct RawCodeImmediate<'a>(pub &'a str);
 impl<'a> UseInJsCode for RawCodeImmediate<'a> {
-    fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        buf.write_str(self.0)
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_raw_code(self.0)
     }
 }
 
@@ -66,6 +466,48 @@ impl<'a> UseInJsCode for RawCodeImmediate<'a> {
 ///
 /// For example, `ToJs<JsNumber>` means serializable to the same type that
 /// `JsNumber` serializes to.
-pub trait ToJs<JsType>: UseInJsCode {}
+///
+/// Standard collections are supported too: `Vec<U>` and slices become arrays,
+/// `HashMap`/`BTreeMap` with string keys become objects, tuples become arrays,
+/// and `Option<U>` becomes a nullable value.
+/// References to values are accepted wherever the value is (e.g. `&HTMLElement` is `ToJs<HTMLElement>`),
+/// so `Vec<&HTMLElement>` is `ToJs<Array<HTMLElement>>`.
+///
+/// Rust structs can be turned into JS object literals with `#[derive(ToJs)]`.
+/// Use `#[js(to = ...)]` to list the dictionary types the struct can be passed as.
+///
+/// ```rust
+/// # use wsdom::Browser;
+/// #[derive(wsdom::ToJs)]
+/// #[js(to = wsdom::dom::EventInit)]
+/// struct MyEventInit {
+///     bubbles: bool,
+///     #[js(rename = "cancelable")]
+///     can_cancel: bool,
+/// }
+/// fn example(browser: &Browser) {
+///     let init = MyEventInit { bubbles: true, can_cancel: false };
+///     // sends `new Event("hello", {"bubbles":true,"cancelable":false,})`
+///     let _event = wsdom::dom::Event::new(browser, &"hello", &Some(&init));
+/// }
+/// ```
+pub trait ToJs<JsType>: UseInJsCode {
+    /// Write the value as a `JsType`.
+    ///
+    /// This is [serialize_to](UseInJsCode::serialize_to), except for values written differently
+    /// depending on the type they're passed as, like `i64`, which is a BigInt as a
+    /// [JsBigInt](crate::js_types::JsBigInt) but a number otherwise.
+    fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        self.serialize_to(buf)
+    }
+}
 
 impl<T> ToJs<T> for T where T: UseInJsCode {}
+
+impl<T> ToJs<T> for &T where T: JsCast + UseInJsCode {}
+
+impl<T: UseInJsCode + ?Sized> UseInJsCode for &T {
+    fn serialize_to(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        T::serialize_to(self, buf)
+    }
+}
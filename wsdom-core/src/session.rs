@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::command::{CodeStyle, Command, CommandFormat};
+use crate::link::Error;
+use crate::protocol::CLIENT_ACK;
+
+/// State for resuming a session after the client reconnects.
+///
+/// Messages to the client are numbered by counting; the client acknowledges how many it has run
+/// and, on reconnecting, says how many it has run so that we can send the rest again.
+/// In the other direction, we tell the client how many of its messages we've received
+/// so that it can send the rest again too.
+#[derive(Debug)]
+pub(crate) struct Session {
+    pub(crate) token: String,
+    /// Number of messages given out so far.
+    sent: u64,
+    /// Messages the client hasn't acknowledged, with their numbers.
+    replay: VecDeque<(u64, String)>,
+    replay_bytes: usize,
+    replay_limit: usize,
+    /// Number of messages received from the client (not counting acks and hellos).
+    received: u64,
+    /// The last `received` we told the client about.
+    acked_received: u64,
+    /// Messages to send again before anything new.
+    pub(crate) resend: VecDeque<String>,
+}
+
+pub(crate) enum Control {
+    /// Not a control message; this is a reply.
+    Reply,
+    /// A control message that was handled.
+    Handled,
+}
+
+impl Session {
+    pub(crate) fn new(replay_limit: usize) -> Self {
+        Self {
+            token: new_token(),
+            sent: 0,
+            replay: VecDeque::new(),
+            replay_bytes: 0,
+            replay_limit,
+            received: 0,
+            acked_received: 0,
+            resend: VecDeque::new(),
+        }
+    }
+    /// Number a new message and keep a copy of it until the client acknowledges it.
+    pub(crate) fn outgoing(
+        &mut self,
+        commands: String,
+        format: CommandFormat,
+        style: CodeStyle,
+    ) -> String {
+        let mut msg = String::new();
+        if self.sent == 0 {
+            format.write(style, &mut msg, Command::Token(&self.token));
+        }
+        if self.acked_received != self.received {
+            format.write(style, &mut msg, Command::Ack(self.received));
+            self.acked_received = self.received;
+        }
+        let msg = format.finish(if msg.is_empty() {
+            commands
+        } else {
+            msg + &commands
+        });
+        self.sent += 1;
+        self.replay_bytes += msg.len();
+        self.replay.push_back((self.sent, msg.clone()));
+        while self.replay_bytes > self.replay_limit {
+            match self.replay.pop_front() {
+                Some((_, old)) => self.replay_bytes -= old.len(),
+                None => break,
+            }
+        }
+        msg
+    }
+    /// Handle acks, and count replies.
+    pub(crate) fn receive(&mut self, message: &str) -> Control {
+        if let Some(count) = message.strip_prefix(CLIENT_ACK) {
+            if let Ok(count) = count.parse() {
+                self.acknowledge(count);
+            }
+            Control::Handled
+        } else {
+            self.received_reply();
+            Control::Reply
+        }
+    }
+    /// Handle a hello. If the client is resuming, this queues the messages it missed
+    /// and writes an ack (so that it sends again the messages we missed) into `commands`.
+    pub(crate) fn resume(
+        &mut self,
+        token: &str,
+        count: u64,
+        commands: &mut String,
+        format: CommandFormat,
+        style: CodeStyle,
+    ) -> Result<(), Error> {
+        if token.is_empty() {
+            // a new client; nothing to resume
+            return Ok(());
+        }
+        if !same_token(token, &self.token) || count > self.sent {
+            return Err(Error::ResumeFailed);
+        }
+        self.acknowledge(count);
+        let next = self.replay.front().map_or(self.sent + 1, |(seq, _)| *seq);
+        if next != count + 1 {
+            // some of the messages the client missed are no longer kept
+            return Err(Error::ResumeFailed);
+        }
+        self.resend = self.replay.iter().map(|(_, msg)| msg.clone()).collect();
+        format.write(style, commands, Command::Ack(self.received));
+        self.acked_received = self.received;
+        Ok(())
+    }
+    /// Count a reply, for acknowledging.
+    pub(crate) fn received_reply(&mut self) {
+        self.received += 1;
+    }
+    fn acknowledge(&mut self, count: u64) {
+        while let Some((seq, _)) = self.replay.front() {
+            if *seq > count {
+                break;
+            }
+            let (_, old) = self.replay.pop_front().unwrap();
+            self.replay_bytes -= old.len();
+        }
+    }
+}
+
+/// Compare tokens in time that doesn't depend on where they differ,
+/// so that timing the comparison doesn't give away a token bit by bit.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("failed to generate a session token");
+    bytes.iter().fold(String::new(), |mut token, b| {
+        let _ = write!(token, "{b:02x}");
+        token
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{same_token, Control, Session};
+    use crate::command::{CodeStyle, CommandFormat};
+    use crate::link::Error;
+
+    fn send(session: &mut Session, commands: &str) -> String {
+        session.outgoing(commands.into(), CommandFormat::Code, CodeStyle::Readable)
+    }
+
+    fn resume(session: &mut Session, token: &str, count: u64) -> Result<String, Error> {
+        let mut commands = String::new();
+        let token = token.to_owned();
+        session
+            .resume(
+                &token,
+                count,
+                &mut commands,
+                CommandFormat::Code,
+                CodeStyle::Readable,
+            )
+            .map(|()| commands)
+    }
+
+    #[test]
+    fn first_message_carries_the_token() {
+        let mut session = Session::new(1 << 20);
+        let token = session.token.clone();
+        assert_eq!(send(&mut session, "a;"), format!("_w.t(\"{token}\");\na;"));
+        assert_eq!(send(&mut session, "b;"), "b;");
+    }
+
+    #[test]
+    fn replies_are_acknowledged_once() {
+        let mut session = Session::new(1 << 20);
+        send(&mut session, "");
+        assert!(matches!(session.receive("[1]"), Control::Reply));
+        assert!(matches!(session.receive("a:1"), Control::Handled));
+        assert_eq!(send(&mut session, "a;"), "_w.k(1);\na;");
+        assert_eq!(send(&mut session, "b;"), "b;");
+    }
+
+    #[test]
+    fn resuming_sends_what_was_not_acknowledged() {
+        let mut session = Session::new(1 << 20);
+        let token = session.token.clone();
+        send(&mut session, "a;");
+        send(&mut session, "b;");
+        send(&mut session, "c;");
+        session.receive("a:1");
+        session.receive("[1]");
+        assert_eq!(resume(&mut session, &token, 2).unwrap(), "_w.k(1);\n");
+        assert_eq!(session.resend, ["c;"]);
+    }
+
+    #[test]
+    fn resuming_needs_the_token_and_a_possible_count() {
+        let mut session = Session::new(1 << 20);
+        let token = session.token.clone();
+        send(&mut session, "a;");
+        let mut wrong = token.clone().into_bytes();
+        wrong[31] ^= 1;
+        let wrong = String::from_utf8(wrong).unwrap();
+        assert!(matches!(
+            resume(&mut session, &wrong, 0),
+            Err(Error::ResumeFailed)
+        ));
+        assert!(matches!(
+            resume(&mut session, &token, 2),
+            Err(Error::ResumeFailed)
+        ));
+        assert!(resume(&mut session, "", 0).unwrap().is_empty());
+        assert!(resume(&mut session, &token, 0).is_ok());
+    }
+
+    #[test]
+    fn replay_limit_drops_the_oldest_messages() {
+        let mut session = Session::new(4);
+        let token = session.token.clone();
+        send(&mut session, "");
+        send(&mut session, "aa;");
+        send(&mut session, "bb;");
+        assert_eq!(session.replay.len(), 1);
+        assert!(resume(&mut session, &token, 2).is_ok());
+        assert_eq!(session.resend, ["bb;"]);
+        // the second message is gone
+        assert!(matches!(
+            resume(&mut session, &token, 1),
+            Err(Error::ResumeFailed)
+        ));
+    }
+
+    #[test]
+    fn tokens_compare_whole() {
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abd", "abc"));
+        assert!(!same_token("ab", "abc"));
+        assert!(!same_token("", "abc"));
+    }
+}
@@ -0,0 +1,121 @@
+/*!
+What a [Browser](crate::Browser) and its clones share.
+
+That's an `Arc<Mutex<_>>`, so that Browsers can be used from any thread,
+and every call on a Browser or a JsValue locks the Mutex.
+
+With the `local` cargo feature, it's an `Rc<RefCell<_>>` instead, which skips the locking
+for apps that keep each Browser on one thread (a Tokio `LocalSet`, or a single-threaded executor).
+Browsers, JsValues, and everything holding them are then neither [Send] nor [Sync],
+so the compiler makes sure they stay on their thread.
+Integrations that move the Browser to other threads, like `wsdom-axum`, don't build with the feature.
+*/
+
+#[cfg(not(feature = "local"))]
+use std::sync::{Arc as Rc, Mutex, MutexGuard};
+#[cfg(feature = "local")]
+use std::{
+    cell::{RefCell, RefMut},
+    rc::Rc,
+};
+
+#[cfg(not(feature = "local"))]
+pub(crate) struct Shared<T>(Rc<Mutex<T>>);
+#[cfg(feature = "local")]
+pub(crate) struct Shared<T>(Rc<RefCell<T>>);
+
+#[cfg(not(feature = "local"))]
+pub(crate) type Guard<'a, T> = MutexGuard<'a, T>;
+#[cfg(feature = "local")]
+pub(crate) type Guard<'a, T> = RefMut<'a, T>;
+
+impl<T> Shared<T> {
+    #[cfg(not(feature = "local"))]
+    pub(crate) fn new(value: T) -> Self {
+        Self(Rc::new(Mutex::new(value)))
+    }
+    #[cfg(feature = "local")]
+    pub(crate) fn new(value: T) -> Self {
+        Self(Rc::new(RefCell::new(value)))
+    }
+    /// Panics if a panic while locked (say, in a [UseInJsCode](crate::UseInJsCode))
+    /// may have left it half-changed.
+    #[cfg(not(feature = "local"))]
+    pub(crate) fn lock(&self) -> Guard<'_, T> {
+        self.0.lock().unwrap()
+    }
+    /// Panics if it's already locked (which would be a deadlock with a Mutex).
+    #[cfg(feature = "local")]
+    pub(crate) fn lock(&self) -> Guard<'_, T> {
+        self.0.borrow_mut()
+    }
+    /// The same for all clones, and different from any other [Shared] that's alive.
+    pub(crate) fn addr(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{js_types::JsValue, Browser};
+
+    #[cfg(not(feature = "local"))]
+    #[test]
+    fn browsers_can_move_between_threads() {
+        fn send_and_sync<T: Send + Sync>() {}
+        send_and_sync::<Browser>();
+        send_and_sync::<JsValue>();
+    }
+
+    #[cfg(feature = "local")]
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn local_browsers_cant_move_between_threads() {
+        use std::marker::PhantomData;
+
+        /// Whether `T` is [Send] or [Sync], found without requiring it.
+        struct Probe<T>(PhantomData<T>);
+        trait Threadsafe {
+            fn threadsafe(&self) -> bool {
+                true
+            }
+        }
+        impl<T: Send> Threadsafe for Probe<(T, bool)> {}
+        impl<T: Sync> Threadsafe for Probe<(T, u8)> {}
+        trait NotThreadsafe {
+            fn threadsafe(&self) -> bool {
+                false
+            }
+        }
+        impl<T> NotThreadsafe for &Probe<T> {}
+
+        assert!(!(&Probe::<(Browser, bool)>(PhantomData)).threadsafe());
+        assert!(!(&Probe::<(Browser, u8)>(PhantomData)).threadsafe());
+        assert!(!(&Probe::<(JsValue, bool)>(PhantomData)).threadsafe());
+        assert!(!(&Probe::<(JsValue, u8)>(PhantomData)).threadsafe());
+        // the probe itself works
+        assert!((&Probe::<(u32, bool)>(PhantomData)).threadsafe());
+    }
+
+    #[test]
+    fn clones_share() {
+        let browser = Browser::new();
+        let clone = browser.clone();
+        let _value: JsValue = clone.new_value(&1);
+        assert_eq!(browser.0.addr(), clone.0.addr());
+        assert_ne!(browser.0.addr(), Browser::new().0.addr());
+        assert_eq!(browser.0.lock().commands_buf, "_w.s(1,1);\n");
+    }
+}
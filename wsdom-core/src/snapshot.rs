@@ -0,0 +1,186 @@
+use crate::{
+    data::JsData,
+    js::value::JsValue,
+    link::Error,
+    protocol::TAG,
+    reply::Payload,
+    retrieve::{Projection, RetrieveFuture},
+};
+
+/// Options for [JsValue::retrieve_snapshot].
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    /// Objects nested deeper than this are replaced with [Snapshot::Truncated].
+    pub max_depth: u32,
+    /// If set, only these properties are captured (at every level).
+    /// Otherwise all enumerable properties, including inherited ones, are captured.
+    pub properties: Option<Vec<String>>,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            properties: None,
+        }
+    }
+}
+
+/// A copy of a JS value that may contain cycles or non-plain objects.
+///
+/// Every object and array captured gets an id, so that objects seen again
+/// (because of a cycle or because they are shared) can be written as [Snapshot::Ref].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Snapshot {
+    /// A primitive, `Date`, or `RegExp`.
+    Value(JsData),
+    Array {
+        id: u32,
+        items: Vec<Snapshot>,
+    },
+    Object {
+        id: u32,
+        /// The constructor name, or `None` for plain objects.
+        class: Option<String>,
+        /// Properties in the order they were enumerated.
+        properties: Vec<(String, Snapshot)>,
+    },
+    Function {
+        name: String,
+    },
+    /// An object or array already captured elsewhere in the snapshot, by id.
+    Ref(u32),
+    /// An object or array beyond [SnapshotOptions::max_depth].
+    Truncated {
+        class: Option<String>,
+    },
+    /// Reading the property threw an exception; this is the exception stringified.
+    Error(String),
+}
+
+impl Snapshot {
+    fn from_wire(payload: Payload<'_>) -> Result<Self, Error> {
+        payload.parse().map(Self::from_json)
+    }
+    fn from_json(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        let Value::Object(mut obj) = value else {
+            return Self::Value(JsData::from_json(value));
+        };
+        let tag = match obj.get(TAG) {
+            Some(Value::String(tag)) => tag.to_owned(),
+            _ => String::new(),
+        };
+        let id = obj.get("i").and_then(Value::as_u64).unwrap_or_default() as u32;
+        let class = match obj.remove("c") {
+            Some(Value::String(class)) => Some(class),
+            _ => None,
+        };
+        let string = |v: Option<Value>| match v {
+            Some(Value::String(s)) => s,
+            _ => String::new(),
+        };
+        match (tag.as_str(), obj.remove("v")) {
+            ("a", Some(Value::Array(items))) => Self::Array {
+                id,
+                items: items.into_iter().map(Self::from_json).collect(),
+            },
+            ("s", Some(Value::Array(entries))) => Self::Object {
+                id,
+                class,
+                properties: entries
+                    .into_iter()
+                    .filter_map(|entry| match entry {
+                        Value::Array(mut kv) if kv.len() == 2 => {
+                            let v = kv.pop()?;
+                            match kv.pop()? {
+                                Value::String(k) => Some((k, Self::from_json(v))),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            },
+            ("@", Some(v)) => Self::Ref(v.as_u64().unwrap_or_default() as u32),
+            ("t", _) => Self::Truncated { class },
+            ("f", v) => Self::Function { name: string(v) },
+            ("x", v) => Self::Error(string(v)),
+            (_, v) => {
+                if let Some(v) = v {
+                    obj.insert("v".to_owned(), v);
+                }
+                if let Some(class) = class {
+                    obj.insert("c".to_owned(), Value::String(class));
+                }
+                Self::Value(JsData::from_json(Value::Object(obj)))
+            }
+        }
+    }
+    /// Convert to a [serde_json::Value], for printing or inspecting.
+    ///
+    /// Objects get a `"$class"` property if they aren't plain objects and an `"$id"` property;
+    /// references become `{"$ref": id}`, truncated objects become `{"$truncated": class}`,
+    /// functions become `{"$function": name}`, and exceptions become `{"$error": message}`.
+    /// Values are converted like [JsData::into_json].
+    pub fn into_json(self) -> serde_json::Value {
+        use serde_json::{Map, Value};
+        let single = |key: &str, value: Value| Value::Object(Map::from_iter([(key.into(), value)]));
+        match self {
+            Self::Value(data) => data.into_json(),
+            Self::Array { items, .. } => {
+                Value::Array(items.into_iter().map(Self::into_json).collect())
+            }
+            Self::Object {
+                id,
+                class,
+                properties,
+            } => {
+                let mut obj = Map::new();
+                if let Some(class) = class {
+                    obj.insert("$class".into(), class.into());
+                }
+                obj.insert("$id".into(), id.into());
+                for (k, v) in properties {
+                    obj.insert(k, v.into_json());
+                }
+                Value::Object(obj)
+            }
+            Self::Function { name } => single("$function", name.into()),
+            Self::Ref(id) => single("$ref", id.into()),
+            Self::Truncated { class } => single(
+                "$truncated",
+                class.unwrap_or_else(|| "Object".to_owned()).into(),
+            ),
+            Self::Error(message) => single("$error", message.into()),
+        }
+    }
+}
+
+impl JsValue {
+    /// Retrieve a structured copy of this value, even if it is a DOM object or has cycles.
+    ///
+    /// Unlike [retrieve_data](JsValue::retrieve_data), this works on any value,
+    /// so it is useful for inspecting events and elements when debugging.
+    ///
+    /// ```rust
+    /// # use wsdom::dom::Event;
+    /// use wsdom::{Snapshot, SnapshotOptions};
+    /// async fn example(event: &Event) {
+    ///     let options = SnapshotOptions {
+    ///         max_depth: 2,
+    ///         properties: Some(vec!["type".into(), "target".into(), "value".into()]),
+    ///     };
+    ///     let snapshot: Snapshot = event.retrieve_snapshot(&options).await;
+    ///     println!("{}", snapshot.into_json());
+    /// }
+    /// ```
+    pub fn retrieve_snapshot(&self, options: &SnapshotOptions) -> RetrieveFuture<'_, Snapshot> {
+        RetrieveFuture::with_decoder(self, Snapshot::from_wire).with_projection(
+            Projection::Snapshot {
+                max_depth: options.max_depth,
+                properties: options.properties.clone(),
+            },
+        )
+    }
+}
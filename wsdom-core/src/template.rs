@@ -0,0 +1,197 @@
+/*!
+[CommandFormat::Templates]: commands as cached, compiled templates.
+
+Commands are written as code, like in [CommandFormat::Code],
+remembering where the literals in each command (strings, numbers, slot ids) are.
+When a batch is sent, they are taken out, leaving the command's shape, like `_w.s(_p[1],(_w.g(_p[2]))[_p[3]]);`.
+The first time a shape is sent, it's given an id and sent for the client to compile with `new Function`;
+after that, only the id and the literals are sent, as `[id, literals...]`.
+
+Commands with raw code in them are sent as they are, to be compiled every time.
+*/
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::command::{CodeStyle, Command, CommandFormat};
+use crate::link::BrowserInternal;
+use crate::serialize::write_json;
+
+/// How many templates a session may have, so that commands of ever-changing shapes
+/// (like arrays of every length) don't fill up the client.
+const MAX_TEMPLATES: usize = 4096;
+
+/// The templates sent to the client, by shape.
+#[derive(Debug, Default)]
+pub(crate) struct Templates {
+    ids: HashMap<String, u64>,
+}
+
+impl Templates {
+    /// Forget the templates, when a new client connects.
+    pub(crate) fn clear(&mut self) {
+        self.ids.clear();
+    }
+}
+
+impl BrowserInternal {
+    /// Turn a batch of commands written as code into ops, mostly template calls.
+    pub(crate) fn templatize(&mut self, commands: &str) -> String {
+        let mut out = String::with_capacity(commands.len());
+        let mut done = 0;
+        for written in std::mem::take(&mut self.written) {
+            if written.start > done {
+                write_raw(&mut out, &commands[done..written.start]);
+            }
+            let code = &commands[written.start..written.end];
+            if written.raw {
+                write_raw(&mut out, code);
+            } else {
+                let (shape, args) = parameterize(code, written.start, &written.params);
+                self.templates.call(&mut out, shape, &args, code);
+            }
+            done = written.end;
+        }
+        if done < commands.len() {
+            write_raw(&mut out, &commands[done..]);
+        }
+        out
+    }
+}
+
+impl Templates {
+    /// Write a call to the template, defining it first if needed.
+    fn call(&mut self, out: &mut String, shape: String, args: &[&str], code: &str) {
+        let next = self.ids.len() as u64;
+        let id = match self.ids.get(&shape) {
+            Some(id) => *id,
+            None if self.ids.len() < MAX_TEMPLATES => {
+                let _ = write!(out, r#"["T",{next},"#);
+                let _ = write_json(out, &shape);
+                out.push_str("],");
+                self.ids.insert(shape, next);
+                next
+            }
+            None => return write_raw(out, code),
+        };
+        let _ = write!(out, "[{id}");
+        for arg in args {
+            out.push(',');
+            out.push_str(arg);
+        }
+        out.push_str("],");
+    }
+}
+
+fn write_raw(out: &mut String, code: &str) {
+    CommandFormat::Ops.write(CodeStyle::default(), out, Command::Raw(&code));
+}
+
+/// Split a command written at `start` into its shape and its parameters.
+///
+/// In the shape, the parameters are replaced by `_p[1]`, `_p[2]`, and so on.
+fn parameterize<'a>(
+    code: &'a str,
+    start: usize,
+    params: &[Range<usize>],
+) -> (String, Vec<&'a str>) {
+    let mut shape = String::with_capacity(code.len());
+    let mut args = Vec::with_capacity(params.len());
+    let mut copied = 0;
+    for param in params {
+        let param = param.start - start..param.end - start;
+        shape.push_str(&code[copied..param.start]);
+        args.push(&code[param.clone()]);
+        let _ = write!(shape, "_p[{}]", args.len());
+        copied = param.end;
+    }
+    shape.push_str(&code[copied..]);
+    (shape, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{FutureExt, StreamExt};
+
+    use crate::{immediates::bigint, js_path, js_types::*, Browser, CommandFormat};
+
+    fn templates_browser() -> Browser {
+        let browser = Browser::new();
+        browser.set_command_format(CommandFormat::Templates);
+        browser
+    }
+
+    fn message(browser: &mut Browser) -> String {
+        browser.next().now_or_never().flatten().unwrap()
+    }
+
+    #[test]
+    fn literals_are_parameters() {
+        let mut browser = templates_browser();
+        let _value: JsValue = browser.new_value(&(-1.5, "a\"b", vec![true], bigint(-5)));
+        assert_eq!(
+            message(&mut browser),
+            r#"[["T",0,"_w.s(_p[1],[_p[2],_p[3],[_p[4],],BigInt(_p[5]),]);\n"],[0,1,-1.5,"a\"b",true,"-5"]]"#
+        );
+    }
+
+    #[test]
+    fn object_keys_stay_in_the_shape() {
+        let mut browser = templates_browser();
+        let map = std::collections::BTreeMap::from([("__proto__", 1)]);
+        let _value: JsObject = browser.new_value(&map);
+        // a computed `["__proto__"]` key would make an own property instead of setting the prototype
+        assert_eq!(
+            message(&mut browser),
+            r#"[["T",0,"_w.s(_p[1],{\"__proto__\":_p[2],});\n"],[0,1,1]]"#
+        );
+    }
+
+    #[test]
+    fn same_shape_same_template() {
+        let mut browser = templates_browser();
+        let _a: JsNumber = browser.new_value(&1);
+        let _b: JsString = browser.new_value(&"two");
+        assert_eq!(
+            message(&mut browser),
+            r#"[["T",0,"_w.s(_p[1],_p[2]);\n"],[0,1,1],[0,2,"two"]]"#
+        );
+    }
+
+    #[test]
+    fn rewritten_commands_keep_their_parameters() {
+        let mut browser = templates_browser();
+        // discarded, then inlined
+        drop(browser.call_function(js_path!("f"), [&1 as &_], false));
+        let value = browser.call_function(js_path!("g"), [&2 as &_], false);
+        let _used = browser.call_function(js_path!("h"), [&value as &_, &3 as &_], false);
+        drop(value);
+        assert_eq!(
+            message(&mut browser),
+            r#"[["T",0,"(f(_p[1],));\n"],[0,1],["T",1,"_w.s(_p[1],h((g(_p[2],)),_p[3],));\n"],[1,2,2,3]]"#
+        );
+    }
+
+    #[test]
+    fn delete_lists_are_one_parameter() {
+        let mut browser = templates_browser();
+        let values: Vec<JsNumber> = (0..4).map(|i| browser.new_value(&i)).collect();
+        let _ = message(&mut browser);
+        let [a, _b, c, d] = <[JsNumber; 4]>::try_from(values).ok().unwrap();
+        drop(a);
+        drop(c);
+        drop(d);
+        assert_eq!(
+            message(&mut browser),
+            r#"[["T",1,"_w.D(_p[1]);\n"],[1,[1,3,4]]]"#
+        );
+    }
+
+    #[test]
+    fn raw_code_is_not_a_template() {
+        let mut browser = templates_browser();
+        let _value = browser.value_from_raw_code(format_args!("1+2"));
+        assert_eq!(message(&mut browser), r#"[["R","_w.s(1,1+2);\n"]]"#);
+    }
+}
@@ -0,0 +1,223 @@
+/*!
+Giving up on retrievals and callbacks that take too long.
+
+WSDOM doesn't depend on any particular async runtime, so timeouts use a [Timer] set on the [Browser].
+With the `tokio` cargo feature enabled, Browsers use [TokioTimer] by default inside a Tokio runtime,
+and with the `async-io` feature, [AsyncIoTimer] otherwise.
+Without a default, call [Browser::set_timer] before using timeouts, or they fail with [Error::NoTimer].
+
+```rust
+# use std::time::Duration;
+# use wsdom::dom::HTMLInputElement;
+async fn example(input: &HTMLInputElement) {
+    match input.get_value().retrieve().timeout(Duration::from_secs(5)).await {
+        Ok(value) => println!("value is {value}"),
+        Err(_) => println!("the browser took too long"),
+    }
+}
+```
+*/
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::link::{Browser, BrowserInternal, Error};
+
+/// A boxed Future that completes after some time. Returned by [Timer::sleep].
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Provides sleeping for timeouts, so that WSDOM stays executor-agnostic.
+pub trait Timer: Send + Sync + 'static {
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// [Timer] using `tokio::time::sleep`. Requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// [Timer] using `async_io::Timer`. Requires the `async-io` feature.
+#[cfg(feature = "async-io")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncIoTimer;
+
+#[cfg(feature = "async-io")]
+impl Timer for AsyncIoTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let timer = async_io::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
+
+/// The [Timer] of the `tokio` or `async-io` feature, if it works here.
+///
+/// The `tokio` feature may be enabled by another crate in the dependency graph (like `wsdom-axum`),
+/// and [TokioTimer] panics outside a Tokio runtime, so it's only used inside one.
+fn default_timer() -> Option<Arc<dyn Timer>> {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Some(Arc::new(TokioTimer));
+    }
+    #[cfg(feature = "async-io")]
+    return Some(Arc::new(AsyncIoTimer));
+    #[allow(unreachable_code)]
+    None
+}
+
+impl Browser {
+    /// Set the [Timer] used for timeouts on this Browser.
+    ///
+    /// Without one, Browsers use a default [Timer] at the time it's needed:
+    /// [TokioTimer] when the `tokio` feature is enabled and that is inside a Tokio runtime,
+    /// or else [AsyncIoTimer] when the `async-io` feature is enabled.
+    /// If there's no default either, timeouts fail with [Error::NoTimer],
+    /// and [FlushPolicy](crate::flow::FlushPolicy) delays work as described there for no Timer.
+    pub fn set_timer(&self, timer: impl Timer) {
+        self.0.lock().timer = Some(Arc::new(timer));
+    }
+}
+
+impl BrowserInternal {
+    /// The [Timer] set with [Browser::set_timer], or else the default one, if any.
+    pub(crate) fn timer(&self) -> Option<Arc<dyn Timer>> {
+        self.timer.clone().or_else(default_timer)
+    }
+}
+
+/// A Future that fails with [Error::Timeout] if the inner Future doesn't complete in time.
+///
+/// The timer starts when this is created, not when it is first polled.
+/// If the Browser has no [Timer], this fails right away with [Error::NoTimer].
+pub struct Timeout<F> {
+    inner: F,
+    sleep: Option<Sleep>,
+}
+
+impl<F: Future + Unpin> Timeout<F> {
+    pub(crate) fn new(browser: &Browser, duration: Duration, inner: F) -> Self {
+        let timer = browser.0.lock().timer();
+        Self {
+            inner,
+            sleep: timer.map(|timer| timer.sleep(duration)),
+        }
+    }
+}
+
+impl<F: Future + Unpin> Future for Timeout<F> {
+    type Output = Result<F::Output, Error>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Some(sleep) = this.sleep.as_mut() else {
+            return Poll::Ready(Err(Error::NoTimer));
+        };
+        if let Poll::Ready(v) = Pin::new(&mut this.inner).poll(cx) {
+            return Poll::Ready(Ok(v));
+        }
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Error::Timeout)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::pending, time::Duration};
+
+    use futures_util::FutureExt;
+
+    use super::{Sleep, Timer};
+    use crate::{js_types::*, Browser, Error};
+
+    /// Times out right away.
+    struct Instant;
+    impl Timer for Instant {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    /// Never times out.
+    struct Never;
+    impl Timer for Never {
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(pending())
+        }
+    }
+
+    // with `async-io`, there's always a default Timer
+    #[cfg(not(feature = "async-io"))]
+    #[test]
+    fn no_timer_is_an_error() {
+        let browser = Browser::new();
+        browser.0.lock().timer = None;
+        let value: JsNumber = browser.new_value(&1.5);
+        let retrieval = value.retrieve_float().timeout(Duration::from_secs(1));
+        assert!(matches!(
+            retrieval.now_or_never(),
+            Some(Err(Error::NoTimer))
+        ));
+    }
+
+    #[test]
+    fn timing_out() {
+        let browser = Browser::new();
+        browser.set_timer(Instant);
+        let value: JsNumber = browser.new_value(&1.5);
+        let retrieval = value.retrieve_float().timeout(Duration::from_secs(1));
+        assert!(matches!(
+            retrieval.now_or_never(),
+            Some(Err(Error::Timeout))
+        ));
+    }
+
+    #[test]
+    fn completing_in_time() {
+        let browser = Browser::new();
+        browser.set_timer(Never);
+        let value: JsNumber = browser.new_value(&1.5);
+        let mut retrieval = std::pin::pin!(value.retrieve_float().timeout(Duration::from_secs(1)));
+        assert!(retrieval.as_mut().now_or_never().is_none());
+        browser.receive_incoming_message("1:1.5".to_owned());
+        assert!(matches!(retrieval.now_or_never(), Some(Ok(1.5))));
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "async-io")))]
+    #[test]
+    fn tokio_timer_is_only_the_default_inside_a_runtime() {
+        let browser = Browser::new();
+        browser.set_flush_policy(crate::flow::FlushPolicy::Interval(Duration::from_secs(1)));
+        let value: JsNumber = browser.new_value(&1.5);
+        let retrieval = value.retrieve_float().timeout(Duration::from_secs(1));
+        assert!(matches!(
+            retrieval.now_or_never(),
+            Some(Err(Error::NoTimer))
+        ));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let retrieval = runtime.block_on(async {
+            // the default Timer is picked when the timeout is created, here inside the runtime
+            value
+                .retrieve_float()
+                .timeout(Duration::from_millis(1))
+                .await
+        });
+        assert!(matches!(retrieval, Err(Error::Timeout)));
+    }
+}
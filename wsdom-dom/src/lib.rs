@@ -4,8 +4,9 @@ mod __wsdom_load_ts_macro {
     pub use ref_cast::RefCast;
     pub use wsdom_core::{
         for_macro::{RawCodeImmediate, UpcastWorkaround},
+        js_ident, js_path,
         js_types::*,
-        Browser, JsCast, ToJs, UseInJsCode,
+        Browser, JsCast, JsWriter, ToJs, UseInJsCode,
     };
     pub use wsdom_macros_decl::*;
 }
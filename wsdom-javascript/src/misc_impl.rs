@@ -1,9 +1,47 @@
 use super::Array;
-use wsdom_core::{JsCast, ToJs};
+use wsdom_core::{for_macro::UpcastWorkaround, immediates::IterImmediate, JsCast, JsWriter, ToJs};
+
+// Items are written with `serialize_as`, so that `Vec<i64>` is an array of BigInts as an `Array<JsBigInt>`.
 
 impl<'a, T, U, const N: usize> ToJs<Array<T>> for [&'a U; N]
 where
     T: JsCast,
     U: ToJs<T>,
 {
+    fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        buf.write_array(self.iter().map(|item| UpcastWorkaround::<_, T>::new(*item)))
+    }
+}
+
+// References to values also implement `ToJs` (e.g. `&HTMLElement: ToJs<HTMLElement>`),
+// so these also cover `Vec<&T>` and `&[&T]`.
+macro_rules! impl_to_js {
+    ([$($item:ident),*] $ty:ty; iter) => {
+        impl<T: JsCast, $($item: ToJs<T>),*> ToJs<Array<T>> for $ty {
+            fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                buf.write_array(self.iter().map(UpcastWorkaround::<_, T>::new))
+            }
+        }
+    };
+    ([$($item:ident),*] $ty:ty; ($($var:ident,)*)) => {
+        impl<T: JsCast, $($item: ToJs<T>),*> ToJs<Array<T>> for $ty {
+            fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+                let ($($var,)*) = self;
+                buf.write_array([$(UpcastWorkaround::<dyn ToJs<T>, T>::new($var),)*])
+            }
+        }
+    };
+}
+
+wsdom_core::for_each_array!(impl_to_js);
+
+impl<T, I> ToJs<Array<T>> for IterImmediate<I>
+where
+    T: JsCast,
+    I: IntoIterator,
+    I::Item: ToJs<T>,
+{
+    fn serialize_as(&self, buf: &mut JsWriter<'_>) -> std::fmt::Result {
+        self.serialize_items_as::<T>(buf)
+    }
 }
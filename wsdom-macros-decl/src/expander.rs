@@ -15,7 +15,7 @@ macro_rules! expand_class_def {
         }
         impl $($impl_generics)* __wsdom_load_ts_macro::UseInJsCode for $name_without_bound
         {
-            fn serialize_to(&self, buf: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            fn serialize_to(&self, buf: &mut __wsdom_load_ts_macro::JsWriter<'_>) -> std::fmt::Result {
                 self.0.serialize_to(buf)
             }
         }
@@ -94,7 +94,7 @@ macro_rules! expand_method {
     (self @ $method_name:ident, [$($generics:tt)*], [$($arg_names:ident : $arg_types:ty,)*], $ret:ty, $js_name:literal, $last_variadic:literal) => {
         pub fn $method_name $($generics)* (&self, $($arg_names: $arg_types,)*) -> $ret {
             __wsdom_load_ts_macro::JsCast::unchecked_from_js(
-                __wsdom_load_ts_macro::JsObject::js_call_method(self.as_ref(), $js_name, [
+                __wsdom_load_ts_macro::JsObject::js_call_method(self.as_ref(), __wsdom_load_ts_macro::js_ident!($js_name), [
                     $(  __wsdom_load_ts_macro::UpcastWorkaround::new( $arg_names ).cast(), )*
                 ], $last_variadic)
             )
@@ -103,7 +103,7 @@ macro_rules! expand_method {
     (constructor @ $method_name:ident, [$($generics:tt)*], [$($arg_names:ident : $arg_types:ty,)*], $ret:ty, $interface_name:literal, $last_variadic:literal) => {
         pub fn $method_name $($generics)* (browser: &__wsdom_load_ts_macro::Browser, $($arg_names: $arg_types,)*) -> $ret {
             __wsdom_load_ts_macro::JsCast::unchecked_from_js(
-                browser.call_constructor($interface_name, [
+                browser.call_constructor(__wsdom_load_ts_macro::js_path!($interface_name), [
                     $(  __wsdom_load_ts_macro::UpcastWorkaround::new( $arg_names ).cast(), )*
                 ], $last_variadic)
             )
@@ -112,7 +112,7 @@ macro_rules! expand_method {
     (free @ $method_name:ident, [$($generics:tt)*], [$($arg_names:ident : $arg_types:ty,)*], $ret:ty, $function_name:literal, $last_variadic:literal) => {
         pub fn $method_name $($generics)* (browser: &__wsdom_load_ts_macro::Browser, $($arg_names: $arg_types,)*) -> $ret {
             __wsdom_load_ts_macro::JsCast::unchecked_from_js(
-                browser.call_function($function_name, [
+                browser.call_function(__wsdom_load_ts_macro::js_path!($function_name), [
                     $(  __wsdom_load_ts_macro::UpcastWorkaround::new( $arg_names ).cast(), )*
                 ], $last_variadic)
             )
@@ -0,0 +1,73 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, LitStr};
+
+use super::{field_js_name, is_js_ident, named_fields, parse_js_path, unknown_attribute};
+
+/// serde only implements `Deserialize` for tuples up to this size.
+const MAX_FIELDS: usize = 16;
+
+pub(crate) fn derive_from_js(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(&input, "FromJs")?;
+    if fields.is_empty() || fields.len() > MAX_FIELDS {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            format!("FromJs can only be derived for structs with 1 to {MAX_FIELDS} fields"),
+        ));
+    }
+
+    let mut paths = Vec::new();
+    for field in &fields {
+        let mut path = None;
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("path") {
+                    path = Some(parse_js_path(&meta.value()?.parse::<LitStr>()?)?);
+                    Ok(())
+                } else {
+                    Err(unknown_attribute(&meta))
+                }
+            })?;
+        }
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let name = field_js_name(field);
+                if !is_js_ident(&name) {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "field name is not a valid JS identifier; add `#[js(path = \"...\")]`",
+                    ));
+                }
+                vec![name]
+            }
+        };
+        paths.push(path.join("."));
+    }
+
+    let name = &input.ident;
+    let idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+    let types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut where_clause = where_clause
+        .cloned()
+        .unwrap_or_else(|| syn::parse_quote!(where));
+    for ty in &types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: __wsdom_load_ts_macro::DeserializeOwned));
+    }
+
+    Ok(quote! {
+        const _: () = {
+            use ::wsdom::__wsdom_load_ts_macro;
+            impl #impl_generics __wsdom_load_ts_macro::FromJs for #name #ty_generics #where_clause {
+                type Raw = (#(#types,)*);
+                const PATHS: &'static [&'static str] = &[#(#paths,)*];
+                fn from_raw((#(#idents,)*): Self::Raw) -> Self {
+                    Self { #(#idents,)* }
+                }
+            }
+        };
+    })
+}
@@ -0,0 +1,130 @@
+mod from_js;
+mod to_js;
+
+pub(crate) use from_js::derive_from_js;
+pub(crate) use to_js::derive_to_js;
+
+use syn::{spanned::Spanned, Data, DeriveInput, Field, Fields, LitStr};
+
+fn named_fields<'a>(input: &'a DeriveInput, derive_name: &str) -> syn::Result<Vec<&'a Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(syn::Error::new(
+                input.ident.span(),
+                format!("{derive_name} can only be derived for structs with named fields"),
+            )),
+        },
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            format!("{derive_name} can only be derived for structs"),
+        )),
+    }
+}
+
+/// The field name as seen from JS, without the `r#` prefix.
+fn field_js_name(field: &Field) -> String {
+    let ident = field.ident.as_ref().unwrap().to_string();
+    ident.strip_prefix("r#").map(str::to_owned).unwrap_or(ident)
+}
+
+/// Whether the string is a valid JS identifier that can be used after a `.`.
+///
+/// Only ASCII identifiers are accepted.
+fn is_js_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Split a dotted path like `target.value`, checking that every part is a JS identifier.
+fn parse_js_path(lit: &LitStr) -> syn::Result<Vec<String>> {
+    let value = lit.value();
+    value
+        .split('.')
+        .map(|part| {
+            is_js_ident(part).then(|| part.to_owned()).ok_or_else(|| {
+                syn::Error::new(
+                    lit.span(),
+                    format!("`{value}` is not a valid JS property path"),
+                )
+            })
+        })
+        .collect()
+}
+
+fn unknown_attribute(meta: &syn::meta::ParseNestedMeta) -> syn::Error {
+    let name = meta
+        .path
+        .get_ident()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    syn::Error::new(meta.path.span(), format!("unknown js attribute `{name}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::{derive_from_js, derive_to_js, is_js_ident, parse_js_path};
+
+    #[test]
+    fn js_idents() {
+        assert!(is_js_ident("value"));
+        assert!(is_js_ident("_$x1"));
+        assert!(!is_js_ident(""));
+        assert!(!is_js_ident("1x"));
+        assert!(!is_js_ident("a-b"));
+        assert!(!is_js_ident("é"));
+    }
+
+    #[test]
+    fn js_paths() {
+        assert_eq!(
+            parse_js_path(&parse_quote!("target.value")).unwrap(),
+            ["target", "value"]
+        );
+        assert!(parse_js_path(&parse_quote!("target.")).is_err());
+        assert!(parse_js_path(&parse_quote!("a[0]")).is_err());
+    }
+
+    #[test]
+    fn from_js_paths() {
+        let expanded = derive_from_js(parse_quote! {
+            struct Changed {
+                #[js(path = "target.value")]
+                value: String,
+                r#type: String,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(
+            expanded.contains(r#"& ["target.value" , "type" ,]"#),
+            "{expanded}"
+        );
+    }
+
+    #[test]
+    fn to_js_keys() {
+        let expanded = derive_to_js(parse_quote! {
+            struct Point {
+                #[js(rename = "a\"b")]
+                x: f64,
+                #[js(skip)]
+                y: f64,
+                r#type: u8,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(expanded.contains(r#"("a\"b" , & self . x"#), "{expanded}");
+        assert!(
+            expanded.contains(r#"("type" , & self . r#type"#),
+            "{expanded}"
+        );
+        assert!(!expanded.contains("self . y"), "{expanded}");
+    }
+}
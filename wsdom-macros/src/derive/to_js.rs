@@ -0,0 +1,81 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, LitStr};
+
+use super::{field_js_name, named_fields, unknown_attribute};
+
+pub(crate) fn derive_to_js(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = named_fields(&input, "ToJs")?;
+
+    let mut targets = Vec::new();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("js")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("to") {
+                targets.push(meta.value()?.parse::<syn::Type>()?);
+                Ok(())
+            } else {
+                Err(unknown_attribute(&meta))
+            }
+        })?;
+    }
+
+    let mut entries = Vec::new();
+    let mut field_types = Vec::new();
+    for field in fields {
+        let mut key = field_js_name(field);
+        let mut skip = false;
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    key = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(unknown_attribute(&meta))
+                }
+            })?;
+        }
+        if skip {
+            continue;
+        }
+        let ident = &field.ident;
+        entries.push(quote! {
+            (#key, &self.#ident as &dyn __wsdom_load_ts_macro::UseInJsCode)
+        });
+        field_types.push(&field.ty);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut where_clause = where_clause
+        .cloned()
+        .unwrap_or_else(|| syn::parse_quote!(where));
+    for ty in field_types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: __wsdom_load_ts_macro::UseInJsCode));
+    }
+
+    let mut ref_generics = input.generics.clone();
+    ref_generics.params.insert(0, syn::parse_quote!('__r));
+    let (ref_impl_generics, _, _) = ref_generics.split_for_impl();
+    targets.push(syn::parse_quote!(__wsdom_load_ts_macro::JsObject));
+    targets.push(syn::parse_quote!(__wsdom_load_ts_macro::JsValue));
+
+    Ok(quote! {
+        const _: () = {
+            use ::wsdom::__wsdom_load_ts_macro;
+            impl #impl_generics __wsdom_load_ts_macro::UseInJsCode for #name #ty_generics #where_clause {
+                fn serialize_to(&self, buf: &mut __wsdom_load_ts_macro::JsWriter<'_>) -> ::std::fmt::Result {
+                    buf.write_object([#(#entries),*])
+                }
+            }
+            #(
+                impl #impl_generics __wsdom_load_ts_macro::ToJs<#targets> for #name #ty_generics #where_clause {}
+                impl #ref_impl_generics __wsdom_load_ts_macro::ToJs<#targets> for &'__r #name #ty_generics #where_clause {}
+            )*
+        };
+    })
+}
@@ -1,3 +1,4 @@
+mod derive;
 mod load_ts;
 use syn::parse_macro_input;
 
@@ -18,3 +19,21 @@ pub fn load_custom_ts(input: proc_macro::TokenStream) -> proc_macro::TokenStream
         Err(e) => e.to_compile_error().into(),
     }
 }
+
+#[proc_macro_derive(ToJs, attributes(js))]
+pub fn derive_to_js(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match derive::derive_to_js(input) {
+        Ok(res) => res.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(FromJs, attributes(js))]
+pub fn derive_from_js(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match derive::derive_from_js(input) {
+        Ok(res) => res.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
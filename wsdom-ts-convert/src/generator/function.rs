@@ -18,7 +18,7 @@ impl<'a> Context<'a> {
         quote! {
             pub fn #function_name_ident #function_generics (browser: &__wsdom_load_ts_macro::Browser, #(#arg_names_sig: #arg_types,)*) -> #ret {
                 __wsdom_load_ts_macro::JsCast::unchecked_from_js(
-                    browser.call_function(#function, [
+                    browser.call_function(__wsdom_load_ts_macro::js_path!(#function), [
                         #(  __wsdom_load_ts_macro::UpcastWorkaround::new(#arg_names_body).cast(), )*
                     ], #last_arg_variadic)
                 )
@@ -217,6 +217,7 @@ impl<'a> Context<'a> {
             "unknown" | "any" => ("JsValue", true),
             "object" => ("JsObject", true),
             "number" => ("JsNumber", true),
+            "bigint" => ("JsBigInt", true),
             "string" => ("JsString", true),
             "boolean" => ("JsBoolean", true),
             "symbol" => ("JsSymbol", true),
@@ -34,7 +34,7 @@ impl<'a> Context<'a> {
             pub fn #name (browser: &__wsdom_load_ts_macro::Browser, #(#arg_names_sig : #arg_types,)*) -> #ret_ty {
                 __wsdom_load_ts_macro::JsCast::unchecked_from_js(
                     browser.call_constructor(
-                        #class_name,
+                        __wsdom_load_ts_macro::js_path!(#class_name),
                         [
                             #(  __wsdom_load_ts_macro::UpcastWorkaround::new( #arg_names_body ).cast(), )*
                         ],
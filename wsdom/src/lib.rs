@@ -26,20 +26,27 @@ pub mod __wsdom_load_ts_macro {
     //! Private module for our macro. Don't use this.
     pub use ref_cast::RefCast;
     pub use wsdom_core::{
-        for_macro::{RawCodeImmediate, UpcastWorkaround},
+        for_macro::{DeserializeOwned, RawCodeImmediate, UpcastWorkaround},
+        js_ident, js_path,
         js_types::*,
-        Browser, JsCast, ToJs, UseInJsCode,
+        Browser, FromJs, JsCast, JsWriter, ToJs, UseInJsCode,
     };
     pub use wsdom_javascript::Array;
     pub use wsdom_macros_decl::*;
 }
 
 pub use wsdom_core::callback;
+pub use wsdom_core::flow;
 pub use wsdom_core::immediates::*;
-pub use wsdom_core::{js_types, Browser, JsCast, ToJs};
+pub use wsdom_core::timeout;
+pub use wsdom_core::{
+    js_ident, js_path, js_types, Browser, Capability, ClientInfo, CodeStyle, CommandFormat, Error,
+    FormatJsCode, FromJs, JsCast, JsData, JsIdent, JsPath, JsWriter, MessagePackError, Policy,
+    Quota, Quotas, ReplyFormat, Snapshot, SnapshotOptions, ToJs, UseInJsCode,
+};
 pub use wsdom_dom as dom;
 pub use wsdom_javascript as js;
-pub use wsdom_macros::load_custom_ts;
+pub use wsdom_macros::{load_custom_ts, FromJs, ToJs};
 
 #[allow(non_snake_case)]
 #[cfg(test)]
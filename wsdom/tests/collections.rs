@@ -0,0 +1,107 @@
+use std::collections::{BTreeMap, HashMap};
+
+use futures_util::{FutureExt, StreamExt};
+use wsdom::{js::Array, js_types::*, Browser};
+
+fn message(browser: &mut Browser) -> String {
+    browser.next().now_or_never().flatten().unwrap()
+}
+
+#[test]
+fn vecs_and_slices_are_arrays() {
+    let mut browser = Browser::new();
+    let _numbers: Array<JsNumber> = browser.new_value(&vec![1.5, -2.0]);
+    let _strings: Array<JsString> = browser.new_value(&&["a", "b\"c"][..]);
+    let _empty: JsValue = browser.new_value(&Vec::<u8>::new());
+    let _nested: JsValue = browser.new_value(&vec![vec![1u8], vec![]]);
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,[1.5,-2.0,]);\n_w.s(2,[\"a\",\"b\\\"c\",]);\n_w.s(3,[]);\n_w.s(4,[[1,],[],]);\n"
+    );
+}
+
+#[test]
+fn vecs_of_references_to_values_are_arrays_of_those_values() {
+    let mut browser = Browser::new();
+    let a: JsString = browser.new_value(&"a");
+    let b: JsString = browser.new_value(&"b");
+    let _both: Array<JsString> = browser.new_value(&vec![&a, &b]);
+    let _again: Array<JsString> = browser.new_value(&&vec![&b]);
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,\"a\");\n_w.s(2,\"b\");\n_w.s(3,[_w.g(1),_w.g(2),]);\n_w.s(4,[_w.g(2),]);\n"
+    );
+}
+
+#[test]
+fn wide_integers_are_bigints_in_arrays_of_bigints() {
+    let mut browser = Browser::new();
+    let _bigs: Array<JsBigInt> = browser.new_value(&vec![1u64, 2]);
+    let _bigs: Array<JsBigInt> = browser.new_value(&(&3i64, 4u128));
+    let _bigs: Array<JsBigInt> = browser.new_value(&wsdom::array_from_iter([5i64]));
+    let _numbers: Array<JsNumber> = browser.new_value(&vec![1u64, 2]);
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,[1n,2n,]);\n_w.s(2,[3n,4n,]);\n_w.s(3,[5n,]);\n_w.s(4,[1,2,]);\n"
+    );
+}
+
+#[test]
+fn maps_are_objects() {
+    let mut browser = Browser::new();
+    let ordered = BTreeMap::from([("b", 2), ("a \"quoted\" key", 1)]);
+    let _ordered: JsObject = browser.new_value(&ordered);
+    let hashed = HashMap::from([("only".to_owned(), vec![true])]);
+    let _hashed: JsValue = browser.new_value(&&hashed);
+    let _empty: JsObject = browser.new_value(&HashMap::<String, u8>::new());
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,{\"a \\\"quoted\\\" key\":1,\"b\":2,});\n_w.s(2,{\"only\":[true,],});\n_w.s(3,{});\n"
+    );
+}
+
+#[test]
+fn tuples_are_arrays_of_mixed_items() {
+    let mut browser = Browser::new();
+    let el: JsObject = browser.new_value(&BTreeMap::<&str, u8>::new());
+    let _mixed: JsValue = browser.new_value(&(1u8, "a", &el, (true,)));
+    let _same: Array<JsNumber> = browser.new_value(&(1u8, 2.5f64));
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,{});\n_w.s(2,[1,\"a\",_w.g(1),[true,],]);\n_w.s(3,[1,2.5,]);\n"
+    );
+}
+
+#[test]
+fn options_are_nullable() {
+    let mut browser = Browser::new();
+    let _some: JsNullable<JsNumber> = browser.new_value(&Some(3u8));
+    let _none: JsNullable<JsNumber> = browser.new_value(&None::<u8>);
+    let _items: JsValue = browser.new_value(&vec![Some("x"), None]);
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,3);\n_w.s(2,null);\n_w.s(3,[\"x\",null,]);\n"
+    );
+}
+
+#[test]
+fn iterators_are_arrays_without_cloning() {
+    struct NotClone(std::vec::IntoIter<u8>);
+    impl Iterator for NotClone {
+        type Item = u8;
+        fn next(&mut self) -> Option<u8> {
+            self.0.next()
+        }
+    }
+    let mut browser = Browser::new();
+    let items = wsdom::array_from_iter(NotClone(vec![1, 2].into_iter()));
+    let _arr: Array<JsNumber> = browser.new_value(&items);
+    assert_eq!(message(&mut browser), "_w.s(1,[1,2,]);\n");
+    // it's used up
+    let _again: Array<JsNumber> = browser.new_value(&items);
+    assert!(matches!(
+        browser.take_error(),
+        Some(wsdom::Error::CommandSerialize(_))
+    ));
+    assert_eq!(browser.next().now_or_never(), Some(None));
+}
@@ -0,0 +1,105 @@
+use futures_util::{FutureExt, StreamExt};
+use wsdom::{js_types::*, Browser, Error, FromJs, ToJs};
+
+fn message(browser: &mut Browser) -> String {
+    browser.next().now_or_never().flatten().unwrap()
+}
+
+#[test]
+fn errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
+
+#[derive(ToJs)]
+struct Point {
+    x: f64,
+    #[js(rename = "a \"quoted\" key")]
+    y: f64,
+    r#type: &'static str,
+    #[js(skip)]
+    _cache: Vec<u8>,
+}
+
+#[test]
+fn to_js_writes_an_object() {
+    let mut browser = Browser::new();
+    let point = Point {
+        x: 1.0,
+        y: -2.5,
+        r#type: "dot",
+        _cache: Vec::new(),
+    };
+    let _point: JsObject = browser.new_value(&point);
+    assert_eq!(
+        message(&mut browser),
+        r#"_w.s(1,{"x":1.0,"a \"quoted\" key":-2.5,"type":"dot",});
+"#
+    );
+}
+
+#[derive(FromJs, Debug, PartialEq)]
+struct Changed {
+    #[js(path = "target.value")]
+    value: String,
+    label: Option<String>,
+}
+
+#[test]
+fn from_js_reads_paths() {
+    let mut browser = Browser::new();
+    let event: JsValue = browser.new_value(&1);
+    let mut changed = std::pin::pin!(event.retrieve_as::<Changed>());
+    assert!(changed.as_mut().now_or_never().is_none());
+    // missing properties along a path are `undefined`, not a JS error
+    assert_eq!(
+        message(&mut browser),
+        "_w.s(1,1);\n_w.r(1,(o=>[o?.target?.value,o?.label,])(_w.g(1)));\n"
+    );
+    browser.receive_incoming_message(r#"1:["a",null]"#.to_owned());
+    assert_eq!(
+        changed.now_or_never().unwrap().unwrap(),
+        Changed {
+            value: "a".to_owned(),
+            label: None
+        }
+    );
+}
+
+#[test]
+fn from_js_fails_on_missing_properties_without_killing_the_browser() {
+    let browser = Browser::new();
+    let event: JsValue = browser.new_value(&1);
+    let mut changed = std::pin::pin!(event.retrieve_as::<Changed>());
+    assert!(changed.as_mut().now_or_never().is_none());
+    browser.receive_incoming_message(r#"1:[null,"b"]"#.to_owned());
+    assert!(matches!(
+        changed.now_or_never(),
+        Some(Err(Error::DataDeserialize(_)))
+    ));
+    assert!(browser.take_error().is_none());
+}
+
+/// Written by hand, so its paths aren't checked like the derive's.
+struct Odd;
+
+impl FromJs for Odd {
+    type Raw = ();
+    const PATHS: &'static [&'static str] = &["a,eval(s)", "items.0", "$el.class"];
+    fn from_raw(_raw: ()) -> Self {
+        Odd
+    }
+}
+
+#[test]
+fn from_js_quotes_names_that_arent_identifiers() {
+    let mut browser = Browser::new();
+    let value: JsValue = browser.new_value(&1);
+    let mut odd = std::pin::pin!(value.retrieve_as::<Odd>());
+    assert!(odd.as_mut().now_or_never().is_none());
+    assert_eq!(
+        message(&mut browser),
+        r#"_w.s(1,1);
+_w.r(1,(o=>[o?.["a,eval(s)"],o?.items?.["0"],o?.$el?.class,])(_w.g(1)));
+"#
+    );
+}
@@ -0,0 +1,7 @@
+#[derive(wsdom::FromJs)]
+struct Changed {
+    #[js(path = "target..value")]
+    value: String,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(wsdom::FromJs)]
+enum Direction {
+    Up,
+    Down,
+}
+
+fn main() {}
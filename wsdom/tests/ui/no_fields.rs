@@ -0,0 +1,4 @@
+#[derive(wsdom::FromJs)]
+struct Empty {}
+
+fn main() {}
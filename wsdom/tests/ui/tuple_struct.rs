@@ -0,0 +1,4 @@
+#[derive(wsdom::ToJs)]
+struct Pair(f64, f64);
+
+fn main() {}
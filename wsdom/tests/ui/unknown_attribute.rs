@@ -0,0 +1,7 @@
+#[derive(wsdom::ToJs)]
+struct Point {
+    #[js(rnme = "X")]
+    x: f64,
+}
+
+fn main() {}